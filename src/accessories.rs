@@ -3,7 +3,17 @@
 //! Accessories is a collection related to accessories, and how their enhancement works.
 
 
-use crate::bdo_market_requests::{CategoryGivenInfo, HasId, ItemBuySellInfo, ItemID};
+use crate::bdo_market_requests::market_client::{new_market_client, MarketClient};
+use crate::bdo_market_requests::{CategoryGivenInfo, HasId, ItemBuySellInfo, SpecificItemInfo};
+use crate::catalogue::Catalogue;
+use crate::general_calcs::enhancement_calcs::{calc_total_chance, simulate_enhancement_with};
+use crate::general_calcs::market_calcs::{
+    calc_profit, calc_profit_taxed, estimate_sell_price, get_cap_state, CapState, PurchaseEstimate, SellEstimate,
+};
+use crate::units::{Probability, Rounding, Silver};
+
+use rand::Rng;
+use std::collections::HashMap;
 /// Contains data about how an accessory will be enhanced.
 /// 
 pub struct AccEnhancementDetails<T: HasId> {
@@ -35,60 +45,457 @@ impl<T: HasId> AccEnhancementDetails<T> {
     pub fn get_level(&self) -> u8 {
         self.level
     }
-
-    /// Gets the first stack
-    /// 
-    /// # Panics
-    /// 
-    /// If there are no stacks.
-    /// 
-    fn get_first_stack(&self) -> u16 {
-        match &self.stacks {
-            None => panic!("There are no stacks when at least 1 is required."),
-            Some(v) => {
-                if let Some(_) = v.get(0) {
-                    return  v[0];
-                } else {
-                    panic!("There are no stacks when at least 1 is required.")
-                }
-            }
-        }
-    }
 }
 
 /// Contains data of what makes an enhancement profitable.
 ///
+/// The taxed value is rounded down, like the silver the game pays out, see [`units`](crate::units).
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::accessories::AccProfitDetails;
+/// use bdo_enhancement_profit_calculator::units::Silver;
+///
+/// let details = AccProfitDetails::new(Silver::new(1000), Silver::new(1999), 0.8515);
+///
+/// assert_eq!(Silver::new(999), details.get_profit());
+/// assert_eq!(Silver::new(702), details.get_profit_taxed());
+/// ```
+///
 pub struct AccProfitDetails {
-    make_cost: u64,
-    actual_value: u64,
-    profit: i64,
-    profit_taxed: i64
+    make_cost: Silver,
+    actual_value: Silver,
+    profit: Silver,
+    profit_taxed: Silver,
+    priced: bool
 } 
 impl AccProfitDetails {
-    pub fn new(make_cost: u64, actual_value: u64, tax_rate: f64) -> Self {
-        let profit = actual_value as i64 - make_cost as i64;
-        let actual_value_taxed = actual_value as f64 * tax_rate;
-        let profit_taxed = actual_value_taxed.ceil() as i64 - make_cost as i64;
+    pub fn new(make_cost: Silver, actual_value: Silver, tax_rate: f64) -> Self {
+        let profit = calc_profit(make_cost, actual_value);
+        let profit_taxed = calc_profit_taxed(make_cost, actual_value, tax_rate);
+
+        AccProfitDetails { make_cost, actual_value, profit, profit_taxed, priced: true }
+    }
 
-        AccProfitDetails { make_cost, actual_value, profit, profit_taxed }
+    /// Details for an item that couldn't be priced, which never pass a profit filter.
+    ///
+    /// Every amount is zero, check [`is_priced`](AccProfitDetails::is_priced) before using them.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bdo_enhancement_profit_calculator::accessories::AccProfitDetails;
+    /// use bdo_enhancement_profit_calculator::units::Silver;
+    ///
+    /// assert!(!AccProfitDetails::unpriced().is_priced());
+    /// assert!(AccProfitDetails::new(Silver::new(10), Silver::ZERO, 0.85).is_priced());
+    /// ```
+    ///
+    pub fn unpriced() -> Self {
+        AccProfitDetails { make_cost: Silver::ZERO, actual_value: Silver::ZERO, profit: Silver::ZERO, profit_taxed: Silver::ZERO, priced: false }
     }
 
-    pub fn get_make_cost(&self) -> u64 {
+    /// Whether the item's market could be read, see [`unpriced`](AccProfitDetails::unpriced).
+    ///
+    pub fn is_priced(&self) -> bool {
+        self.priced
+    }
+
+    pub fn get_make_cost(&self) -> Silver {
         self.make_cost
     }
 
-    pub fn get_actual_value(&self) -> u64 {
+    pub fn get_actual_value(&self) -> Silver {
         self.actual_value
     }
 
-    pub fn get_profit(&self) -> i64 {
+    pub fn get_profit(&self) -> Silver {
         self.profit
     }
 
-    pub fn get_profit_taxed(&self) -> i64 {
+    pub fn get_profit_taxed(&self) -> Silver {
         self.profit_taxed
     }
 }
+/// A single tap of a route, see [`AccRouteBreakdown`].
+///
+pub struct TapStep {
+    from_level: u8,
+    to_level: u8,
+    stack: u16,
+    chance: Probability,
+    expected_bases: f64,
+    cumulative_cost: Silver,
+    market_value: Option<Silver>,
+}
+impl TapStep {
+    pub fn get_from_level(&self) -> u8 {
+        self.from_level
+    }
+
+    pub fn get_to_level(&self) -> u8 {
+        self.to_level
+    }
+
+    pub fn get_stack(&self) -> u16 {
+        self.stack
+    }
+
+    pub fn get_chance(&self) -> Probability {
+        self.chance
+    }
+
+    /// Taps needed on average to succeed once.
+    ///
+    pub fn get_expected_attempts(&self) -> f64 {
+        self.chance.expected_attempts()
+    }
+
+    /// Base accessories used as material by the whole route up to this step, not counting the accessory being enhanced.
+    ///
+    pub fn get_expected_bases(&self) -> f64 {
+        self.expected_bases
+    }
+
+    /// Expected cost of making one accessory at `to_level`, from the start of the route.
+    ///
+    pub fn get_cumulative_cost(&self) -> Silver {
+        self.cumulative_cost
+    }
+
+    /// Market price of `to_level`, `None` if it wasn't known.
+    ///
+    pub fn get_market_value(&self) -> Option<Silver> {
+        self.market_value
+    }
+}
+
+/// Contains every tap of a route, and the profit of the route as a whole.
+///
+pub struct AccRouteBreakdown {
+    steps: Vec<TapStep>,
+    start_cost: Silver,
+    base_cost: Silver,
+    base_purchase: Option<PurchaseEstimate>,
+    unused_stacks: Vec<u16>,
+    sell_estimate: SellEstimate,
+    profit_details: AccProfitDetails,
+}
+impl AccRouteBreakdown {
+    /// Gets what the accessory at the starting level was bought for.
+    ///
+    pub fn get_start_cost(&self) -> Silver {
+        self.start_cost
+    }
+
+    /// Gets the average price paid for each base accessory the route uses, rounded up.
+    ///
+    pub fn get_base_cost(&self) -> Silver {
+        self.base_cost
+    }
+
+    /// Gets how the route's bases are bought, when too few are listed and they're priced off their ladder.
+    ///
+    pub fn get_base_purchase(&self) -> Option<&PurchaseEstimate> {
+        self.base_purchase.as_ref()
+    }
+
+    /// Gets the taps in order, first tap first.
+    ///
+    pub fn get_steps(&self) -> &[TapStep] {
+        &self.steps
+    }
+
+    /// Gets the stacks given past the route's last tap, which it doesn't use.
+    ///
+    pub fn get_unused_stacks(&self) -> &[u16] {
+        &self.unused_stacks
+    }
+
+    /// Gets how the finished accessory is expected to sell, see [`estimate_sell_price`].
+    ///
+    pub fn get_sell_estimate(&self) -> &SellEstimate {
+        &self.sell_estimate
+    }
+
+    pub fn get_profit_details(&self) -> &AccProfitDetails {
+        &self.profit_details
+    }
+
+    pub fn into_profit_details(self) -> AccProfitDetails {
+        self.profit_details
+    }
+}
+
+/// Contains the risk of an enhancement, alongside its profit.
+///
+pub struct AccRiskMetrics {
+    profit_details: AccProfitDetails,
+    cost_variance: f64,
+    profit_chance: Probability,
+    kelly_fraction: f64,
+}
+impl AccRiskMetrics {
+    pub fn get_profit_details(&self) -> &AccProfitDetails {
+        &self.profit_details
+    }
+
+    /// Taxed profit as a fraction of the make cost.
+    ///
+    pub fn get_roi(&self) -> f64 {
+        if self.profit_details.make_cost == Silver::ZERO {
+            return 0.0;
+        }
+        self.profit_details.profit_taxed.to_f64() / self.profit_details.make_cost.to_f64()
+    }
+
+    pub fn get_cost_variance(&self) -> f64 {
+        self.cost_variance
+    }
+
+    pub fn get_cost_std_dev(&self) -> f64 {
+        self.cost_variance.sqrt()
+    }
+
+    /// Chance that the make cost ends up below the taxed value.
+    ///
+    pub fn get_profit_chance(&self) -> Probability {
+        self.profit_chance
+    }
+
+    /// Suggested fraction of a bankroll to commit, between 0 and 1.
+    ///
+    pub fn get_kelly_fraction(&self) -> f64 {
+        self.kelly_fraction
+    }
+}
+
+/// Contains the market for every level of an accessory.
+///
+/// Prices and stock come from a single request for every level. Bid ladders are only requested
+/// when a calculation can't do without them, and are kept for later calculations.
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::accessories::{AccEnhancementDetails, AccMarketData};
+/// use bdo_enhancement_profit_calculator::bdo_market_requests::market_client::PcMarket;
+/// use bdo_enhancement_profit_calculator::bdo_market_requests::{ItemID, SpecificItemInfo};
+/// use bdo_enhancement_profit_calculator::units::Silver;
+///
+/// // Plenty of bases listed, and a TET that sells freely between its caps.
+/// let levels = SpecificItemInfo::build_vec(String::from(
+///     "11607-0-0-1000-500-900-800-1200-1000-0|11607-1-1-4000-5-90-3000-5000-4000-0|11607-4-4-900000-1-9-500000-1000000-900000-0",
+/// ));
+/// let client = PcMarket::new("eu");
+/// let mut market = AccMarketData::new(&client, 11607, levels);
+///
+/// let details = AccEnhancementDetails::new(ItemID::new(11607), 0, Some(vec![20, 40, 44, 110]));
+/// let profit_details = market.get_tap_profit(&details, 4, 0.85);
+///
+/// assert_eq!(Silver::new(900000), profit_details.get_actual_value());
+/// assert_eq!(0, market.get_ladder_requests());
+/// ```
+///
+pub struct AccMarketData<'a> {
+    client: &'a dyn MarketClient,
+    item_id: u32,
+    levels: Vec<SpecificItemInfo>,
+    /// Ladders requested so far by level, `None` if the request failed.
+    ladders: HashMap<u8, Option<ItemBuySellInfo>>,
+}
+
+impl<'a> AccMarketData<'a> {
+    pub fn new(client: &'a dyn MarketClient, item_id: u32, levels: Vec<SpecificItemInfo>) -> Self {
+        AccMarketData {
+            client,
+            item_id,
+            levels,
+            ladders: HashMap::new(),
+        }
+    }
+
+    /// Gets every level of an item in a single request.
+    ///
+    pub fn from_post(client: &'a dyn MarketClient, item_id: u32) -> Result<Self, String> {
+        Ok(AccMarketData::new(client, item_id, client.get_item_levels(item_id)?))
+    }
+
+    pub fn get_levels(&self) -> &[SpecificItemInfo] {
+        &self.levels
+    }
+
+    pub fn get_level(&self, enhancement_level: u8) -> Option<&SpecificItemInfo> {
+        SpecificItemInfo::find_level(&self.levels, enhancement_level)
+    }
+
+    /// Number of bid ladders that were requested, including failed requests.
+    ///
+    pub fn get_ladder_requests(&self) -> usize {
+        self.ladders.len()
+    }
+
+    /// Adds a level's bid ladder that was already fetched, so it isn't requested again.
+    ///
+    pub fn add_ladder(&mut self, enhancement_level: u8, info: ItemBuySellInfo) {
+        self.ladders.insert(enhancement_level, Some(info));
+    }
+
+    /// Gets the bid ladder of a level, requesting it the first time.
+    ///
+    pub fn get_ladder(&mut self, enhancement_level: u8) -> Option<&ItemBuySellInfo> {
+        let (client, item_id) = (self.client, self.item_id);
+        self.ladders
+            .entry(enhancement_level)
+            .or_insert_with(|| client.get_bids(item_id, enhancement_level).ok())
+            .as_ref()
+    }
+
+    /// Calculates profit to tap from the item's level to `end_level`, like [`get_tap_profit_mult`].
+    ///
+    /// Base accessories are priced at the market price while enough are listed to cover the route, and off
+    /// their ladder otherwise. The finished accessory sells at the market price, or at its price cap if it's
+    /// at one, in which case its ladder decides the chance of a sale.
+    ///
+    /// # Panics
+    ///
+    /// The same as [`get_tap_profit_mult`].
+    ///
+    pub fn get_tap_profit<T: HasId>(&mut self, item_details: &AccEnhancementDetails<T>, end_level: u8, tax_rate: f64) -> AccProfitDetails {
+        match self.get_tap_breakdown(item_details, end_level, tax_rate) {
+            Ok(breakdown) => breakdown.into_profit_details(),
+            Err(_) => AccProfitDetails::unpriced(),
+        }
+    }
+
+    /// Calculates profit like [`get_tap_profit`](AccMarketData::get_tap_profit), along with every tap of the route.
+    ///
+    /// Each step's market value is the market price of the level it makes.
+    ///
+    /// # Errors
+    ///
+    /// If base accessories, the start level or the end level aren't on the market, or the end level's ladder is needed and couldn't be fetched.
+    ///
+    /// # Panics
+    ///
+    /// The same as [`get_tap_profit_mult`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bdo_enhancement_profit_calculator::accessories::{AccEnhancementDetails, AccMarketData};
+    /// use bdo_enhancement_profit_calculator::bdo_market_requests::market_client::PcMarket;
+    /// use bdo_enhancement_profit_calculator::bdo_market_requests::{ItemID, SpecificItemInfo};
+    /// use bdo_enhancement_profit_calculator::units::Silver;
+    ///
+    /// let levels = SpecificItemInfo::build_vec(String::from(
+    ///     "11607-0-0-1000-50-900-800-1200-1000-0|11607-1-1-4000-5-90-3000-5000-4000-0|11607-2-2-12000-5-90-9000-15000-12000-0",
+    /// ));
+    /// let client = PcMarket::new("eu");
+    /// let mut market = AccMarketData::new(&client, 11607, levels);
+    ///
+    /// // The third stack is left unused, as the route only has 2 taps.
+    /// let details = AccEnhancementDetails::new(ItemID::new(11607), 0, Some(vec![20, 40, 200]));
+    /// let breakdown = market.get_tap_breakdown(&details, 2, 0.85).unwrap();
+    /// let steps = breakdown.get_steps();
+    ///
+    /// assert_eq!(2, steps.len());
+    /// assert_eq!(&[200], breakdown.get_unused_stacks());
+    /// assert_eq!((1, 2, 40), (steps[1].get_from_level(), steps[1].get_to_level(), steps[1].get_stack()));
+    /// assert_eq!("50%", steps[1].get_chance().to_string());
+    /// // 1000 + 1000 per attempt at a 71% chance, then that plus 1000 per attempt at a 50% chance.
+    /// assert_eq!(Silver::new(2817), steps[0].get_cumulative_cost());
+    /// assert_eq!(Silver::new(7634), steps[1].get_cumulative_cost());
+    /// assert_eq!(Some(Silver::new(12000)), steps[1].get_market_value());
+    /// assert_eq!(steps[1].get_cumulative_cost(), breakdown.get_profit_details().get_make_cost());
+    /// ```
+    ///
+    /// When fewer bases are listed than the route uses, they're priced by walking the base ladder.
+    ///
+    /// ```
+    /// use bdo_enhancement_profit_calculator::accessories::{AccEnhancementDetails, AccMarketData};
+    /// use bdo_enhancement_profit_calculator::bdo_market_requests::market_client::PcMarket;
+    /// use bdo_enhancement_profit_calculator::bdo_market_requests::{BiddingInfo, ItemBuySellInfo, ItemID, SpecificItemInfo};
+    /// use bdo_enhancement_profit_calculator::units::Silver;
+    ///
+    /// let levels = SpecificItemInfo::build_vec(String::from(
+    ///     "11607-0-0-1000-2-900-800-1500-1000-0|11607-1-1-4000-5-90-3000-5000-4000-0|11607-2-2-12000-5-90-9000-15000-12000-0",
+    /// ));
+    /// let client = PcMarket::new("eu");
+    /// let mut market = AccMarketData::new(&client, 11607, levels);
+    /// // 2 bases listed at 1000, and 4 buyers waiting at 1500.
+    /// let bids = vec![BiddingInfo::new(1000, 2, 0), BiddingInfo::new(1500, 0, 4)];
+    /// market.add_ladder(0, ItemBuySellInfo::new(bids, 1000, 0, 0, 0, 0, 20));
+    ///
+    /// // The route uses about 4.82 bases, so 5 are bought: 2 listed, and 3 pre-ordered at 1500.
+    /// let details = AccEnhancementDetails::new(ItemID::new(11607), 0, Some(vec![20, 40]));
+    /// let breakdown = market.get_tap_breakdown(&details, 2, 0.85).unwrap();
+    ///
+    /// // The same route as above, with bases at 1300 instead of 1000.
+    /// assert_eq!(Silver::new(1300), breakdown.get_base_cost());
+    /// // Behind the 4 buyers already waiting, so 7 bases have to trade before the last is filled.
+    /// assert_eq!(Some(3.5), breakdown.get_base_purchase().unwrap().get_fill_days(2.0));
+    /// assert_eq!(Silver::new(3240), breakdown.get_steps()[0].get_cumulative_cost());
+    /// ```
+    ///
+    pub fn get_tap_breakdown<T: HasId>(&mut self, item_details: &AccEnhancementDetails<T>, end_level: u8, tax_rate: f64) -> Result<AccRouteBreakdown, String> {
+        let current_level = item_details.get_level();
+        let unused_stacks = check_tap_route(item_details, end_level)[(end_level - current_level) as usize..].to_vec();
+        let item_id = self.item_id;
+
+        let start_cost = match self.get_level(current_level) {
+            Some(v) => Silver::from(v.get_lowest_listed()),
+            None => return Err(format!("Level {} of item {} isn't on the market.", current_level, item_id)),
+        };
+        let (base_price, base_stock) = match self.get_level(0) {
+            Some(v) => (v.get_base_price(), v.get_stock()),
+            None => return Err(format!("Base accessories of item {} aren't on the market.", item_id)),
+        };
+        let (end_price, cap_min, cap_max) = match self.get_level(end_level) {
+            Some(v) => (v.get_base_price(), v.get_price_cap_min(), v.get_price_cap_max()),
+            None => return Err(format!("Level {} of item {} isn't on the market.", end_level, item_id)),
+        };
+        let market_values: Vec<Option<Silver>> = (0..=end_level)
+            .map(|level| self.get_level(level).map(|l| Silver::from(l.get_base_price())))
+            .collect();
+
+        // Every base the route is expected to use is bought up front, so the whole batch is priced together.
+        let total_bases = calc_route_steps(item_details, end_level, start_cost, 0.0, |_| None)
+            .last()
+            .map(|s| s.expected_bases.ceil() as u64)
+            .unwrap_or(0);
+        let base_purchase = match base_stock >= total_bases {
+            true => None,
+            false => self.get_ladder(0).map(|v| v.get_purchase_estimate(total_bases)),
+        };
+        let base_cost = base_purchase.as_ref().map(|p| p.get_average_price()).unwrap_or(base_price as f64);
+        let steps = calc_route_steps(item_details, end_level, start_cost, base_cost, |level| market_values[level as usize]);
+
+        let bids = if get_cap_state(end_price, cap_min, cap_max) == CapState::FreeFloating {
+            &[]
+        } else {
+            match self.get_ladder(end_level) {
+                Some(v) => v.get_bids(),
+                None => return Err(format!("The bid ladder of level {} of item {} couldn't be fetched.", end_level, item_id)),
+            }
+        };
+        let sell_estimate = estimate_sell_price(end_price, cap_min, cap_max, bids);
+        let actual_value = sell_estimate.get_expected_value();
+        let make_cost = steps.last().map(|s| s.cumulative_cost).unwrap_or(start_cost);
+
+        Ok(AccRouteBreakdown {
+            steps,
+            start_cost,
+            base_cost: Silver::from_f64(base_cost, Rounding::Up),
+            base_purchase,
+            unused_stacks,
+            sell_estimate,
+            profit_details: AccProfitDetails::new(make_cost, actual_value, tax_rate),
+        })
+    }
+}
+
 /// Filters accessories by grade, a minimum price, and a maximum price.
 ///
 pub fn filter_accessories_category(
@@ -120,17 +527,17 @@ pub fn filter_accessories_category(
 /// let stack = 17;
 /// let chance = bdo_enhancement_profit_calculator::accessories::calc_accessory_chance(1, stack);
 ///
-/// assert_eq!(0.675, chance);
+/// assert_eq!(0.675, chance.get());
 /// ```
 ///
 /// ```
 /// let stack = 110;
 /// let chance = bdo_enhancement_profit_calculator::accessories::calc_accessory_chance(5, stack);
 ///
-/// assert_eq!(0.06, chance);
+/// assert_eq!(0.06, chance.get());
 /// ```
 ///
-pub fn calc_accessory_chance(enhancement_level: u8, failstack: u16) -> f64 {
+pub fn calc_accessory_chance(enhancement_level: u8, failstack: u16) -> Probability {
     if enhancement_level < 1 || enhancement_level > 5 {
         panic!("Enhancement level of {enhancement_level}, was given, when it should be in the range 1-5.");
     }
@@ -178,10 +585,10 @@ pub fn calc_accessory_chance(enhancement_level: u8, failstack: u16) -> f64 {
     }
 
     if chance > 0.9 {
-        return 0.9;
+        return Probability::clamped(0.9);
     };
 
-    chance
+    Probability::clamped(chance)
 }
 
 /// Calculates accessories required to enhance from base to a certain level.
@@ -218,7 +625,7 @@ pub fn accessories_required(end_enhancement: u8, stacks: Vec<u16>) -> u16 {
     let mut amount: f64 = 1.0;
     let mut i: u8 = 1;
     for stack in stacks {
-        amount = (1.0 / calc_accessory_chance(i, stack)) * (amount + 1.0);
+        amount = calc_accessory_chance(i, stack).expected_attempts() * (amount + 1.0);
         if i >= end_enhancement {
             break;
         }
@@ -262,7 +669,7 @@ pub fn accessories_required_exact(end_enhancement: u8, stacks: Vec<u16>) -> f64
     let mut amount: f64 = 1.0;
     let mut i: u8 = 1;
     for stack in stacks {
-        amount = (1.0 / calc_accessory_chance(i, stack)) * (amount + 1.0);
+        amount = calc_accessory_chance(i, stack).expected_attempts() * (amount + 1.0);
         if i >= end_enhancement {
             break;
         }
@@ -272,85 +679,333 @@ pub fn accessories_required_exact(end_enhancement: u8, stacks: Vec<u16>) -> f64
     amount
 }
 
-/// Calculates the cost of a singular tap.
-/// 
+/// Calculates the risk of enhancing an accessory, without making any requests.
+///
+/// Each step takes a geometric number of attempts, and every attempt uses the accessory made by the
+/// last step and one base accessory, so the mean and variance of the total cost follow exactly from
+/// [`calc_accessory_chance`].
+/// The chance of profit is exact for a single step, and uses a normal approximation for longer routes.
+/// The Kelly fraction treats the enhancement as a bet of its mean cost.
+///
+/// `start_cost` is the price of the accessory at the starting level, and `base_cost` the price of a base accessory.
+///
 /// # Panics
-/// 
-/// If the given item has an enhancement level above TET (4)
-/// 
-pub fn get_tap_cost<T: HasId>(item_details: AccEnhancementDetails<T>, item_cost: Option<u64>, region: &str) -> u64 {
+///
+/// If no stacks are provided, the end level isn't above the starting level or is above 5 (PEN),
+/// or there are fewer stacks than levels to enhance.
+///
+/// # Examples
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::accessories::{calc_risk_metrics, AccEnhancementDetails};
+/// use bdo_enhancement_profit_calculator::bdo_market_requests::ItemID;
+/// use bdo_enhancement_profit_calculator::units::Silver;
+///
+/// let details = AccEnhancementDetails::new(ItemID::new(1), 0, Some(vec![17]));
+/// let metrics = calc_risk_metrics(&details, 1, Silver::new(1000), Silver::new(1000), Silver::new(5000), 1.0);
+///
+/// assert_eq!(Silver::new(2963), metrics.get_profit_details().get_make_cost());
+/// assert_eq!(Silver::new(2037), metrics.get_profit_details().get_profit_taxed());
+/// // Profitable unless the first 2 attempts both fail.
+/// assert_eq!("89.44%", metrics.get_profit_chance().to_string());
+/// ```
+///
+pub fn calc_risk_metrics<T: HasId>(
+    item_details: &AccEnhancementDetails<T>,
+    end_level: u8,
+    start_cost: Silver,
+    base_cost: Silver,
+    actual_value: Silver,
+    tax_rate: f64,
+) -> AccRiskMetrics {
+    let current_level = item_details.get_level();
+    let stacks = check_tap_route(item_details, end_level);
+    let level_gap = end_level - current_level;
 
-    
-    let id = item_details.get_item_id();
-    let level = item_details.get_level();
-    let stack = item_details.get_first_stack();
+    let mut mean = start_cost.to_f64();
+    let mut variance = 0.0;
+    let mut last_unit = 0.0;
+    let mut last_chance = 1.0;
+    for i in 0..level_gap {
+        let chance = calc_accessory_chance(current_level + i + 1, stacks[i as usize]).get();
+        // Cost of a single attempt, and the compound sum over a geometric number of attempts.
+        let unit = mean + base_cost.to_f64();
+        variance = variance / chance + ((1.0 - chance) / chance.powi(2)) * unit.powi(2);
+        mean = unit / chance;
+        last_unit = unit;
+        last_chance = chance;
+    }
 
-    let cost = match item_cost {
-        Some(v) => v,
-        None => {
-            let item = ItemBuySellInfo::from_post(region, &id.to_string(), &level.to_string());
-            match item {
-                Ok(v) => v.get_lowest_listed(),
-                Err(_) => 0
-            }
-        }
+    let taxed_value = actual_value.after_tax(tax_rate).to_f64();
+    let profit_chance = if level_gap == 1 {
+        let affordable_attempts = (taxed_value / last_unit).floor() as i32;
+        calc_total_chance(last_chance, affordable_attempts.max(0))
+    } else if variance == 0.0 {
+        if taxed_value >= mean { 1.0 } else { 0.0 }
+    } else {
+        normal_cdf((taxed_value - mean) / variance.sqrt())
     };
+    let profit_chance = Probability::clamped(profit_chance);
 
-
-    if level > 4 {
-        panic!("An enhancement level greater than 4 (TET, max - 1 for accessories) was supplied.")
+    let kelly_fraction = if variance == 0.0 {
+        if taxed_value > mean { 1.0 } else { 0.0 }
+    } else {
+        ((taxed_value - mean) * mean / variance).clamp(0.0, 1.0)
     };
 
-    
-    let base_item = ItemBuySellInfo::from_post(region, &id.to_string(), "0").unwrap(); // Level of 0 should always be safe.
-    let chance = calc_accessory_chance(level + 1, stack);
+    AccRiskMetrics {
+        profit_details: AccProfitDetails::new(Silver::from_f64(mean, Rounding::Up), actual_value, tax_rate),
+        cost_variance: variance,
+        profit_chance,
+        kelly_fraction,
+    }
+}
 
-    let make_cost =
-        (base_item.get_lowest_listed() + cost) as f64 * (1.0 / chance);
+/// Standard normal cumulative distribution, using the Abramowitz and Stegun approximation of erf.
+///
+fn normal_cdf(z: f64) -> f64 {
+    let x = z.abs() / std::f64::consts::SQRT_2;
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let poly = t * (0.254829592 + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let erf = 1.0 - poly * (-x * x).exp();
+
+    if z >= 0.0 {
+        0.5 * (1.0 + erf)
+    } else {
+        0.5 * (1.0 - erf)
+    }
+}
+
+/// Contains the costs seen over many simulated enhancements.
+///
+pub struct AccSimulation {
+    costs: Vec<Silver>, // Sorted
+    bases_used: u64,
+}
+impl AccSimulation {
+    pub fn get_runs(&self) -> usize {
+        self.costs.len()
+    }
+
+    pub fn get_mean_cost(&self) -> f64 {
+        self.costs.iter().map(|c| c.to_f64()).sum::<f64>() / self.costs.len() as f64
+    }
+
+    pub fn get_min_cost(&self) -> Silver {
+        self.costs[0]
+    }
+
+    pub fn get_max_cost(&self) -> Silver {
+        self.costs[self.costs.len() - 1]
+    }
+
+    /// Gets the cost that `percentile` percent of runs came in under.
+    ///
+    pub fn get_percentile_cost(&self, percentile: f64) -> Silver {
+        let index = ((percentile / 100.0) * (self.costs.len() - 1) as f64).round() as usize;
+        self.costs[index.min(self.costs.len() - 1)]
+    }
 
-    make_cost.ceil() as u64
+    /// Average base accessories used as material, not counting the accessory being enhanced.
+    ///
+    pub fn get_mean_bases_used(&self) -> f64 {
+        self.bases_used as f64 / self.costs.len() as f64
+    }
+
+    /// Fraction of runs where the cost came in below the taxed value.
+    ///
+    pub fn get_profit_chance(&self, actual_value: Silver, tax_rate: f64) -> Probability {
+        let taxed_value = actual_value.after_tax(tax_rate);
+        let profitable = self.costs.iter().filter(|c| **c < taxed_value).count();
+        Probability::clamped(profitable as f64 / self.costs.len() as f64)
+    }
 }
-/// Calculates the average profit from a singular tap.
+
+/// Simulates enhancing an accessory many times, see [`calc_risk_metrics`] for the arguments.
 ///
 /// # Panics
 ///
-/// If the given item has an enhancement level above TET (4)
+/// The same as [`calc_risk_metrics`], or if `runs` is 0.
 ///
-pub fn get_tap_proft<T: HasId>(item_details: AccEnhancementDetails<T>, item_cost: Option<u64>, tax_rate: f64, region: &str) -> AccProfitDetails {
-
-    let id = item_details.get_item_id();
-    let level = item_details.get_level();
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::accessories::{simulate_tap_mult, AccEnhancementDetails};
+/// use bdo_enhancement_profit_calculator::bdo_market_requests::ItemID;
+/// use bdo_enhancement_profit_calculator::units::Silver;
+///
+/// let details = AccEnhancementDetails::new(ItemID::new(1), 0, Some(vec![20, 40]));
+/// let simulation = simulate_tap_mult(&details, 2, Silver::new(1000), Silver::new(1000), 100);
+///
+/// assert_eq!(100, simulation.get_runs());
+/// // At best, a PRI then a DUO first time.
+/// assert!(simulation.get_min_cost() >= Silver::new(3000));
+/// ```
+///
+pub fn simulate_tap_mult<T: HasId>(
+    item_details: &AccEnhancementDetails<T>,
+    end_level: u8,
+    start_cost: Silver,
+    base_cost: Silver,
+    runs: u32,
+) -> AccSimulation {
+    simulate_tap_mult_with(item_details, end_level, start_cost, base_cost, runs, &mut rand::thread_rng())
+}
 
-    if level > 4 {
-        panic!("An enhancement level greater than TET (4) was given.")
+/// Simulates enhancing an accessory like [`simulate_tap_mult`], drawing from `rng` so results can be repeated.
+///
+pub fn simulate_tap_mult_with<T: HasId, R: Rng>(
+    item_details: &AccEnhancementDetails<T>,
+    end_level: u8,
+    start_cost: Silver,
+    base_cost: Silver,
+    runs: u32,
+    rng: &mut R,
+) -> AccSimulation {
+    let current_level = item_details.get_level();
+    let stacks = check_tap_route(item_details, end_level);
+    let level_gap = end_level - current_level;
+    if runs == 0 {
+        panic!("At least 1 run is required.")
     }
-    let make_cost = get_tap_cost(item_details, item_cost, region);
-    // println!("calling on region: {}, id: {}, level: {}", region, &id.to_string(), &(level+1).to_string());
 
-    let upgrade_item =
-        ItemBuySellInfo::from_post(region, &id.to_string(), &(level + 1).to_string());
+    let chances: Vec<f64> = (0..level_gap)
+        .map(|i| calc_accessory_chance(current_level + i + 1, stacks[i as usize]).get())
+        .collect();
 
-    match upgrade_item {
-        Ok(v) => AccProfitDetails::new(make_cost, v.get_base_price(), tax_rate),
-        Err(_) => AccProfitDetails::new(1,0,0.0)
+    let mut costs = Vec::new();
+    let mut bases_used = 0;
+    for _ in 0..runs {
+        let (cost, bases) = simulate_make(&chances, start_cost, base_cost, rng);
+        costs.push(cost);
+        bases_used += bases;
     }
+    costs.sort_unstable();
 
-    
+    AccSimulation { costs, bases_used }
 }
+
+/// Simulates making one accessory through every step in `chances`, returning the cost and bases used.
+///
+fn simulate_make<R: Rng>(chances: &[f64], start_cost: Silver, base_cost: Silver, rng: &mut R) -> (Silver, u64) {
+    let (last_chance, previous) = match chances.split_last() {
+        Some(v) => v,
+        None => return (start_cost, 0),
+    };
+
+    let mut cost = Silver::ZERO;
+    let mut bases = 0;
+    loop {
+        let (item_cost, item_bases) = simulate_make(previous, start_cost, base_cost, rng);
+        cost += item_cost + base_cost;
+        bases += item_bases + 1;
+        if simulate_enhancement_with(*last_chance, rng) {
+            return (cost, bases);
+        }
+    }
+}
+
 /// Calculates profit to tap from [Enhancement 1] to [Enhancement 2]
 /// 
+/// Every level is fetched in one request, with bid ladders only fetched when needed, see [`AccMarketData`].
+/// 
 /// # Panics 
 /// 
-/// If enhancement 2 >= Enhancement 1
-/// If either is >5 or <0
-/// If not enough stacks are provided.
+/// The same as [`calc_risk_metrics`], the route is checked before anything is requested.
 /// 
 pub fn get_tap_profit_mult<T: HasId>(item_details: AccEnhancementDetails<T>, end_level: u8, tax_rate: f64, region: &str) -> AccProfitDetails {
-    let id = item_details.get_item_id();
+    match get_tap_breakdown_mult(&item_details, end_level, tax_rate, region) {
+        Ok(breakdown) => breakdown.into_profit_details(),
+        Err(_) => AccProfitDetails::unpriced()
+    }
+}
+
+/// Calculates profit like [`get_tap_profit_mult`], along with every tap of the route, see [`AccMarketData::get_tap_breakdown`].
+/// 
+/// # Panics 
+/// 
+/// The same as [`get_tap_profit_mult`].
+/// 
+pub fn get_tap_breakdown_mult<T: HasId>(item_details: &AccEnhancementDetails<T>, end_level: u8, tax_rate: f64, region: &str) -> Result<AccRouteBreakdown, String> {
+    check_tap_route(item_details, end_level);
+
+    let client = new_market_client(region, Catalogue::bundled());
+    let mut market = AccMarketData::from_post(client.as_ref(), item_details.get_item_id())?;
+    market.get_tap_breakdown(item_details, end_level, tax_rate)
+}
+
+/// Calculates every tap of a route, with base accessories at a fixed price and no market values.
+///
+/// Makes no requests, so a route can be recalculated whenever its stacks change.
+///
+/// # Panics
+///
+/// The same as [`get_tap_profit_mult`].
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::accessories::{calc_tap_steps, AccEnhancementDetails};
+/// use bdo_enhancement_profit_calculator::bdo_market_requests::ItemID;
+/// use bdo_enhancement_profit_calculator::units::Silver;
+///
+/// let details = AccEnhancementDetails::new(ItemID::new(1), 0, Some(vec![20, 40]));
+/// let steps = calc_tap_steps(&details, 2, Silver::new(1000), Silver::new(1000));
+///
+/// // 1 / 0.71 attempts, then 2 attempts that each use a PRI and a base.
+/// assert_eq!(1.41, (steps[0].get_expected_bases() * 100.0).round() / 100.0);
+/// assert_eq!(4.82, (steps[1].get_expected_bases() * 100.0).round() / 100.0);
+/// assert_eq!(Silver::new(7634), steps[1].get_cumulative_cost());
+/// ```
+///
+pub fn calc_tap_steps<T: HasId>(item_details: &AccEnhancementDetails<T>, end_level: u8, start_cost: Silver, base_cost: Silver) -> Vec<TapStep> {
+    calc_route_steps(item_details, end_level, start_cost, base_cost.to_f64(), |_| None)
+}
+
+/// Calculates every tap of a route, given the average price paid for a base and the market value of each level.
+///
+/// Each tap uses the stack for its own level, and its cost is rounded up before the next tap builds on it.
+///
+fn calc_route_steps<T: HasId, G: Fn(u8) -> Option<Silver>>(
+    item_details: &AccEnhancementDetails<T>,
+    end_level: u8,
+    start_cost: Silver,
+    base_cost: f64,
+    market_value: G,
+) -> Vec<TapStep> {
+    let current_level = item_details.get_level();
+    let stacks = check_tap_route(item_details, end_level);
+
+    let mut steps: Vec<TapStep> = Vec::new();
+    for (i, &stack) in stacks.iter().enumerate().take((end_level - current_level) as usize) {
+        let from_level = current_level + i as u8;
+        let chance = calc_accessory_chance(from_level + 1, stack);
+        let (item_cost, item_bases) = match steps.last() {
+            Some(v) => (v.cumulative_cost, v.expected_bases),
+            None => (start_cost, 0.0),
+        };
+
+        let cumulative_cost = Silver::from_f64((base_cost + item_cost.to_f64()) * chance.expected_attempts(), Rounding::Up);
+
+        steps.push(TapStep {
+            from_level,
+            to_level: from_level + 1,
+            stack,
+            chance,
+            expected_bases: (item_bases + 1.0) * chance.expected_attempts(),
+            cumulative_cost,
+            market_value: market_value(from_level + 1),
+        });
+    }
+    steps
+}
+
+/// Checks a route from the item's level to `end_level`, returning its stacks.
+/// 
+fn check_tap_route<T: HasId>(item_details: &AccEnhancementDetails<T>, end_level: u8) -> &Vec<u16> {
     let current_level = item_details.get_level();
-    let stacks = item_details.get_stacks();
 
-    let stacks = if let Some(v) = stacks {
+    let stacks = if let Some(v) = item_details.get_stacks() {
         v
     } else {
         panic!("No stacks were provided, when one or more were required.")
@@ -371,29 +1026,5 @@ pub fn get_tap_profit_mult<T: HasId>(item_details: AccEnhancementDetails<T>, end
         panic!("Not enough stacks were provided (needed {level_gap}).")
     }
 
-    let mut cost: u64 = 0;
-
-    // Loops 1 time less than necessary, so the final call can be get_tap_profit
-    // This allows for easy access to the final actual value, removing the need for redundant API calls.
-    // Looping 0..0 will entirely skip the for loop, and is therefore valid.
-    for i in 0..(level_gap - 1).into() {
-        let details = AccEnhancementDetails::new(ItemID::new(id), current_level + i, Some(vec![stacks[i as usize]])); // Takes only the current stack
-        let single_cost = if i == 0 {
-            get_tap_cost(details, None, region)
-        } else { 
-            get_tap_cost(details, Some(cost), region)
-        };
-        cost += single_cost;
-    }
-
-    let details = AccEnhancementDetails::new(ItemID::new(id), end_level - 1, Some(vec![*stacks.last().unwrap()])); // Impossible that it's empty, safe to unwrap.
-    let last_cost = get_tap_proft(details, Some(cost), tax_rate, region);
-
-    let ac_val = last_cost.get_actual_value();
-    let total_cost = cost + last_cost.get_make_cost();
-
-    let profit_data = AccProfitDetails::new(total_cost, ac_val, tax_rate);
-    profit_data
-
+    stacks
 }
-
@@ -0,0 +1,482 @@
+//! # Backtest
+//!
+//! Backtest replays recorded market days, to see what a strategy would have made if it had been run every day.
+//!
+//! Each day, open trades whose sale is due are sold at that day's recorded prices, then the strategy buys
+//! the most profitable enhancements it can afford at that day's prices. Sales take a delay to go through,
+//! which is longer when the finished item is capped and sold by lottery.
+
+use crate::accessories::{simulate_tap_mult_with, AccEnhancementDetails, AccMarketData, AccProfitDetails};
+use crate::bdo_market_requests::market_client::MarketClient;
+use crate::bdo_market_requests::{
+    CategoryGivenInfo, HotItem, ItemBuySellInfo, ItemID, PriceHistory, RegQueueItem, SearchedItem, SpecificItemInfo,
+};
+use crate::general_calcs::market_calcs::calc_profit_taxed;
+use crate::report::csv_field;
+use crate::scan::ScanOptions;
+use crate::units::{Probability, Silver};
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+pub const SECONDS_PER_DAY: u64 = 60 * 60 * 24;
+
+/// Everything recorded on one day.
+///
+pub struct MarketDay {
+    day: u64,
+    items: Vec<CategoryGivenInfo>,
+    ladders: HashMap<(u32, u8), ItemBuySellInfo>,
+}
+
+impl MarketDay {
+    /// Creates a day, where `day` counts days since the unix epoch.
+    ///
+    pub fn new(day: u64, items: Vec<CategoryGivenInfo>) -> Self {
+        MarketDay {
+            day,
+            items,
+            ladders: HashMap::new(),
+        }
+    }
+
+    /// Adds an item's bid ladder at a level, replacing any already added.
+    ///
+    pub fn add_ladder(&mut self, item_id: u32, enhancement_level: u8, info: ItemBuySellInfo) {
+        self.ladders.insert((item_id, enhancement_level), info);
+    }
+
+    pub fn get_day(&self) -> u64 {
+        self.day
+    }
+
+    pub fn get_items(&self) -> &[CategoryGivenInfo] {
+        &self.items
+    }
+
+    pub fn get_ladder(&self, item_id: u32, enhancement_level: u8) -> Option<&ItemBuySellInfo> {
+        self.ladders.get(&(item_id, enhancement_level))
+    }
+
+    /// Gets a level's bid ladder if it has any bids, so an empty ladder counts as no price for the day.
+    ///
+    pub fn get_sellable_ladder(&self, item_id: u32, enhancement_level: u8) -> Option<&ItemBuySellInfo> {
+        self.get_ladder(item_id, enhancement_level).filter(|l| !l.get_bids().is_empty())
+    }
+}
+
+/// Serves a recorded day as a market, so routes are priced like a scan prices them.
+///
+/// Only recorded ladders are known, so a level without bids isn't on this market.
+///
+impl MarketClient for MarketDay {
+    /// Recorded days aren't tied to a region.
+    ///
+    fn get_region(&self) -> &str {
+        "recorded"
+    }
+
+    /// A day only holds the categories that were recorded, so every item is returned.
+    ///
+    fn get_category(&self, _main_category: u16, _sub_category: u16) -> Result<Vec<CategoryGivenInfo>, String> {
+        Ok(self.items.clone())
+    }
+
+    fn get_item_levels(&self, item_id: u32) -> Result<Vec<SpecificItemInfo>, String> {
+        let mut levels: Vec<SpecificItemInfo> = self
+            .ladders
+            .iter()
+            .filter(|((id, _), ladder)| *id == item_id && !ladder.get_bids().is_empty())
+            .map(|((_, level), ladder)| SpecificItemInfo::from_ladder(item_id, *level, ladder))
+            .collect();
+        if levels.is_empty() {
+            return Err(format!("Item {} wasn't recorded on day {}.", item_id, self.day));
+        }
+        levels.sort_by_key(|l| l.get_enhancement_min());
+        Ok(levels)
+    }
+
+    fn get_bids(&self, item_id: u32, enhancement_level: u8) -> Result<ItemBuySellInfo, String> {
+        self.get_sellable_ladder(item_id, enhancement_level)
+            .cloned()
+            .ok_or_else(|| format!("Level {} of item {} wasn't recorded on day {}.", enhancement_level, item_id, self.day))
+    }
+
+    fn get_history(&self, _item_id: u32, _enhancement_level: u8) -> Result<PriceHistory, String> {
+        Err(String::from("Price histories aren't recorded."))
+    }
+
+    fn get_registration_queue(&self) -> Result<Vec<RegQueueItem>, String> {
+        Err(String::from("The registration queue isn't replayed."))
+    }
+
+    fn get_hot_list(&self) -> Result<Vec<HotItem>, String> {
+        Err(String::from("The hot list isn't recorded."))
+    }
+
+    fn search_items(&self, _item_ids: &[u32]) -> Result<Vec<SearchedItem>, String> {
+        Err(String::from("Searches aren't recorded."))
+    }
+}
+
+/// What to buy each day, and how much can be spent.
+///
+pub struct Strategy {
+    /// Filters, stacks, levels, tax and profit thresholds, as for a scan.
+    pub options: ScanOptions,
+    /// Silver available on the first day.
+    pub budget: u64,
+    /// Days between buying the materials and the finished item selling.
+    pub sell_delay_days: u64,
+    /// Most trades started on a single day.
+    pub max_trades_per_day: usize,
+    /// Draw each trade's cost from the simulator, instead of using the expected cost.
+    pub simulate: bool,
+    /// Seeds the simulator, so replaying the same days gives the same trades.
+    pub seed: u64,
+}
+
+/// A single enhancement, from buying the materials to selling the result.
+///
+#[derive(Serialize, Debug, Clone)]
+pub struct Trade {
+    pub item_id: u32,
+    pub name: String,
+    pub start_level: u8,
+    pub end_level: u8,
+    pub bought_on: u64,
+    /// The day the sale is expected to go through.
+    pub sell_on: u64,
+    /// The day it sold, if it has.
+    pub sold_on: Option<u64>,
+    pub cost: Silver,
+    /// Silver received after tax, if it has sold.
+    pub proceeds: Option<Silver>,
+    pub profit: Option<Silver>,
+}
+
+/// Silver held at the end of a day.
+///
+#[derive(Serialize, Debug, Clone)]
+pub struct EquityPoint {
+    pub day: u64,
+    pub cash: Silver,
+    /// Open trades, valued at the day's price after tax, or at cost without a price.
+    pub open_value: Silver,
+    pub open_trades: usize,
+}
+
+impl EquityPoint {
+    pub fn get_equity(&self) -> Silver {
+        self.cash + self.open_value
+    }
+}
+
+pub struct BacktestResult {
+    budget: u64,
+    equity: Vec<EquityPoint>,
+    trades: Vec<Trade>,
+}
+
+impl BacktestResult {
+    pub fn get_equity_curve(&self) -> &[EquityPoint] {
+        &self.equity
+    }
+
+    pub fn get_trades(&self) -> &[Trade] {
+        &self.trades
+    }
+
+    pub fn get_final_equity(&self) -> Silver {
+        self.equity.last().map(|p| p.get_equity()).unwrap_or(Silver::from(self.budget))
+    }
+
+    /// Profit of every trade that sold.
+    ///
+    pub fn get_realised_profit(&self) -> Silver {
+        self.trades.iter().filter_map(|t| t.profit).sum()
+    }
+
+    /// Largest fall in equity from a previous high, as a fraction of that high.
+    ///
+    pub fn get_max_drawdown(&self) -> f64 {
+        let mut high = Silver::from(self.budget);
+        let mut drawdown: f64 = 0.0;
+        for point in &self.equity {
+            high = high.max(point.get_equity());
+            if high > Silver::ZERO {
+                drawdown = drawdown.max((high - point.get_equity()).to_f64() / high.to_f64());
+            }
+        }
+        drawdown
+    }
+}
+
+/// Replays a strategy over recorded days, which are sorted oldest first.
+///
+/// At most one trade per item is open at a time, as the market can only absorb so many of one accessory.
+/// Trades that haven't sold by the last day stay open, valued in the equity curve.
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::backtest::{run_backtest, MarketDay, Strategy};
+/// use bdo_enhancement_profit_calculator::bdo_market_requests::{BiddingInfo, CategoryGivenInfo, ItemBuySellInfo};
+/// use bdo_enhancement_profit_calculator::scan::ScanOptions;
+/// use bdo_enhancement_profit_calculator::units::Silver;
+///
+/// let ladder = |price| {
+///     let bids = vec![BiddingInfo::new(price - 1000, 0, 0), BiddingInfo::new(price, 10, 0), BiddingInfo::new(price + 1000, 0, 0)];
+///     ItemBuySellInfo::new(bids, price, 0, 0, 0, 0, 20)
+/// };
+/// let day = |day| {
+///     let ring = CategoryGivenInfo::new(11607, String::from("Ring"), 3, 2000000, 10);
+///     let mut market_day = MarketDay::new(day, vec![ring]);
+///     market_day.add_ladder(11607, 0, ladder(2000000));
+///     market_day.add_ladder(11607, 1, ladder(10000000));
+///     market_day
+/// };
+///
+/// let mut options = ScanOptions::default();
+/// options.target_levels = vec![1];
+/// options.stacks = vec![20];
+/// options.min_profit = 0;
+///
+/// let strategy = Strategy { options, budget: 10000000, sell_delay_days: 1, max_trades_per_day: 1, simulate: false, seed: 0 };
+/// let result = run_backtest(&[day(1), day(2)], &strategy).unwrap();
+///
+/// // Bought on the first day, sold on the second, then bought again.
+/// let trades = result.get_trades();
+/// assert_eq!((1, Some(2)), (trades[0].bought_on, trades[0].sold_on));
+/// assert_eq!((2, None), (trades[1].bought_on, trades[1].sold_on));
+/// assert_eq!(trades[0].profit, Some(trades[0].proceeds.unwrap() - trades[0].cost));
+/// assert!(result.get_realised_profit() > Silver::ZERO);
+/// ```
+///
+pub fn run_backtest(days: &[MarketDay], strategy: &Strategy) -> Result<BacktestResult, String> {
+    strategy.options.validate()?;
+    if strategy.max_trades_per_day == 0 {
+        return Err(String::from("At least 1 trade a day must be allowed."));
+    }
+    if days.windows(2).any(|w| w[0].day >= w[1].day) {
+        return Err(String::from("The days must be sorted, with one entry per day."));
+    }
+
+    let options = &strategy.options;
+    // Silver saturates, so a wild price can't overflow the balance.
+    let mut cash = Silver::from(strategy.budget);
+    let mut trades: Vec<Trade> = Vec::new();
+    let mut equity = Vec::new();
+    let mut rng = StdRng::seed_from_u64(strategy.seed);
+
+    for day in days {
+        // Sell what's due at its list price, if the day has a price for it. The wait already covers the lottery.
+        for trade in trades.iter_mut().filter(|t| t.sold_on.is_none() && t.sell_on <= day.day) {
+            if let Some(ladder) = day.get_sellable_ladder(trade.item_id, trade.end_level) {
+                let value = ladder.get_sell_estimate().get_list_price();
+                let proceeds = value.after_tax(options.tax_rate);
+                cash += proceeds;
+                trade.sold_on = Some(day.day);
+                trade.proceeds = Some(proceeds);
+                trade.profit = Some(calc_profit_taxed(trade.cost, value, options.tax_rate));
+            }
+        }
+
+        // Buy the best routes the day offers.
+        let mut routes = find_routes(day, options, &trades);
+        routes.sort_by_key(|route| Reverse(route.profit.get_profit_taxed()));
+        let mut started = 0;
+        for route in routes {
+            if started == strategy.max_trades_per_day {
+                break;
+            }
+            let cost = if strategy.simulate {
+                let details = AccEnhancementDetails::new(ItemID::new(route.item.get_item_id()), options.start_level, Some(options.stacks.clone()));
+                simulate_tap_mult_with(&details, route.end_level, route.start_cost, route.base_cost, 1, &mut rng).get_min_cost()
+            } else {
+                route.profit.get_make_cost()
+            };
+            if cost > cash {
+                continue;
+            }
+
+            cash -= cost;
+            started += 1;
+            trades.push(Trade {
+                item_id: route.item.get_item_id(),
+                name: route.item.get_item_name().to_owned(),
+                start_level: options.start_level,
+                end_level: route.end_level,
+                bought_on: day.day,
+                sell_on: day.day + strategy.sell_delay_days + route.lottery_days,
+                sold_on: None,
+                cost,
+                proceeds: None,
+                profit: None,
+            });
+        }
+
+        let open: Vec<&Trade> = trades.iter().filter(|t| t.sold_on.is_none()).collect();
+        let open_value = open
+            .iter()
+            .map(|t| match day.get_sellable_ladder(t.item_id, t.end_level) {
+                Some(ladder) => ladder.get_sell_estimate().get_list_price().after_tax(options.tax_rate),
+                None => t.cost,
+            })
+            .sum();
+        equity.push(EquityPoint {
+            day: day.day,
+            cash,
+            open_value,
+            open_trades: open.len(),
+        });
+    }
+
+    Ok(BacktestResult {
+        budget: strategy.budget,
+        equity,
+        trades,
+    })
+}
+
+/// The best route for an item on a day.
+///
+struct Route<'a> {
+    item: &'a CategoryGivenInfo,
+    end_level: u8,
+    start_cost: Silver,
+    base_cost: Silver,
+    profit: AccProfitDetails,
+    /// Extra days a capped item waits in the pre-order lottery.
+    lottery_days: u64,
+}
+
+fn find_routes<'a>(day: &'a MarketDay, options: &ScanOptions, trades: &[Trade]) -> Vec<Route<'a>> {
+    let items: Vec<&CategoryGivenInfo> = day
+        .items
+        .iter()
+        .filter(|item| {
+            options.is_item_allowed(item)
+                && !trades.iter().any(|t| t.sold_on.is_none() && t.item_id == item.get_item_id())
+        })
+        .collect();
+
+    let mut routes = Vec::new();
+    for item in items {
+        let id = item.get_item_id();
+        // Priced the same way as a scan, with the day standing in for the market.
+        let mut market = match AccMarketData::from_post(day, id) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        let (start_cost, base_cost) = match (market.get_level(options.start_level), market.get_level(0)) {
+            (Some(start), Some(base)) => (Silver::from(start.get_lowest_listed()), Silver::from(base.get_lowest_listed())),
+            _ => continue,
+        };
+
+        let best = options
+            .target_levels
+            .iter()
+            .filter_map(|end_level| {
+                let end = day.get_sellable_ladder(id, *end_level)?;
+                let details = AccEnhancementDetails::new(ItemID::new(id), options.start_level, Some(options.stacks.clone()));
+                let profit = market.get_tap_breakdown(&details, *end_level, options.tax_rate).ok()?.into_profit_details();
+                let sale_chance = end.get_sell_estimate().get_sale_chance();
+                let lottery_days = if sale_chance > Probability::ZERO { sale_chance.expected_attempts().ceil() as u64 - 1 } else { 0 };
+                Some(Route {
+                    item,
+                    end_level: *end_level,
+                    start_cost,
+                    base_cost,
+                    profit,
+                    lottery_days,
+                })
+            })
+            .max_by_key(|route| route.profit.get_profit_taxed());
+
+        if let Some(route) = best {
+            let passes_taxed = match options.min_profit_taxed {
+                Some(min) => route.profit.get_profit_taxed().get() > min,
+                None => true,
+            };
+            if route.profit.is_priced() && route.profit.get_profit().get() > options.min_profit && passes_taxed {
+                routes.push(route);
+            }
+        }
+    }
+    routes
+}
+
+/// Writes the equity curve as CSV.
+///
+pub fn write_equity_csv<W: Write>(writer: &mut W, result: &BacktestResult) -> io::Result<()> {
+    writeln!(writer, "day,cash,open_value,open_trades,equity")?;
+    for point in result.get_equity_curve() {
+        writeln!(
+            writer,
+            "{},{},{},{},{}",
+            point.day,
+            point.cash.get(),
+            point.open_value.get(),
+            point.open_trades,
+            point.get_equity().get()
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes every trade as CSV, with empty fields for trades that haven't sold.
+///
+pub fn write_trades_csv<W: Write>(writer: &mut W, result: &BacktestResult) -> io::Result<()> {
+    writeln!(writer, "item_id,name,start_level,end_level,bought_on,sell_on,sold_on,cost,proceeds,profit")?;
+    for trade in result.get_trades() {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{},{}",
+            trade.item_id,
+            csv_field(&trade.name),
+            trade.start_level,
+            trade.end_level,
+            trade.bought_on,
+            trade.sell_on,
+            trade.sold_on.map(|v| v.to_string()).unwrap_or_default(),
+            trade.cost.get(),
+            trade.proceeds.map(|v| v.get().to_string()).unwrap_or_default(),
+            trade.profit.map(|v| v.get().to_string()).unwrap_or_default()
+        )?;
+    }
+    Ok(())
+}
+
+/// Loads recorded days from storage, between `from` and `to` in seconds since the unix epoch.
+///
+/// When a day has several snapshots, the latest of each is used.
+///
+#[cfg(feature = "sqlite")]
+pub fn load_days(storage: &crate::storage::Storage, region: &str, from: u64, to: u64) -> Result<Vec<MarketDay>, String> {
+    use std::collections::BTreeMap;
+
+    let mut items: BTreeMap<u64, HashMap<u32, CategoryGivenInfo>> = BTreeMap::new();
+    for (observed_at, item) in storage.get_region_items(region, from, to)? {
+        items.entry(observed_at / SECONDS_PER_DAY).or_default().insert(item.get_item_id(), item);
+    }
+
+    let mut days: BTreeMap<u64, MarketDay> = items
+        .into_iter()
+        .map(|(day, items)| (day, MarketDay::new(day, items.into_values().collect())))
+        .collect();
+
+    // Ladders come oldest first, so later snapshots replace earlier ones.
+    for ladder in storage.get_region_ladders(region, from, to)? {
+        let day = ladder.observed_at / SECONDS_PER_DAY;
+        days.entry(day)
+            .or_insert_with(|| MarketDay::new(day, Vec::new()))
+            .add_ladder(ladder.item_id, ladder.enhancement_level, ladder.info);
+    }
+
+    Ok(days.into_values().collect())
+}
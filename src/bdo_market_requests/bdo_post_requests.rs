@@ -41,6 +41,40 @@ const COOKIE_REQUEST_VERIFICATION_TOKEN: &'static str =
 
 // __RequestVerificationToken URL Encoded Param
 const QUERY_REQUEST_VERFICATION_TOKEN: &'static str = env!("BDO_QUERY_REQUEST_VERFICATION_TOKEN");
+
+/// The cookies and token of a web market login, see above for where to find them.
+///
+/// Logins are per region, so scanning several regions at once needs one for each of them.
+///
+#[derive(Clone)]
+pub struct Credentials {
+    trade_auth: String,
+    request_verification_token: String,
+    query_request_verification_token: String,
+}
+
+impl Credentials {
+    pub fn new(trade_auth: &str, request_verification_token: &str, query_request_verification_token: &str) -> Self {
+        Credentials {
+            trade_auth: trade_auth.to_owned(),
+            request_verification_token: request_verification_token.to_owned(),
+            query_request_verification_token: query_request_verification_token.to_owned(),
+        }
+    }
+
+    /// Gets the login the crate was built with.
+    ///
+    pub fn compiled() -> Self {
+        Credentials::new(COOKIE_TRADE_AUTH, COOKIE_REQUEST_VERIFICATION_TOKEN, QUERY_REQUEST_VERFICATION_TOKEN)
+    }
+
+    fn get_cookie(&self) -> String {
+        format!(
+            "{}={}; {}={}",
+            TRADE_AUTH_SESSION, self.trade_auth, REQUEST_VERIFICATION, self.request_verification_token
+        )
+    }
+}
 /*
 * These functions parse the results of post requests.
 * The data is extracted from the json returned, and made into a string.
@@ -71,7 +105,7 @@ fn get_result_msg_market_list(body_text: String) -> Result<String, serde_json::E
 * There are two types, with the second requiring valid cookies (and therefore a BDO account that can access the market.)
 */
 
-pub fn get_item_info(region: &str, item_id: u16) -> Result<String, reqwest::Error> {
+pub fn get_item_info(region: &str, item_id: u32) -> Result<String, reqwest::Error> {
     // Type needs to be declared
     let zero: u8 = 0;
     let data = json!({"keyType": zero, "mainKey": item_id});
@@ -94,9 +128,54 @@ pub fn get_item_info(region: &str, item_id: u16) -> Result<String, reqwest::Erro
     Ok(result_msg.to_string())
 }
 
+/*
+* The following functions use the public trade api, which console markets also answer.
+* They need no cookies, and the results are the same "-" and "|" separated strings.
+*/
+
+pub fn get_category_list(region: &str, main_category_no: u16, sub_category_no: u16) -> Result<String, String> {
+    let zero: u8 = 0;
+    let data = json!({"keyType": zero, "mainCategory": main_category_no, "subCategory": sub_category_no});
+
+    let client = reqwest::blocking::Client::new();
+    let res = client
+        .post(create_post_url(
+            get_market_url(region),
+            "/Trademarket/GetWorldMarketList",
+        ))
+        .json(&data)
+        .send()
+        .map_err(|e| e.to_string())?;
+
+    let res_body = res.text().map_err(|e| e.to_string())?;
+    let result_msg = get_result_msg_data(res_body).map_err(|e| format!("The market response couldn't be read: {}", e))?;
+
+    Ok(result_msg.to_string())
+}
+
+pub fn get_bidding_info_list(region: &str, item_id: u32, enhancement_id: u8) -> Result<String, String> {
+    let zero: u8 = 0;
+    let data = json!({"keyType": zero, "mainKey": item_id, "subKey": enhancement_id});
+
+    let client = reqwest::blocking::Client::new();
+    let res = client
+        .post(create_post_url(
+            get_market_url(region),
+            "/Trademarket/GetBiddingInfoList",
+        ))
+        .json(&data)
+        .send()
+        .map_err(|e| e.to_string())?;
+
+    let res_body = res.text().map_err(|e| e.to_string())?;
+    let result_msg = get_result_msg_data(res_body).map_err(|e| format!("The market response couldn't be read: {}", e))?;
+
+    Ok(result_msg.to_string())
+}
+
 pub fn get_item_price_history(
     region: &str,
-    item_id: u16,
+    item_id: u32,
     enhancement_id: u8,
 ) -> Result<String, reqwest::Error> {
     let zero: u8 = 0;
@@ -135,6 +214,25 @@ pub fn get_registration_queue(region: &str) -> Result<String, reqwest::Error> {
     Ok(result_msg.to_string())
 }
 
+pub fn get_hot_list(region: &str) -> Result<String, String> {
+    let data = json!({});
+
+    let client = reqwest::blocking::Client::new();
+    let res = client
+        .post(create_post_url(
+            get_market_url(region),
+            "/Trademarket/GetWorldMarketHotList",
+        ))
+        .json(&data)
+        .send()
+        .map_err(|e| e.to_string())?;
+
+    let res_body = res.text().map_err(|e| e.to_string())?;
+    let result_msg = get_result_msg_data(res_body).map_err(|e| format!("The market response couldn't be read: {}", e))?;
+
+    Ok(result_msg.to_string())
+}
+
 pub fn search_market_by_id(region: &str, item_ids: Vec<&str>) -> Result<String, reqwest::Error> {
     let data = json!({"searchResult": item_ids.join(",")});
 
@@ -157,19 +255,28 @@ pub fn get_items_from_category(
     region: &str,
     main_category_no: u16,
     sub_category_no: u16,
+) -> Result<String, reqwest::Error> {
+    get_items_from_category_as(region, &Credentials::compiled(), main_category_no, sub_category_no)
+}
+
+pub fn get_items_from_category_as(
+    region: &str,
+    credentials: &Credentials,
+    main_category_no: u16,
+    sub_category_no: u16,
 ) -> Result<String, reqwest::Error> {
     let main_category_no = main_category_no.to_string();
     let sub_category_no = sub_category_no.to_string();
 
     let params = [
-        (REQUEST_VERIFICATION, QUERY_REQUEST_VERFICATION_TOKEN),
+        (REQUEST_VERIFICATION, credentials.query_request_verification_token.as_str()),
         ("mainCategory", &main_category_no),
         ("subCategory", &sub_category_no),
     ];
 
     let client = reqwest::blocking::Client::new();
     let res = client.post(create_post_url(get_market_url(region), "/Home/GetWorldMarketList"))
-        .header("Cookie", format!("{}={}; {}={}", TRADE_AUTH_SESSION, COOKIE_TRADE_AUTH, REQUEST_VERIFICATION, COOKIE_REQUEST_VERIFICATION_TOKEN))
+        .header("Cookie", credentials.get_cookie())
         .header("Content-Type", "application/x-www-form-urlencoded; charset=UTF-8")
         .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/96.0.4664.45 Safari/537.36")
         .form(&params)
@@ -186,9 +293,18 @@ pub fn get_item_buy_sell_info(
     region: &str,
     item_id: &str,
     enhancement_id: &str,
+) -> Result<String, reqwest::Error> {
+    get_item_buy_sell_info_as(region, &Credentials::compiled(), item_id, enhancement_id)
+}
+
+pub fn get_item_buy_sell_info_as(
+    region: &str,
+    credentials: &Credentials,
+    item_id: &str,
+    enhancement_id: &str,
 ) -> Result<String, reqwest::Error> {
     let params = [
-        (REQUEST_VERIFICATION, QUERY_REQUEST_VERFICATION_TOKEN),
+        (REQUEST_VERIFICATION, credentials.query_request_verification_token.as_str()),
         ("keyType", "0"),
         ("mainKey", item_id),
         ("subKey", enhancement_id),
@@ -197,7 +313,7 @@ pub fn get_item_buy_sell_info(
 
     let client = reqwest::blocking::Client::new();
     let res = client.post(create_post_url(get_market_url(region), "/Home/GetItemSellBuyInfo"))
-        .header("Cookie", format!("{}={}; {}={}", TRADE_AUTH_SESSION, COOKIE_TRADE_AUTH, REQUEST_VERIFICATION, COOKIE_REQUEST_VERIFICATION_TOKEN))
+        .header("Cookie", credentials.get_cookie())
         .header("Content-Type", "application/x-www-form-urlencoded; charset=UTF-8")
         .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/96.0.4664.45 Safari/537.36")
         .form(&params)
@@ -1,3 +1,9 @@
+/// Abbreviations of every region with a market.
+///
+pub const REGIONS: [&str; 13] = [
+    "na", "eu", "sea", "mena", "kr", "ru", "jp", "th", "tw", "sa", "console_eu", "console_na", "console_asia",
+];
+
 /// Gets the url of a region's market given the abbreviation of the region.
 ///
 /// # Panics
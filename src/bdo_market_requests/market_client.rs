@@ -0,0 +1,408 @@
+//! # Market Client
+//!
+//! Market Client fetches the market data the profit tools need, in the same models whichever market answers.
+//!
+//! PC markets are read through the web market, which needs the cookies described in
+//! [`bdo_post_requests`](super::bdo_post_requests). Console markets don't serve the web market, so
+//! [`ConsoleMarket`] reads the public trade api instead. Its category lists carry no names or grades,
+//! which are filled in from a [`Catalogue`].
+//!
+//! Clients can be wrapped in a [`RateLimitedClient`], to keep each region's requests under its own limit.
+
+use super::bdo_post_requests::{get_bidding_info_list, get_category_list, Credentials};
+use super::{BiddingInfo, CategoryGivenInfo, HotItem, ItemBuySellInfo, PriceHistory, RegQueueItem, SearchedItem, SpecificItemInfo};
+use crate::catalogue::{Catalogue, DEFAULT_LANGUAGE};
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Something that can answer market requests for a region.
+///
+pub trait MarketClient: Send + Sync {
+    fn get_region(&self) -> &str;
+
+    /// Gets every item in a category.
+    ///
+    fn get_category(&self, main_category: u16, sub_category: u16) -> Result<Vec<CategoryGivenInfo>, String>;
+
+    /// Gets every enhancement level of an item, see [`SpecificItemInfo`].
+    ///
+    fn get_item_levels(&self, item_id: u32) -> Result<Vec<SpecificItemInfo>, String>;
+
+    /// Gets an item's bid ladder at a level.
+    ///
+    fn get_bids(&self, item_id: u32, enhancement_level: u8) -> Result<ItemBuySellInfo, String>;
+
+    /// Gets an item's daily prices at a level, see [`PriceHistory`].
+    ///
+    fn get_history(&self, item_id: u32, enhancement_level: u8) -> Result<PriceHistory, String>;
+
+    /// Gets the items waiting to be registered.
+    ///
+    fn get_registration_queue(&self) -> Result<Vec<RegQueueItem>, String>;
+
+    /// Gets the items trading unusually often, see [`HotItem`].
+    ///
+    fn get_hot_list(&self) -> Result<Vec<HotItem>, String>;
+
+    /// Gets the prices of several items in a single request, see [`SearchedItem`].
+    ///
+    fn search_items(&self, item_ids: &[u32]) -> Result<Vec<SearchedItem>, String>;
+}
+
+/// Checks whether a region is a console market.
+///
+pub fn is_console_region(region: &str) -> bool {
+    region.starts_with("console_")
+}
+
+/// Creates the client a region needs. The catalogue is only used by console markets.
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::bdo_market_requests::market_client::new_market_client;
+/// use bdo_enhancement_profit_calculator::catalogue::Catalogue;
+///
+/// let client = new_market_client("console_eu", Catalogue::default());
+/// assert_eq!("console_eu", client.get_region());
+/// ```
+///
+pub fn new_market_client(region: &str, catalogue: Catalogue) -> Box<dyn MarketClient> {
+    new_market_client_as(region, catalogue, Credentials::compiled())
+}
+
+/// Like [`new_market_client`], logged in to PC markets with `credentials`.
+///
+pub fn new_market_client_as(region: &str, catalogue: Catalogue, credentials: Credentials) -> Box<dyn MarketClient> {
+    if is_console_region(region) {
+        Box::new(ConsoleMarket::new(region, catalogue))
+    } else {
+        Box::new(PcMarket::with_credentials(region, credentials))
+    }
+}
+
+/// Reads a PC region's web market.
+///
+pub struct PcMarket {
+    region: String,
+    credentials: Credentials,
+}
+
+impl PcMarket {
+    /// Creates a client logged in with the crate's built in login.
+    ///
+    pub fn new(region: &str) -> Self {
+        PcMarket::with_credentials(region, Credentials::compiled())
+    }
+
+    pub fn with_credentials(region: &str, credentials: Credentials) -> Self {
+        PcMarket {
+            region: region.to_owned(),
+            credentials,
+        }
+    }
+}
+
+impl MarketClient for PcMarket {
+    fn get_region(&self) -> &str {
+        &self.region
+    }
+
+    fn get_category(&self, main_category: u16, sub_category: u16) -> Result<Vec<CategoryGivenInfo>, String> {
+        CategoryGivenInfo::from_post_as(&self.region, &self.credentials, main_category, sub_category)
+    }
+
+    fn get_item_levels(&self, item_id: u32) -> Result<Vec<SpecificItemInfo>, String> {
+        SpecificItemInfo::from_post(&self.region, item_id)
+    }
+
+    fn get_bids(&self, item_id: u32, enhancement_level: u8) -> Result<ItemBuySellInfo, String> {
+        ItemBuySellInfo::from_post_as(&self.region, &self.credentials, &item_id.to_string(), &enhancement_level.to_string())
+    }
+
+    fn get_history(&self, item_id: u32, enhancement_level: u8) -> Result<PriceHistory, String> {
+        PriceHistory::from_post(&self.region, item_id, enhancement_level)
+    }
+
+    fn get_registration_queue(&self) -> Result<Vec<RegQueueItem>, String> {
+        RegQueueItem::from_post(&self.region)
+    }
+
+    fn get_hot_list(&self) -> Result<Vec<HotItem>, String> {
+        HotItem::from_post(&self.region)
+    }
+
+    fn search_items(&self, item_ids: &[u32]) -> Result<Vec<SearchedItem>, String> {
+        search_items(&self.region, item_ids)
+    }
+}
+
+/// Reads a console region's trade api.
+///
+pub struct ConsoleMarket {
+    region: String,
+    catalogue: Catalogue,
+}
+
+impl ConsoleMarket {
+    pub fn new(region: &str, catalogue: Catalogue) -> Self {
+        ConsoleMarket {
+            region: region.to_owned(),
+            catalogue,
+        }
+    }
+
+    /// Parses a category list, written as `id-stock-trades-price` per item.
+    ///
+    /// Items missing from the catalogue are named by their id, with a grade of 0.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bdo_enhancement_profit_calculator::bdo_market_requests::market_client::ConsoleMarket;
+    /// use bdo_enhancement_profit_calculator::bdo_market_requests::CategoryGivenInfo;
+    /// use bdo_enhancement_profit_calculator::catalogue::Catalogue;
+    ///
+    /// let mut catalogue = Catalogue::default();
+    /// catalogue.import_category(20, 1, &[CategoryGivenInfo::new(11607, String::from("Ring"), 3, 0, 0)], "en");
+    ///
+    /// let items = ConsoleMarket::build_category("11607-12-3400-250000000|11853-0-10-90000000|", &catalogue);
+    ///
+    /// assert_eq!("Ring", items[0].get_item_name());
+    /// assert_eq!(3, items[0].get_item_grade());
+    /// assert_eq!(250000000, items[0].get_base_price());
+    /// assert_eq!(12, items[0].get_stock());
+    /// assert_eq!("Item 11853", items[1].get_item_name());
+    /// ```
+    ///
+    pub fn build_category(data: &str, catalogue: &Catalogue) -> Vec<CategoryGivenInfo> {
+        data.split('|')
+            .filter(|f| !f.is_empty())
+            .filter_map(|s| {
+                let vec: Vec<&str> = s.split('-').collect();
+                if vec.len() < 4 {
+                    return None;
+                }
+                let item_id = vec[0].parse::<u32>().ok()?;
+                let entry = catalogue.get(item_id);
+                let name = entry
+                    .and_then(|e| e.get_name(DEFAULT_LANGUAGE))
+                    .map(|n| n.to_owned())
+                    .unwrap_or_else(|| format!("Item {}", item_id));
+
+                Some(CategoryGivenInfo::new(
+                    item_id,
+                    name,
+                    entry.map(|e| e.grade).unwrap_or(0),
+                    vec[3].parse::<u64>().unwrap_or(0),
+                    vec[1].parse::<u64>().unwrap_or(0),
+                ))
+            })
+            .collect()
+    }
+
+    /// Parses a bid ladder, written as `price-listed-pre-ordered` per price.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bdo_enhancement_profit_calculator::bdo_market_requests::market_client::ConsoleMarket;
+    ///
+    /// let bids = ConsoleMarket::build_bids("1000-3-0|1100-0-5|");
+    ///
+    /// assert_eq!(2, bids.len());
+    /// assert_eq!(3, bids[0].get_sell_count());
+    /// assert_eq!(5, bids[1].get_buy_count());
+    /// ```
+    ///
+    pub fn build_bids(data: &str) -> Vec<BiddingInfo> {
+        data.split('|')
+            .filter(|f| !f.is_empty())
+            .filter_map(|s| {
+                let vec: Vec<&str> = s.split('-').collect();
+                if vec.len() < 3 {
+                    return None;
+                }
+                Some(BiddingInfo::new(
+                    vec[0].parse::<u64>().ok()?,
+                    vec[1].parse::<u32>().unwrap_or(0),
+                    vec[2].parse::<u32>().unwrap_or(0),
+                ))
+            })
+            .collect()
+    }
+}
+
+impl MarketClient for ConsoleMarket {
+    fn get_region(&self) -> &str {
+        &self.region
+    }
+
+    fn get_category(&self, main_category: u16, sub_category: u16) -> Result<Vec<CategoryGivenInfo>, String> {
+        let data = get_category_list(&self.region, main_category, sub_category)?;
+        let items = ConsoleMarket::build_category(&data, &self.catalogue);
+
+        // Uncatalogued items have no name or grade, so scans filtering by grade quietly drop them.
+        let missing = items.iter().filter(|i| self.catalogue.get(i.get_item_id()).is_none()).count();
+        if missing * 2 > items.len() {
+            eprintln!(
+                "{} of {} items in category {}-{} aren't in the catalogue, give --catalogue a fuller one to scan them",
+                missing,
+                items.len(),
+                main_category,
+                sub_category
+            );
+        }
+        Ok(items)
+    }
+
+    fn get_item_levels(&self, item_id: u32) -> Result<Vec<SpecificItemInfo>, String> {
+        // The sub list is part of the public trade api, so it's the same request as on PC.
+        SpecificItemInfo::from_post(&self.region, item_id)
+    }
+
+    /// The trade api's ladder has no base price, so the level's price comes from its sub list.
+    /// Enhancement materials and the bid limit aren't given, and are left at 0.
+    ///
+    fn get_bids(&self, item_id: u32, enhancement_level: u8) -> Result<ItemBuySellInfo, String> {
+        let levels = self.get_item_levels(item_id)?;
+        let base_price = SpecificItemInfo::find_level(&levels, enhancement_level)
+            .map(|l| l.get_base_price())
+            .ok_or_else(|| format!("Level {} of item {} isn't on the market.", enhancement_level, item_id))?;
+
+        let data = get_bidding_info_list(&self.region, item_id, enhancement_level)?;
+        Ok(ItemBuySellInfo::new(ConsoleMarket::build_bids(&data), base_price, 0, 0, 0, 0, 0))
+    }
+
+    // Histories, the queue, the hot list and searches are part of the public trade api, so they're the same requests as on PC.
+
+    fn get_history(&self, item_id: u32, enhancement_level: u8) -> Result<PriceHistory, String> {
+        PriceHistory::from_post(&self.region, item_id, enhancement_level)
+    }
+
+    fn get_registration_queue(&self) -> Result<Vec<RegQueueItem>, String> {
+        RegQueueItem::from_post(&self.region)
+    }
+
+    fn get_hot_list(&self) -> Result<Vec<HotItem>, String> {
+        HotItem::from_post(&self.region)
+    }
+
+    fn search_items(&self, item_ids: &[u32]) -> Result<Vec<SearchedItem>, String> {
+        search_items(&self.region, item_ids)
+    }
+}
+
+fn search_items(region: &str, item_ids: &[u32]) -> Result<Vec<SearchedItem>, String> {
+    let ids: Vec<String> = item_ids.iter().map(|id| id.to_string()).collect();
+    SearchedItem::from_post(region, ids.iter().map(|id| id.as_str()).collect())
+}
+
+/// Spaces out requests evenly, so no more than a set number are made each second.
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::bdo_market_requests::market_client::RateLimiter;
+/// use std::time::{Duration, Instant};
+///
+/// let limiter = RateLimiter::new(20.0).unwrap();
+/// let start = Instant::now();
+/// for _ in 0..3 {
+///     limiter.wait();
+/// }
+///
+/// // The first request goes straight away, the others wait 50ms each.
+/// assert!(start.elapsed() >= Duration::from_millis(100));
+/// assert!(RateLimiter::new(0.0).is_err());
+/// ```
+///
+pub struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: f64) -> Result<Self, String> {
+        if !(requests_per_second > 0.0 && requests_per_second.is_finite()) {
+            return Err(format!("Requests per second must be above 0, got {}.", requests_per_second));
+        }
+        Ok(RateLimiter {
+            interval: Duration::from_secs_f64(1.0 / requests_per_second),
+            next: Mutex::new(Instant::now()),
+        })
+    }
+
+    /// Blocks until the next request is allowed.
+    ///
+    pub fn wait(&self) {
+        // The lock is held while sleeping, so threads sharing a limiter take turns.
+        let mut next = self.next.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        if *next > now {
+            thread::sleep(*next - now);
+        }
+        *next = now.max(*next) + self.interval;
+    }
+}
+
+/// A client that waits for its [`RateLimiter`] before every call.
+///
+/// Each call counts as one request, although console bid ladders make two.
+///
+pub struct RateLimitedClient {
+    client: Box<dyn MarketClient>,
+    limiter: RateLimiter,
+}
+
+impl RateLimitedClient {
+    pub fn new(client: Box<dyn MarketClient>, requests_per_second: f64) -> Result<Self, String> {
+        Ok(RateLimitedClient {
+            client,
+            limiter: RateLimiter::new(requests_per_second)?,
+        })
+    }
+}
+
+impl MarketClient for RateLimitedClient {
+    fn get_region(&self) -> &str {
+        self.client.get_region()
+    }
+
+    fn get_category(&self, main_category: u16, sub_category: u16) -> Result<Vec<CategoryGivenInfo>, String> {
+        self.limiter.wait();
+        self.client.get_category(main_category, sub_category)
+    }
+
+    fn get_item_levels(&self, item_id: u32) -> Result<Vec<SpecificItemInfo>, String> {
+        self.limiter.wait();
+        self.client.get_item_levels(item_id)
+    }
+
+    fn get_bids(&self, item_id: u32, enhancement_level: u8) -> Result<ItemBuySellInfo, String> {
+        self.limiter.wait();
+        self.client.get_bids(item_id, enhancement_level)
+    }
+
+    fn get_history(&self, item_id: u32, enhancement_level: u8) -> Result<PriceHistory, String> {
+        self.limiter.wait();
+        self.client.get_history(item_id, enhancement_level)
+    }
+
+    fn get_registration_queue(&self) -> Result<Vec<RegQueueItem>, String> {
+        self.limiter.wait();
+        self.client.get_registration_queue()
+    }
+
+    fn get_hot_list(&self) -> Result<Vec<HotItem>, String> {
+        self.limiter.wait();
+        self.client.get_hot_list()
+    }
+
+    fn search_items(&self, item_ids: &[u32]) -> Result<Vec<SearchedItem>, String> {
+        self.limiter.wait();
+        self.client.search_items(item_ids)
+    }
+}
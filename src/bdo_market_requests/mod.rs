@@ -1,15 +1,22 @@
 pub mod bdo_post_requests;
 pub mod get_bdo_urls;
+pub mod market_client;
 
 use bdo_post_requests::{
-    get_item_buy_sell_info, get_items_from_category, get_registration_queue, search_market_by_id,
+    get_hot_list, get_item_buy_sell_info_as, get_item_info, get_item_price_history,
+    get_items_from_category_as, get_registration_queue, search_market_by_id, Credentials,
 };
 use serde_json::{Error, Value};
 
+use crate::general_calcs::market_calcs::{
+    estimate_purchase, estimate_sell_price, PurchaseEstimate, SellEstimate,
+};
+
 pub trait HasId {
     fn get_item_id(&self) -> u32;
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ItemID {
     item_id: u32,
 }
@@ -27,6 +34,7 @@ impl ItemID {
 }
 
 // Get item info
+#[derive(Debug, Clone)]
 pub struct SpecificItemInfo {
     item_id: u32,
     enhancement_min: u8,
@@ -41,10 +49,127 @@ pub struct SpecificItemInfo {
 }
 
 impl SpecificItemInfo {
-    pub fn from_post(region: &str, item_id: &str, enhancement_id: &str) -> Vec<Self> {
-        let data = get_item_buy_sell_info(region, item_id, enhancement_id).unwrap();
+    pub fn get_item_id(&self) -> u32 {
+        self.item_id
+    }
+
+    pub fn get_enhancement_min(&self) -> u8 {
+        self.enhancement_min
+    }
+
+    pub fn get_enhancement_max(&self) -> u8 {
+        self.enhancement_max
+    }
+
+    pub fn get_base_price(&self) -> u64 {
+        self.base_price
+    }
+
+    pub fn get_stock(&self) -> u64 {
+        self.stock
+    }
+
+    pub fn get_total_trades(&self) -> u64 {
+        self.total_trades
+    }
+
+    pub fn get_price_cap_min(&self) -> u64 {
+        self.price_cap_min
+    }
+
+    pub fn get_price_cap_max(&self) -> u64 {
+        self.price_cap_max
+    }
 
-        SpecificItemInfo::build_vec(data)
+    pub fn get_last_sale_price(&self) -> u64 {
+        self.last_sale_price
+    }
+
+    pub fn get_last_sale_time(&self) -> &str {
+        &self.last_sale_time
+    }
+
+    /// Estimates the realised sale value, using the parsed price caps and the given bid ladder.
+    ///
+    pub fn get_sell_estimate(&self, bids: &[BiddingInfo]) -> SellEstimate {
+        estimate_sell_price(self.base_price, self.price_cap_min, self.price_cap_max, bids)
+    }
+
+    /// Gets every enhancement level of an item, in a single request.
+    ///
+    pub fn from_post(region: &str, item_id: u32) -> Result<Vec<Self>, String> {
+        let data = get_item_info(region, item_id).map_err(|e| e.to_string())?;
+        let levels = SpecificItemInfo::build_vec(data);
+        if levels.is_empty() {
+            return Err(String::from("The item can't be found on the market."));
+        }
+        Ok(levels)
+    }
+
+    /// Finds the entry covering an enhancement level, since some items group several levels into one.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bdo_enhancement_profit_calculator::bdo_market_requests::SpecificItemInfo;
+    ///
+    /// let levels = SpecificItemInfo::build_vec(String::from(
+    ///     "10007-0-7-50000-12-900-40000-60000-50000-1700000000|10007-8-8-90000-3-100-70000-110000-90000-1700000000|",
+    /// ));
+    ///
+    /// assert_eq!(50000, SpecificItemInfo::find_level(&levels, 5).unwrap().get_base_price());
+    /// assert_eq!(3, SpecificItemInfo::find_level(&levels, 8).unwrap().get_stock());
+    /// assert!(SpecificItemInfo::find_level(&levels, 9).is_none());
+    /// ```
+    ///
+    pub fn find_level(levels: &[SpecificItemInfo], enhancement_level: u8) -> Option<&SpecificItemInfo> {
+        levels
+            .iter()
+            .find(|l| l.enhancement_min <= enhancement_level && enhancement_level <= l.enhancement_max)
+    }
+
+    /// Gets what the cheapest listing costs, assuming listings sit at the market price.
+    ///
+    /// With nothing listed, a buyer has to pre-order at the top of the range.
+    ///
+    pub fn get_lowest_listed(&self) -> u64 {
+        if self.stock > 0 {
+            self.base_price
+        } else {
+            self.price_cap_max
+        }
+    }
+
+    /// Builds a single level from its bid ladder, for example one that was recorded earlier.
+    ///
+    /// The ladder's ends are used as the price caps, like [`ItemBuySellInfo::get_sell_estimate`], and
+    /// stock is everything listed on it. Trades and the last sale aren't known, and are left at 0.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bdo_enhancement_profit_calculator::bdo_market_requests::{BiddingInfo, ItemBuySellInfo, SpecificItemInfo};
+    ///
+    /// let bids = vec![BiddingInfo::new(3000, 2, 0), BiddingInfo::new(4000, 3, 0), BiddingInfo::new(5000, 0, 7)];
+    /// let level = SpecificItemInfo::from_ladder(11607, 1, &ItemBuySellInfo::new(bids, 4000, 0, 0, 0, 0, 0));
+    ///
+    /// assert_eq!((3000, 5000), (level.get_price_cap_min(), level.get_price_cap_max()));
+    /// assert_eq!(5, level.get_stock());
+    /// ```
+    ///
+    pub fn from_ladder(item_id: u32, enhancement_level: u8, ladder: &ItemBuySellInfo) -> Self {
+        SpecificItemInfo {
+            item_id,
+            enhancement_min: enhancement_level,
+            enhancement_max: enhancement_level,
+            base_price: ladder.get_base_price(),
+            stock: ladder.get_bids().iter().map(|b| b.get_sell_count() as u64).sum(),
+            total_trades: 0,
+            price_cap_min: ladder.get_min_price(),
+            price_cap_max: ladder.get_max_price(),
+            last_sale_price: 0,
+            last_sale_time: String::new(),
+        }
     }
 
     pub fn build_vec(data: String) -> Vec<Self> {
@@ -74,7 +199,99 @@ impl SpecificItemInfo {
         item_info
     }
 }
-// Get item price history doesn't need a struct - list of strings
+// Get item price history
+#[derive(Debug, Clone)]
+pub struct PriceHistory {
+    item_id: u32,
+    enhancement_level: u8,
+    prices: Vec<u64>, // Daily, oldest first
+}
+
+impl HasId for PriceHistory {
+    fn get_item_id(&self) -> u32 {
+        self.item_id
+    }
+}
+
+impl PriceHistory {
+    pub fn get_enhancement_level(&self) -> u8 {
+        self.enhancement_level
+    }
+
+    pub fn get_prices(&self) -> &[u64] {
+        &self.prices
+    }
+
+    pub fn get_latest_price(&self) -> Option<u64> {
+        self.prices.last().copied()
+    }
+
+    /// Average price of the last `days` days.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bdo_enhancement_profit_calculator::bdo_market_requests::PriceHistory;
+    ///
+    /// let history = PriceHistory::build(1, 0, String::from("100-200-300-400"));
+    ///
+    /// assert_eq!(Some(350.0), history.get_average(2));
+    /// assert_eq!(Some(250.0), history.get_average(90));
+    /// ```
+    ///
+    pub fn get_average(&self, days: usize) -> Option<f64> {
+        let recent = &self.prices[self.prices.len().saturating_sub(days)..];
+        if recent.is_empty() {
+            return None;
+        }
+        Some(recent.iter().sum::<u64>() as f64 / recent.len() as f64)
+    }
+
+    /// Percentage change of the latest price against the average of the `days` days before it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bdo_enhancement_profit_calculator::bdo_market_requests::PriceHistory;
+    ///
+    /// let history = PriceHistory::build(1, 0, String::from("100-100-80"));
+    ///
+    /// assert_eq!(Some(-20.0), history.get_change_percent(7));
+    /// ```
+    ///
+    pub fn get_change_percent(&self, days: usize) -> Option<f64> {
+        let (latest, previous) = self.prices.split_last()?;
+        let previous = &previous[previous.len().saturating_sub(days)..];
+        if previous.is_empty() {
+            return None;
+        }
+        let average = previous.iter().sum::<u64>() as f64 / previous.len() as f64;
+        if average == 0.0 {
+            return None;
+        }
+        Some((*latest as f64 - average) / average * 100.0)
+    }
+
+    pub fn from_post(region: &str, item_id: u32, enhancement_level: u8) -> Result<Self, String> {
+        let data = get_item_price_history(region, item_id, enhancement_level).map_err(|e| e.to_string())?;
+        Ok(PriceHistory::build(item_id, enhancement_level, data))
+    }
+
+    pub fn build(item_id: u32, enhancement_level: u8, data: String) -> Self {
+        let prices = data
+            .split('-')
+            .filter(|f| f != &"")
+            .map(|p| p.parse::<u64>().unwrap_or(0))
+            .collect();
+
+        PriceHistory {
+            item_id,
+            enhancement_level,
+            prices,
+        }
+    }
+}
+
 
 // Get registration queue
 pub struct RegQueueItem {
@@ -91,9 +308,21 @@ impl HasId for RegQueueItem {
 }
 
 impl RegQueueItem {
-    pub fn from_post(region: &str) -> Vec<Self> {
-        let data = get_registration_queue("eu").unwrap();
-        return RegQueueItem::build_vec(data);
+    pub fn get_enhancement_level(&self) -> u8 {
+        self.enhancement_level
+    }
+
+    pub fn get_listed_price(&self) -> u64 {
+        self.listed_price
+    }
+
+    pub fn get_registered_timestamp(&self) -> &str {
+        &self.registered_timestamp
+    }
+
+    pub fn from_post(region: &str) -> Result<Vec<Self>, String> {
+        let data = get_registration_queue(region).map_err(|e| e.to_string())?;
+        Ok(RegQueueItem::build_vec(data))
     }
 
     pub fn build_vec(data: String) -> Vec<Self> {
@@ -117,7 +346,157 @@ impl RegQueueItem {
     }
 }
 
+// Get hot list
+/// Which way a hot item's price last moved.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PriceDirection {
+    Up,
+    Down,
+    Unchanged,
+}
+
+/// An item on the market's hot list, of items trading unusually often.
+///
+#[derive(Debug, Clone)]
+pub struct HotItem {
+    item_id: u32,
+    enhancement_min: u8,
+    enhancement_max: u8,
+    base_price: u64,
+    stock: u64,
+    total_trades: u64,
+    price_direction: PriceDirection,
+    price_change: u64,
+    price_cap_min: u64,
+    price_cap_max: u64,
+    last_sale_price: u64,
+    last_sale_time: String, // Unix timestamp
+}
+
+impl HasId for HotItem {
+    fn get_item_id(&self) -> u32 {
+        self.item_id
+    }
+}
+
+impl HotItem {
+    pub fn get_enhancement_min(&self) -> u8 {
+        self.enhancement_min
+    }
+
+    pub fn get_enhancement_max(&self) -> u8 {
+        self.enhancement_max
+    }
+
+    pub fn get_base_price(&self) -> u64 {
+        self.base_price
+    }
+
+    pub fn get_stock(&self) -> u64 {
+        self.stock
+    }
+
+    pub fn get_total_trades(&self) -> u64 {
+        self.total_trades
+    }
+
+    pub fn get_price_direction(&self) -> PriceDirection {
+        self.price_direction
+    }
+
+    /// Size of the last price move, always positive, see [`HotItem::get_price_direction`].
+    ///
+    pub fn get_price_change(&self) -> u64 {
+        self.price_change
+    }
+
+    /// Last price move with its sign.
+    ///
+    pub fn get_signed_price_change(&self) -> i64 {
+        match self.price_direction {
+            PriceDirection::Up => self.price_change as i64,
+            PriceDirection::Down => -(self.price_change as i64),
+            PriceDirection::Unchanged => 0,
+        }
+    }
+
+    pub fn get_price_cap_min(&self) -> u64 {
+        self.price_cap_min
+    }
+
+    pub fn get_price_cap_max(&self) -> u64 {
+        self.price_cap_max
+    }
+
+    pub fn get_last_sale_price(&self) -> u64 {
+        self.last_sale_price
+    }
+
+    pub fn get_last_sale_time(&self) -> &str {
+        &self.last_sale_time
+    }
+
+    pub fn from_post(region: &str) -> Result<Vec<Self>, String> {
+        let data = get_hot_list(region)?;
+        Ok(HotItem::build_vec(data))
+    }
+
+    /// Parses the hot list, skipping entries without every field.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bdo_enhancement_profit_calculator::bdo_market_requests::{HasId, HotItem, PriceDirection};
+    ///
+    /// let hot = HotItem::build_vec(String::from(
+    ///     "11607-4-4-900000000-2-150-2-50000000-800000000-1000000000-910000000-1700000000|broken|",
+    /// ));
+    ///
+    /// assert_eq!(1, hot.len());
+    /// assert_eq!(11607, hot[0].get_item_id());
+    /// assert_eq!(PriceDirection::Down, hot[0].get_price_direction());
+    /// assert_eq!(-50000000, hot[0].get_signed_price_change());
+    /// ```
+    ///
+    pub fn build_vec(data: String) -> Vec<Self> {
+        let outer_split = data.split("|").filter(|f| f != &"");
+
+        let mut item_info: Vec<HotItem> = Vec::new();
+        for s in outer_split {
+            let vec: Vec<&str> = s.split("-").collect();
+            if vec.len() < 12 {
+                continue;
+            }
+            let price_direction = match vec[6] {
+                "1" => PriceDirection::Up,
+                "2" => PriceDirection::Down,
+                _ => PriceDirection::Unchanged,
+            };
+            let single_item_info = HotItem {
+                item_id: vec[0].parse::<u32>().unwrap_or(0),
+                enhancement_min: vec[1].parse::<u8>().unwrap_or(0),
+                enhancement_max: vec[2].parse::<u8>().unwrap_or(0),
+                base_price: vec[3].parse::<u64>().unwrap_or(0),
+                stock: vec[4].parse::<u64>().unwrap_or(0),
+                total_trades: vec[5].parse::<u64>().unwrap_or(0),
+                price_direction,
+                price_change: vec[7].parse::<u64>().unwrap_or(0),
+                price_cap_min: vec[8].parse::<u64>().unwrap_or(0),
+                price_cap_max: vec[9].parse::<u64>().unwrap_or(0),
+                last_sale_price: vec[10].parse::<u64>().unwrap_or(0),
+                last_sale_time: vec[11].to_string(),
+            };
+
+            item_info.push(single_item_info)
+        }
+
+        item_info
+    }
+}
+
 // Search market by id
+#[derive(Debug, Clone)]
 pub struct SearchedItem {
     item_id: u32,
     stock: u64,
@@ -132,10 +511,22 @@ impl HasId for SearchedItem {
 }
 
 impl SearchedItem {
-    pub fn from_post(region: &str, item_ids: Vec<&str>) -> Vec<Self> {
-        let data = search_market_by_id(region, item_ids).unwrap();
+    pub fn get_stock(&self) -> u64 {
+        self.stock
+    }
+
+    pub fn get_base_price(&self) -> u64 {
+        self.base_price
+    }
+
+    pub fn get_total_trades(&self) -> u64 {
+        self.total_trades
+    }
 
-        return SearchedItem::build_vec(data);
+    pub fn from_post(region: &str, item_ids: Vec<&str>) -> Result<Vec<Self>, String> {
+        let data = search_market_by_id(region, item_ids).map_err(|e| e.to_string())?;
+
+        Ok(SearchedItem::build_vec(data))
     }
 
     pub fn build_vec(data: String) -> Vec<Self> {
@@ -172,7 +563,7 @@ fn remove_second_quotes(st: String) -> String {
 }
 
 // Get items from category
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CategoryGivenInfo {
     item_grade: u8,
     item_id: u32,
@@ -188,6 +579,16 @@ impl HasId for CategoryGivenInfo {
 }
 
 impl CategoryGivenInfo {
+    pub fn new(item_id: u32, item_name: String, item_grade: u8, base_price: u64, stock: u64) -> Self {
+        CategoryGivenInfo {
+            item_grade,
+            item_id,
+            base_price,
+            item_name,
+            stock,
+        }
+    }
+
     pub fn get_item_grade(&self) -> u8 {
         self.item_grade
     }
@@ -204,9 +605,19 @@ impl CategoryGivenInfo {
         &self.item_name
     }
 
-    pub fn from_post(region: &str, main_category_no: u16, sub_category_no: u16) -> Vec<Self> {
-        let data = get_items_from_category(region, main_category_no, sub_category_no).unwrap();
-        return CategoryGivenInfo::build_vec(data).unwrap();
+    pub fn get_stock(&self) -> u64 {
+        self.stock
+    }
+
+    pub fn from_post(region: &str, main_category_no: u16, sub_category_no: u16) -> Result<Vec<Self>, String> {
+        CategoryGivenInfo::from_post_as(region, &Credentials::compiled(), main_category_no, sub_category_no)
+    }
+
+    /// Like [`from_post`](Self::from_post), logged in with `credentials` instead of the built in login.
+    ///
+    pub fn from_post_as(region: &str, credentials: &Credentials, main_category_no: u16, sub_category_no: u16) -> Result<Vec<Self>, String> {
+        let data = get_items_from_category_as(region, credentials, main_category_no, sub_category_no).map_err(|e| e.to_string())?;
+        CategoryGivenInfo::build_vec(data).map_err(|e| e.to_string())
     }
 
     pub fn build_vec(data: String) -> Result<Vec<Self>, Error> {
@@ -243,7 +654,7 @@ impl CategoryGivenInfo {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 // Get item buy/sell info
 pub struct BiddingInfo {
     sell_count: u32,
@@ -251,7 +662,29 @@ pub struct BiddingInfo {
     bidding_price: u64,
 }
 
-#[derive(Debug)]
+impl BiddingInfo {
+    pub fn new(bidding_price: u64, sell_count: u32, buy_count: u32) -> Self {
+        BiddingInfo {
+            sell_count,
+            buy_count,
+            bidding_price,
+        }
+    }
+
+    pub fn get_sell_count(&self) -> u32 {
+        self.sell_count
+    }
+
+    pub fn get_buy_count(&self) -> u32 {
+        self.buy_count
+    }
+
+    pub fn get_bidding_price(&self) -> u64 {
+        self.bidding_price
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct ItemBuySellInfo {
     // Impls need testing
     bids: Vec<BiddingInfo>,
@@ -263,14 +696,56 @@ pub struct ItemBuySellInfo {
     max_bids_per_person: u16,
 }
 impl ItemBuySellInfo {
+    /// Creates the info from its parts, for example when it was stored earlier.
+    ///
+    pub fn new(
+        bids: Vec<BiddingInfo>,
+        base_price: u64,
+        enhancement_group: u8,
+        enhancement_material_id: u32,
+        enhancement_material_base_price: u64,
+        enhancement_material_required_amount: u8,
+        max_bids_per_person: u16,
+    ) -> Self {
+        ItemBuySellInfo {
+            bids,
+            base_price,
+            enhancement_group,
+            enhancement_material_id,
+            enhancement_material_base_price,
+            enhancement_material_required_amount,
+            max_bids_per_person,
+        }
+    }
+
     pub fn get_base_price(&self) -> u64 {
         self.base_price
     }
 
+    pub fn get_bids(&self) -> &[BiddingInfo] {
+        &self.bids
+    }
+
+    pub fn get_enhancement_group(&self) -> u8 {
+        self.enhancement_group
+    }
+
     pub fn get_enhancement_material_id(&self) -> u32 {
         self.enhancement_material_id
     }
 
+    pub fn get_enhancement_material_base_price(&self) -> u64 {
+        self.enhancement_material_base_price
+    }
+
+    pub fn get_enhancement_material_required_amount(&self) -> u8 {
+        self.enhancement_material_required_amount
+    }
+
+    pub fn get_max_bids_per_person(&self) -> u16 {
+        self.max_bids_per_person
+    }
+
     pub fn get_max_price(&self) -> u64 {
         let mut max: u64 = 0;
         for bid in &self.bids {
@@ -291,6 +766,21 @@ impl ItemBuySellInfo {
 
         min
     }
+    /// Estimates the realised sale value.
+    ///
+    /// The bid ladder covers every allowed price, so its ends are used as the price caps.
+    /// An empty ladder can't be sold into, see [`CapState::Unlisted`](crate::general_calcs::market_calcs::CapState::Unlisted).
+    ///
+    pub fn get_sell_estimate(&self) -> SellEstimate {
+        estimate_sell_price(self.base_price, self.get_min_price(), self.get_max_price(), &self.bids)
+    }
+
+    /// Estimates the cost of buying a quantity of this item, see [`estimate_purchase`].
+    ///
+    pub fn get_purchase_estimate(&self, quantity: u64) -> PurchaseEstimate {
+        estimate_purchase(&self.bids, quantity)
+    }
+
     pub fn get_lowest_listed(&self) -> u64 {
         let mut price: u64 = u64::MAX;
         let mut max_price: u64 = 0;
@@ -311,11 +801,17 @@ impl ItemBuySellInfo {
     }
 
     pub fn from_post(region: &str, item_id: &str, enhancement_id: &str) -> Result<Self, String> {
-        let data = get_item_buy_sell_info(region, item_id, enhancement_id).unwrap();
+        ItemBuySellInfo::from_post_as(region, &Credentials::compiled(), item_id, enhancement_id)
+    }
+
+    /// Like [`from_post`](Self::from_post), logged in with `credentials` instead of the built in login.
+    ///
+    pub fn from_post_as(region: &str, credentials: &Credentials, item_id: &str, enhancement_id: &str) -> Result<Self, String> {
+        let data = get_item_buy_sell_info_as(region, credentials, item_id, enhancement_id).map_err(|e| e.to_string())?;
         if data.contains("This item cannot be registered on the Central Market.") {
             return Err(String::from("The item can't be found on the market."))
         }
-        return Ok(ItemBuySellInfo::build_vec(data).unwrap());
+        ItemBuySellInfo::build_vec(data).map_err(|e| e.to_string())
     }
 
     fn build_vec(data: String) -> Result<Self, Error> {
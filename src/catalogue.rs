@@ -0,0 +1,248 @@
+//! # Catalogue
+//!
+//! Catalogue maps item ids to names, grades, categories and how they enhance, so results can be named
+//! without an authenticated category request.
+//!
+//! A catalogue is bundled with the crate in `data/catalogue.json`, and can be refreshed from category
+//! scans with [`Catalogue::import_category`] and saved to a file of its own.
+
+use crate::bdo_market_requests::CategoryGivenInfo;
+use crate::enhancement_level::MAX_SUB_KEY;
+use crate::market_category::MarketCategory;
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+const BUNDLED: &str = include_str!("../data/catalogue.json");
+
+/// Version of the catalogue file format.
+pub const CATALOGUE_VERSION: u32 = 1;
+
+/// Language used when a name isn't known in the one asked for.
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+/// How an item is enhanced, which decides the levels it can have.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EnhancementType {
+    /// Can't be enhanced.
+    None,
+    /// Base, then PRI to PEN, by combining with a base copy.
+    Accessory,
+    /// +1 to +15, then PRI to PEN.
+    Gear,
+    /// Fallen God armour, I to V.
+    FallenGod,
+    /// Not known yet.
+    Unknown,
+}
+
+impl EnhancementType {
+    /// Gets the highest level as a market sub key, 0 for items that can't be enhanced.
+    ///
+    /// Items of an unknown type could be at any level, up to the highest any item has.
+    ///
+    pub fn get_max_level(&self) -> u8 {
+        match self {
+            EnhancementType::Accessory | EnhancementType::FallenGod => 5,
+            EnhancementType::Gear | EnhancementType::Unknown => MAX_SUB_KEY,
+            EnhancementType::None => 0,
+        }
+    }
+}
+
+/// Everything known about a single item.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct CatalogueEntry {
+    pub item_id: u32,
+    /// Names by language code, such as `en` or `de`.
+    pub names: BTreeMap<String, String>,
+    pub grade: u8,
+    pub main_category: u16,
+    pub sub_category: u16,
+    pub enhancement_type: EnhancementType,
+    /// Highest level as a market sub key, 0 for items that can't be enhanced.
+    pub max_level: u8,
+}
+
+impl CatalogueEntry {
+    /// Gets the name in a language, falling back to English, then any language.
+    ///
+    pub fn get_name(&self, language: &str) -> Option<&str> {
+        self.names
+            .get(language)
+            .or_else(|| self.names.get(DEFAULT_LANGUAGE))
+            .or_else(|| self.names.values().next())
+            .map(|n| n.as_str())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CatalogueFile {
+    version: u32,
+    items: Vec<CatalogueEntry>,
+}
+
+/// A lookup of items by id.
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::bdo_market_requests::CategoryGivenInfo;
+/// use bdo_enhancement_profit_calculator::catalogue::{Catalogue, EnhancementType};
+///
+/// let mut catalogue = Catalogue::default();
+/// let scanned = vec![CategoryGivenInfo::new(11607, String::from("Ring of Crescent Guardian"), 3, 2000000, 10)];
+/// catalogue.import_category(20, 1, &scanned, "en");
+/// catalogue.import_category(20, 1, &[CategoryGivenInfo::new(11607, String::from("Ring des Halbmondwächters"), 3, 2000000, 10)], "de");
+///
+/// let entry = catalogue.get(11607).unwrap();
+/// assert_eq!(EnhancementType::Accessory, entry.enhancement_type);
+/// assert_eq!(Some("Ring des Halbmondwächters"), catalogue.get_name(11607, "de"));
+/// assert_eq!(Some("Ring of Crescent Guardian"), catalogue.get_name(11607, "fr"));
+///
+/// // Saved catalogues read back the same.
+/// let reloaded = Catalogue::parse(&catalogue.to_json().unwrap()).unwrap();
+/// assert_eq!(Some(entry), reloaded.get(11607));
+/// ```
+///
+#[derive(Default, Debug, Clone)]
+pub struct Catalogue {
+    entries: BTreeMap<u32, CatalogueEntry>,
+}
+
+impl Catalogue {
+    /// Gets the catalogue bundled with the crate.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bdo_enhancement_profit_calculator::catalogue::{Catalogue, EnhancementType};
+    ///
+    /// let catalogue = Catalogue::bundled();
+    /// let entry = catalogue.get(11629).unwrap();
+    /// assert_eq!(Some("Ring of Cadry Guardian"), entry.get_name("en"));
+    /// assert_eq!(3, entry.grade);
+    /// assert_eq!((20, 1), (entry.main_category, entry.sub_category));
+    /// assert_eq!(EnhancementType::Accessory, entry.enhancement_type);
+    ///
+    /// assert!(catalogue.get(0).is_none());
+    /// ```
+    ///
+    pub fn bundled() -> Self {
+        Catalogue::parse(BUNDLED).unwrap() // Checked by the doc tests, so it always parses.
+    }
+
+    pub fn parse(data: &str) -> Result<Self, String> {
+        let file: CatalogueFile = serde_json::from_str(data).map_err(|e| e.to_string())?;
+        if file.version != CATALOGUE_VERSION {
+            return Err(format!("Catalogue version {} isn't supported, expected {}.", file.version, CATALOGUE_VERSION));
+        }
+        Ok(Catalogue {
+            entries: file.items.into_iter().map(|e| (e.item_id, e)).collect(),
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        Catalogue::parse(&data).map_err(|e| format!("Could not parse {}: {}", path.display(), e))
+    }
+
+    /// Gets the bundled catalogue, with a catalogue file on top of it if one is given.
+    ///
+    pub fn load_with_bundled(path: Option<&Path>) -> Result<Self, String> {
+        let mut catalogue = Catalogue::bundled();
+        if let Some(path) = path {
+            catalogue.merge(Catalogue::load(path)?);
+        }
+        Ok(catalogue)
+    }
+
+    pub fn to_json(&self) -> Result<String, String> {
+        let file = CatalogueFile {
+            version: CATALOGUE_VERSION,
+            items: self.entries.values().cloned().collect(),
+        };
+        serde_json::to_string_pretty(&file).map_err(|e| e.to_string())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_json()? + "\n").map_err(|e| format!("Could not write {}: {}", path.display(), e))
+    }
+
+    /// Adds every entry of another catalogue, with its entries replacing these, and names merged.
+    ///
+    pub fn merge(&mut self, other: Catalogue) {
+        for (id, mut entry) in other.entries {
+            if let Some(existing) = self.entries.get(&id) {
+                let mut names = existing.names.clone();
+                names.append(&mut entry.names);
+                entry.names = names;
+            }
+            self.entries.insert(id, entry);
+        }
+    }
+
+    /// Adds or updates every item from a category scan, naming them in `language`.
+    ///
+    /// Returns the number of items that weren't in the catalogue before.
+    ///
+    pub fn import_category(&mut self, main_category: u16, sub_category: u16, items: &[CategoryGivenInfo], language: &str) -> usize {
+        let enhancement_type = MarketCategory::find(main_category, sub_category)
+            .map(|c| c.get_enhancement_type())
+            .unwrap_or(EnhancementType::Unknown);
+        let max_level = enhancement_type.get_max_level();
+        let mut added = 0;
+        for item in items {
+            let entry = self.entries.entry(item.get_item_id()).or_insert_with(|| {
+                added += 1;
+                CatalogueEntry {
+                    item_id: item.get_item_id(),
+                    names: BTreeMap::new(),
+                    grade: item.get_item_grade(),
+                    main_category,
+                    sub_category,
+                    enhancement_type,
+                    max_level,
+                }
+            });
+            entry.names.insert(language.to_owned(), item.get_item_name().to_owned());
+            entry.grade = item.get_item_grade();
+            entry.main_category = main_category;
+            entry.sub_category = sub_category;
+        }
+        added
+    }
+
+    pub fn get(&self, item_id: u32) -> Option<&CatalogueEntry> {
+        self.entries.get(&item_id)
+    }
+
+    pub fn get_name(&self, item_id: u32, language: &str) -> Option<&str> {
+        self.get(item_id).and_then(|e| e.get_name(language))
+    }
+
+    /// Finds items whose name in any language contains `name`, ignoring case.
+    ///
+    pub fn find_by_name(&self, name: &str) -> Vec<&CatalogueEntry> {
+        let name = name.to_lowercase();
+        self.entries
+            .values()
+            .filter(|e| e.names.values().any(|n| n.to_lowercase().contains(&name)))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
@@ -0,0 +1,371 @@
+use bdo_enhancement_profit_calculator::bdo_market_requests::get_bdo_urls::REGIONS;
+use bdo_enhancement_profit_calculator::enhancement_level::parse_accessory_level;
+use bdo_enhancement_profit_calculator::report::OutputFormat;
+
+use clap::builder::PossibleValuesParser;
+use clap::{Args, Parser, Subcommand};
+
+use std::path::PathBuf;
+
+/// Calculates profits from enhancing in Black Desert.
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Config file with scan profiles, later files override earlier ones (can be repeated)
+    #[arg(short, long, global = true)]
+    pub config: Vec<PathBuf>,
+    /// Profile from the config files to use
+    #[arg(short, long, global = true, requires = "config")]
+    pub profile: Option<String>,
+    /// Item catalogue to name items with, on top of the bundled one
+    #[arg(long, global = true)]
+    pub catalogue: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Scan market categories for profitable enhancements
+    Scan {
+        #[command(flatten)]
+        market: MarketArgs,
+        #[command(flatten)]
+        scan: ScanArgs,
+        /// Write the profitable results as table, json, ndjson or csv, instead of printing each one
+        #[arg(short, long)]
+        format: Option<OutputFormat>,
+        /// File to write the results to, instead of stdout
+        #[arg(short, long, requires = "format")]
+        output: Option<PathBuf>,
+    },
+    /// Scan the same categories in several regions at once, and compare prices and profits side by side
+    Compare {
+        /// Regions to compare, comma separated, if the profile doesn't set them
+        #[arg(long, num_args = 1.., value_delimiter = ',', ignore_case = true, value_parser = PossibleValuesParser::new(REGIONS))]
+        regions: Option<Vec<String>>,
+        #[command(flatten)]
+        scan: ScanArgs,
+        /// Write the comparison as table, json, ndjson or csv
+        #[arg(short, long, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+        /// File to write the comparison to, instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Browse scan results in an interactive terminal UI
+    #[cfg(feature = "tui")]
+    Tui {
+        #[command(flatten)]
+        market: MarketArgs,
+        #[command(flatten)]
+        scan: ScanArgs,
+    },
+    /// Show the prices and enhancement profit of a single item
+    Item {
+        /// Item id
+        id: u32,
+        #[command(flatten)]
+        market: MarketArgs,
+        #[command(flatten)]
+        route: RouteArgs,
+        /// Base accessories traded a day, to estimate how long pre-ordered bases take to fill
+        #[arg(long)]
+        base_volume: Option<f64>,
+    },
+    /// Simulate enhancing a single item many times
+    Simulate {
+        /// Item id
+        id: u32,
+        #[command(flatten)]
+        market: MarketArgs,
+        #[command(flatten)]
+        route: RouteArgs,
+        /// Number of simulated enhancements
+        #[arg(long, default_value_t = 10000)]
+        runs: u32,
+    },
+    /// Work out when to sell and when to keep tapping an item, compared with each target level
+    Policy {
+        /// Item id
+        id: u32,
+        #[command(flatten)]
+        market: MarketArgs,
+        #[command(flatten)]
+        route: RouteArgs,
+        /// Highest stack that failures build up to
+        #[arg(long, default_value_t = 200)]
+        max_stack: u16,
+    },
+    /// List the profiles in the config files
+    Profiles,
+    /// List the market categories that can be scanned by name
+    Categories,
+    /// Show the daily price history of an item
+    History {
+        /// Item id
+        id: u32,
+        #[command(flatten)]
+        market: MarketArgs,
+        /// Enhancement level, such as 0, TET or +15, named for the item's type in the catalogue
+        #[arg(long, default_value = "0")]
+        level: String,
+    },
+    /// Show items waiting to be registered on the market
+    Queue {
+        #[command(flatten)]
+        market: MarketArgs,
+    },
+    /// Rank the items moving on the market, from the hot list, price histories and the registration queue
+    Movers {
+        #[command(flatten)]
+        market: MarketArgs,
+        /// Days of history to compare the latest price against
+        #[arg(long, default_value_t = 7)]
+        days: usize,
+        /// Most movers to show
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Show the current price, stock and trades of many items, in as few requests as possible
+    Prices {
+        /// Item ids, comma or space separated
+        #[arg(required = true, num_args = 1.., value_delimiter = ',')]
+        ids: Vec<u32>,
+        #[command(flatten)]
+        market: MarketArgs,
+    },
+    /// Look up or refresh the item catalogue
+    Catalogue {
+        #[command(subcommand)]
+        action: CatalogueCommand,
+    },
+    /// Scan, then split a silver budget across the profitable enhancements
+    Plan {
+        #[command(flatten)]
+        market: MarketArgs,
+        #[command(flatten)]
+        scan: ScanArgs,
+        /// Silver available to spend
+        #[arg(long)]
+        budget: u64,
+        /// Standard deviations of cost taken off each accessory's profit
+        #[arg(long, default_value_t = 0.0)]
+        risk_aversion: f64,
+        /// Share of what's listed at the target level to make of a single accessory, at least 1
+        #[arg(long, default_value_t = 0.25)]
+        liquidity_share: f64,
+        /// Most of a single accessory to make, whatever is listed
+        #[arg(long)]
+        max_per_item: Option<u32>,
+    },
+    /// Re-scan on an interval, and print an alert whenever a rule matches
+    Watch {
+        #[command(flatten)]
+        market: MarketArgs,
+        #[command(flatten)]
+        scan: ScanArgs,
+        /// Seconds between scans
+        #[arg(long, default_value_t = 600)]
+        interval: u64,
+        /// Seconds before the same alert can fire again for an item
+        #[arg(long, default_value_t = 3600)]
+        cooldown: u64,
+        /// Alert when profit after tax is above this
+        #[arg(long)]
+        alert_profit: Option<i64>,
+        /// Least return on the make cost for a profit alert, as a fraction
+        #[arg(long, default_value_t = 0.0, requires = "alert_profit")]
+        alert_roi: f64,
+        /// Alert when a scanned item is newly waiting to be registered
+        #[arg(long)]
+        alert_queue: bool,
+        /// Alert when a base price is this many percent below its average
+        #[arg(long)]
+        alert_drop: Option<f64>,
+        /// Days to average over for price drop alerts
+        #[arg(long, default_value_t = 7, requires = "alert_drop")]
+        drop_days: usize,
+        #[command(flatten)]
+        notify: NotifyArgs,
+    },
+    /// Record a snapshot of the scanned categories, their bid ladders and the registration queue to SQLite
+    #[cfg(feature = "sqlite")]
+    Record {
+        #[command(flatten)]
+        market: MarketArgs,
+        #[command(flatten)]
+        scan: ScanArgs,
+        /// Database file, created if it doesn't exist
+        #[arg(long)]
+        db: PathBuf,
+        /// Keep recording, every this many seconds
+        #[arg(long)]
+        interval: Option<u64>,
+    },
+    /// Replay a strategy over days recorded with `record`
+    #[cfg(feature = "sqlite")]
+    Backtest {
+        #[command(flatten)]
+        market: MarketArgs,
+        #[command(flatten)]
+        scan: ScanArgs,
+        /// Database file written by `record`
+        #[arg(long)]
+        db: PathBuf,
+        /// Number of days to replay, ending at the latest recording
+        #[arg(long, default_value_t = 30)]
+        days: u64,
+        /// Silver available on the first day
+        #[arg(long)]
+        budget: u64,
+        /// Days for a finished accessory to sell
+        #[arg(long, default_value_t = 1)]
+        sell_delay: u64,
+        /// Most enhancements started on a single day
+        #[arg(long, default_value_t = 3)]
+        max_trades_per_day: usize,
+        /// Draw each enhancement's cost from the simulator, instead of using the expected cost
+        #[arg(long)]
+        simulate: bool,
+        /// Seed for the simulator, the same seed replays the same trades
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        /// Write the equity curve as CSV to this file
+        #[arg(long)]
+        equity: Option<PathBuf>,
+        /// Write every trade as CSV to this file
+        #[arg(long)]
+        trades: Option<PathBuf>,
+    },
+    /// Serve market data and profit calculations as JSON over HTTP
+    #[cfg(feature = "server")]
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        bind: String,
+        /// Number of requests handled at once
+        #[arg(long, default_value_t = 4)]
+        threads: usize,
+        /// Seconds to keep market responses for
+        #[arg(long, default_value_t = 300)]
+        cache_ttl: u64,
+        /// Most runs a single simulation request can ask for
+        #[arg(long, default_value_t = 100000)]
+        max_runs: u32,
+        /// Default region for requests that don't give one
+        #[command(flatten)]
+        market: MarketArgs,
+        /// Defaults for requests that don't give them
+        #[command(flatten)]
+        route: RouteArgs,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CatalogueCommand {
+    /// Add every item in the scanned categories to a catalogue file, creating it if needed
+    Import {
+        #[command(flatten)]
+        market: MarketArgs,
+        /// Categories to import by name or as main/sub numbers, comma separated
+        #[arg(long, value_delimiter = ',', default_value = "accessories")]
+        categories: Vec<String>,
+        /// Language the region's market names items in
+        #[arg(long, default_value = "en")]
+        language: String,
+        /// Catalogue file to update
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// Show the items with an id, or whose name contains some text
+    Lookup {
+        query: String,
+    },
+}
+
+#[derive(Args)]
+pub struct MarketArgs {
+    /// Market region, if the profile doesn't set one
+    #[arg(short, long, ignore_case = true, value_parser = PossibleValuesParser::new(REGIONS))]
+    pub region: Option<String>,
+}
+
+#[derive(Args)]
+pub struct NotifyArgs {
+    /// Post each alert as JSON to this url (can be repeated)
+    #[arg(long)]
+    pub webhook: Vec<String>,
+    /// Post each alert as a message to this Discord webhook url (can be repeated)
+    #[arg(long)]
+    pub discord: Vec<String>,
+    /// Post each alert as a message to this Slack webhook url (can be repeated)
+    #[arg(long)]
+    pub slack: Vec<String>,
+    /// Show each alert as a desktop notification, with notify-send
+    #[arg(long)]
+    pub desktop: bool,
+    /// Append each alert as a line of JSON to this file
+    #[arg(long)]
+    pub log: Option<PathBuf>,
+    /// Message for stdout, desktop notifications and chat webhooks, with fields such as {name} and {profit_taxed} [default: "[{fired_at}] {rule}: {message}"]
+    #[arg(long)]
+    pub template: Option<String>,
+    /// Don't print alerts to stdout
+    #[arg(long)]
+    pub quiet: bool,
+}
+
+// Flags without a default are filled in from the profile, or the scan defaults.
+
+#[derive(Args)]
+pub struct RouteArgs {
+    /// Failstacks for each level, comma separated [default: 20,40,44,110]
+    #[arg(long, value_delimiter = ',')]
+    pub stacks: Option<Vec<u16>>,
+    /// Level to start from, such as base, PRI or 1 [default: base]
+    #[arg(long, value_parser = parse_accessory_level)]
+    pub start_level: Option<u8>,
+    /// Levels to enhance to, comma separated [default: TET]
+    #[arg(long, value_delimiter = ',', value_parser = parse_accessory_level)]
+    pub target_levels: Option<Vec<u8>>,
+    /// Fraction of the sale price kept after market tax [default: 0.8515]
+    #[arg(long)]
+    pub tax: Option<f64>,
+}
+
+#[derive(Args)]
+pub struct ScanArgs {
+    /// Categories to scan by name, such as ring or accessories, comma separated [default: accessories]
+    #[arg(long, value_delimiter = ',', conflicts_with_all = ["main_category", "sub_categories"])]
+    pub categories: Option<Vec<String>>,
+    /// Main market category [default: 20]
+    #[arg(long)]
+    pub main_category: Option<u16>,
+    /// Sub categories to scan, comma separated [default: 1,2,3,4]
+    #[arg(long, value_delimiter = ',')]
+    pub sub_categories: Option<Vec<u16>>,
+    /// Item grade to keep [default: 3]
+    #[arg(long)]
+    pub grade: Option<u8>,
+    /// Lowest base price to keep [default: 1000000]
+    #[arg(long)]
+    pub min_price: Option<u64>,
+    /// Highest base price to keep
+    #[arg(long)]
+    pub max_price: Option<u64>,
+    /// Only keep items matching this pattern, `*` matches anything (can be repeated)
+    #[arg(long)]
+    pub include: Option<Vec<String>>,
+    /// Skip items matching this pattern (can be repeated) [default: Manos]
+    #[arg(long)]
+    pub exclude: Option<Vec<String>>,
+    /// Lowest profit (before tax) to report [default: 50000000]
+    #[arg(long)]
+    pub min_profit: Option<i64>,
+    /// Lowest profit after tax to report
+    #[arg(long)]
+    pub min_profit_taxed: Option<i64>,
+    #[command(flatten)]
+    pub route: RouteArgs,
+}
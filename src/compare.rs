@@ -0,0 +1,201 @@
+//! # Compare
+//!
+//! Compare scans the same accessories in several regions at once, and lines up their prices and profits
+//! side by side, to spot items priced differently between regions and decide where to enhance.
+//!
+//! Every region is scanned on its own thread with its own [`MarketClient`], so each keeps its own login
+//! and rate limit.
+
+use crate::accessories::AccMarketData;
+use crate::bdo_market_requests::market_client::MarketClient;
+use crate::bdo_market_requests::SpecificItemInfo;
+use crate::catalogue::EnhancementType;
+use crate::report::ReportRow;
+use crate::scan::{get_scan_candidates, scan_item_market, ScanOptions};
+
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::thread;
+
+/// An item's market in a single region.
+///
+#[derive(Serialize, Debug, Clone)]
+pub struct RegionPrices {
+    pub region: String,
+    pub base_price: u64,
+    /// Base accessories listed on the market.
+    pub stock: u64,
+    /// Market price of every level by sub key, `None` for levels that couldn't be fetched.
+    pub level_prices: Vec<Option<u64>>,
+    /// Most profitable of the scanned routes after tax, `None` if the item's market couldn't be fetched.
+    pub best_route: Option<ReportRow>,
+}
+
+/// An item as it was scanned in one region.
+///
+#[derive(Debug, Clone)]
+pub struct RegionItem {
+    pub item_id: u32,
+    pub name: String,
+    pub prices: RegionPrices,
+}
+
+/// An item in every region it was found in.
+///
+#[derive(Serialize, Debug, Clone)]
+pub struct ComparisonRow {
+    pub item_id: u32,
+    pub name: String,
+    /// In the order the regions were scanned.
+    pub regions: Vec<RegionPrices>,
+    /// Region with the most profitable route after tax.
+    pub best_region: Option<String>,
+    /// Difference between the highest and lowest base price, in percent of the lowest.
+    pub price_spread_percent: Option<f64>,
+    /// Difference between the best and worst region's most profitable route after tax.
+    pub profit_gap: i64,
+}
+
+/// Gets the market price of every accessory level, by sub key.
+///
+fn get_level_prices(levels: &[SpecificItemInfo]) -> Vec<Option<u64>> {
+    (0..=EnhancementType::Accessory.get_max_level())
+        .map(|level| SpecificItemInfo::find_level(levels, level).map(|l| l.get_base_price()))
+        .collect()
+}
+
+/// Scans every candidate accessory in a region, keeping every item rather than only the profitable ones.
+///
+pub fn scan_region(client: &dyn MarketClient, options: &ScanOptions) -> Result<Vec<RegionItem>, String> {
+    options.validate()?;
+
+    let mut items = Vec::new();
+    for item in get_scan_candidates(client, options)? {
+        let mut market = AccMarketData::from_post(client, item.get_item_id()).ok();
+        let results = scan_item_market(&item, market.as_mut(), options);
+
+        let level_prices = get_level_prices(market.as_ref().map(|m| m.get_levels()).unwrap_or(&[]));
+        let best_route = market
+            .as_ref()
+            .and(results.iter().max_by_key(|r| r.get_profit_details().get_profit_taxed()))
+            .map(ReportRow::from);
+
+        items.push(RegionItem {
+            item_id: item.get_item_id(),
+            name: item.get_item_name().to_owned(),
+            prices: RegionPrices {
+                region: client.get_region().to_owned(),
+                base_price: item.get_base_price(),
+                stock: item.get_stock(),
+                level_prices,
+                best_route,
+            },
+        });
+    }
+    Ok(items)
+}
+
+/// Scans every region at the same time, with one thread per client.
+///
+/// Returns each region's scan in the order the clients were given, so one failing region doesn't lose the others.
+///
+pub fn scan_regions(clients: &[Box<dyn MarketClient>], options: &ScanOptions) -> Vec<(String, Result<Vec<RegionItem>, String>)> {
+    thread::scope(|scope| {
+        let handles: Vec<_> = clients
+            .iter()
+            .map(|client| scope.spawn(move || scan_region(client.as_ref(), options)))
+            .collect();
+
+        clients
+            .iter()
+            .zip(handles)
+            .map(|(client, handle)| {
+                let result = handle.join().unwrap_or_else(|_| Err(String::from("The scan stopped unexpectedly.")));
+                (client.get_region().to_owned(), result)
+            })
+            .collect()
+    })
+}
+
+/// Groups scanned items by id, largest profit gap between regions first.
+///
+/// Items are named as they were in the first region they were found in.
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::compare::{compare_items, RegionItem, RegionPrices};
+/// use bdo_enhancement_profit_calculator::report::ReportRow;
+///
+/// let item = |region: &str, base_price: u64, profit_taxed: i64| RegionItem {
+///     item_id: 11607,
+///     name: String::from("Ring"),
+///     prices: RegionPrices {
+///         region: region.to_owned(),
+///         base_price,
+///         stock: 5,
+///         level_prices: vec![Some(base_price), None, None, None, Some(900000), None],
+///         best_route: Some(ReportRow {
+///             item_id: 11607,
+///             name: String::from("Ring"),
+///             start_level: 0,
+///             end_level: 4,
+///             stacks: vec![20, 40, 44, 110],
+///             base_price,
+///             make_cost: 0,
+///             actual_value: 0,
+///             profit: profit_taxed,
+///             profit_taxed,
+///             stock: 5,
+///             sale_chance: None,
+///             cap_state: None,
+///         }),
+///     },
+/// };
+///
+/// let rows = compare_items(vec![item("eu", 1000, 20000), item("na", 1250, 50000)]);
+///
+/// assert_eq!(1, rows.len());
+/// assert_eq!(Some(String::from("na")), rows[0].best_region);
+/// assert_eq!(30000, rows[0].profit_gap);
+/// assert_eq!(Some(25.0), rows[0].price_spread_percent);
+/// assert_eq!("eu", rows[0].regions[0].region);
+/// ```
+///
+pub fn compare_items(items: Vec<RegionItem>) -> Vec<ComparisonRow> {
+    let mut rows: Vec<ComparisonRow> = Vec::new();
+    for item in items {
+        match rows.iter_mut().find(|r| r.item_id == item.item_id) {
+            Some(row) => row.regions.push(item.prices),
+            None => rows.push(ComparisonRow {
+                item_id: item.item_id,
+                name: item.name,
+                regions: vec![item.prices],
+                best_region: None,
+                price_spread_percent: None,
+                profit_gap: 0,
+            }),
+        }
+    }
+
+    for row in &mut rows {
+        let profits: Vec<(&str, i64)> = row
+            .regions
+            .iter()
+            .filter_map(|r| r.best_route.as_ref().map(|b| (r.region.as_str(), b.profit_taxed)))
+            .collect();
+        row.best_region = profits.iter().max_by_key(|(_, profit)| *profit).map(|(region, _)| (*region).to_owned());
+        if let (Some(max), Some(min)) = (profits.iter().map(|p| p.1).max(), profits.iter().map(|p| p.1).min()) {
+            row.profit_gap = max - min;
+        }
+
+        let prices: Vec<u64> = row.regions.iter().map(|r| r.base_price).filter(|p| *p > 0).collect();
+        if prices.len() > 1 {
+            let (max, min) = (*prices.iter().max().unwrap(), *prices.iter().min().unwrap());
+            row.price_spread_percent = Some((max - min) as f64 / min as f64 * 100.0);
+        }
+    }
+
+    rows.sort_by_key(|r| Reverse(r.profit_gap));
+    rows
+}
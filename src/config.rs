@@ -0,0 +1,470 @@
+//! # Config
+//!
+//! Config loads named scan profiles from TOML files.
+//!
+//! ```toml
+//! [profiles.daily]
+//! region = "eu"
+//! tax = { fame = 5000, value_pack = true, merch_ring = false }
+//! categories = ["ring", "necklace", { main = 20, sub = [3, 4] }]
+//! grade = 3
+//! min_price = 1000000
+//! exclude = ["Manos"]
+//! stacks = [20, 40, 44, 110]
+//! target_levels = ["TRI", "TET"]
+//! min_profit = 50000000
+//!
+//! [profiles.daily-pen]
+//! inherits = "daily"
+//! stacks = [20, 40, 44, 110, 250]
+//! target_levels = ["PEN"]
+//!
+//! [profiles.compare]
+//! inherits = "daily"
+//! regions = ["eu", "na"]
+//!
+//! [accounts.na]
+//! trade_auth = "..."
+//! request_verification_token = "..."
+//! query_request_verification_token = "..."
+//! requests_per_second = 2.0
+//! ```
+//!
+//! Anything a profile leaves out comes from the profile it inherits, and then the [`ScanOptions`] defaults.
+//!
+//! Accounts log in to a region's market instead of the login the crate was built with, and can limit how fast
+//! it's requested. They're best kept in a personal config file, on top of a shared one.
+
+use crate::bdo_market_requests::bdo_post_requests::Credentials;
+use crate::bdo_market_requests::get_bdo_urls::REGIONS;
+use crate::enhancement_level::parse_accessory_level;
+use crate::general_calcs::market_calcs::get_market_tax;
+use crate::market_category::MarketCategory;
+use crate::scan::ScanOptions;
+
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Family fame and buffs, used to work out the market tax.
+///
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TaxProfile {
+    #[serde(default)]
+    pub fame: u16,
+    #[serde(default)]
+    pub value_pack: bool,
+    #[serde(default)]
+    pub merch_ring: bool,
+}
+
+impl TaxProfile {
+    pub fn get_tax_rate(&self) -> f64 {
+        get_market_tax(self.fame, self.value_pack, self.merch_ring)
+    }
+}
+
+/// Categories to scan, by name (see [`MarketCategory::parse`]) or as a main category and its sub categories.
+///
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum CategorySelection {
+    Name(String),
+    Numbers(CategoryNumbers),
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CategoryNumbers {
+    pub main: u16,
+    pub sub: Vec<u16>,
+}
+
+impl CategorySelection {
+    /// Gets the pairs of main and sub category this stands for.
+    ///
+    pub fn get_categories(&self) -> Result<Vec<(u16, u16)>, String> {
+        match self {
+            CategorySelection::Name(name) => MarketCategory::parse(name),
+            CategorySelection::Numbers(numbers) => {
+                if numbers.sub.is_empty() {
+                    return Err(format!("Category {} has no sub categories.", numbers.main));
+                }
+                Ok(numbers.sub.iter().map(|sub| (numbers.main, *sub)).collect())
+            }
+        }
+    }
+}
+
+/// An accessory level, written as a sub key or a name such as `"TET"`.
+///
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LevelValue {
+    SubKey(u8),
+    Name(String),
+}
+
+impl LevelValue {
+    fn get_sub_key(&self) -> Result<u8, String> {
+        match self {
+            LevelValue::SubKey(sub_key) => Ok(*sub_key),
+            LevelValue::Name(name) => parse_accessory_level(name),
+        }
+    }
+}
+
+fn deserialize_level<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u8>, D::Error> {
+    let level: Option<LevelValue> = Option::deserialize(deserializer)?;
+    level.map(|l| l.get_sub_key()).transpose().map_err(de::Error::custom)
+}
+
+fn deserialize_levels<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error> {
+    let levels: Option<Vec<LevelValue>> = Option::deserialize(deserializer)?;
+    levels
+        .map(|levels| levels.iter().map(|l| l.get_sub_key()).collect())
+        .transpose()
+        .map_err(de::Error::custom)
+}
+
+/// A region's market login and request limit, as written in the config file.
+///
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Account {
+    pub trade_auth: Option<String>,
+    pub request_verification_token: Option<String>,
+    pub query_request_verification_token: Option<String>,
+    pub requests_per_second: Option<f64>,
+}
+
+impl Account {
+    /// Overwrites every field that is set in `other`.
+    ///
+    fn merge(&mut self, other: &Account) {
+        macro_rules! take {
+            ($($field:ident),*) => {
+                $(if other.$field.is_some() {
+                    self.$field = other.$field.clone();
+                })*
+            };
+        }
+        take!(trade_auth, request_verification_token, query_request_verification_token, requests_per_second);
+    }
+
+    /// Gets the login, if the account has one.
+    ///
+    pub fn get_credentials(&self) -> Option<Credentials> {
+        match (&self.trade_auth, &self.request_verification_token, &self.query_request_verification_token) {
+            (Some(trade_auth), Some(cookie), Some(query)) => Some(Credentials::new(trade_auth, cookie, query)),
+            _ => None,
+        }
+    }
+
+    pub fn get_requests_per_second(&self) -> Option<f64> {
+        self.requests_per_second
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let set = [&self.trade_auth, &self.request_verification_token, &self.query_request_verification_token]
+            .iter()
+            .filter(|f| f.is_some())
+            .count();
+        if set != 0 && set != 3 {
+            return Err(String::from(
+                "A login needs all of trade_auth, request_verification_token and query_request_verification_token.",
+            ));
+        }
+        if let Some(requests_per_second) = self.requests_per_second {
+            if !(requests_per_second > 0.0 && requests_per_second.is_finite()) {
+                return Err(format!("requests_per_second must be above 0, got {}.", requests_per_second));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn validate_region(region: &str) -> Result<(), String> {
+    if !REGIONS.contains(&region.to_lowercase().as_str()) {
+        return Err(format!("Unknown region \"{}\", expected one of: {}.", region, REGIONS.join(", ")));
+    }
+    Ok(())
+}
+
+/// A single named profile, as written in the config file.
+///
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub inherits: Option<String>,
+    pub region: Option<String>,
+    /// Regions to compare, see [`compare`](crate::compare).
+    pub regions: Option<Vec<String>>,
+    pub tax: Option<TaxProfile>,
+    pub tax_rate: Option<f64>,
+    pub categories: Option<Vec<CategorySelection>>,
+    pub grade: Option<u8>,
+    pub min_price: Option<u64>,
+    pub max_price: Option<u64>,
+    pub include: Option<Vec<String>>,
+    pub exclude: Option<Vec<String>>,
+    pub stacks: Option<Vec<u16>>,
+    #[serde(default, deserialize_with = "deserialize_level")]
+    pub start_level: Option<u8>,
+    #[serde(default, deserialize_with = "deserialize_levels")]
+    pub target_levels: Option<Vec<u8>>,
+    pub min_profit: Option<i64>,
+    pub min_profit_taxed: Option<i64>,
+}
+
+impl Profile {
+    /// Overwrites every field that is set in `other`.
+    ///
+    fn merge(&mut self, other: &Profile) {
+        macro_rules! take {
+            ($($field:ident),*) => {
+                $(if other.$field.is_some() {
+                    self.$field = other.$field.clone();
+                })*
+            };
+        }
+        take!(inherits, region, regions, categories, grade, min_price, max_price, include, exclude, stacks, start_level, target_levels, min_profit, min_profit_taxed);
+
+        // Either way of setting the tax replaces the other.
+        if other.tax.is_some() || other.tax_rate.is_some() {
+            self.tax = other.tax.clone();
+            self.tax_rate = other.tax_rate;
+        }
+    }
+
+    pub fn get_region(&self) -> Option<&str> {
+        self.region.as_deref()
+    }
+
+    pub fn get_regions(&self) -> Option<&[String]> {
+        self.regions.as_deref()
+    }
+
+    /// Gets the tax rate, if the profile sets one.
+    ///
+    pub fn get_tax_rate(&self) -> Option<f64> {
+        match (&self.tax, self.tax_rate) {
+            (Some(tax), _) => Some(tax.get_tax_rate()),
+            (None, rate) => rate,
+        }
+    }
+
+    /// Fills in scan options from the profile, using the defaults for anything not set.
+    ///
+    pub fn get_scan_options(&self) -> ScanOptions {
+        let mut options = ScanOptions::default();
+
+        if let Some(categories) = &self.categories {
+            // Selections that don't resolve are reported by validate.
+            options.categories = categories
+                .iter()
+                .flat_map(|c| c.get_categories().unwrap_or_default())
+                .collect();
+        }
+        if let Some(grade) = self.grade {
+            options.grade = grade;
+        }
+        if let Some(min_price) = self.min_price {
+            options.min_price = min_price;
+        }
+        if let Some(max_price) = self.max_price {
+            options.max_price = max_price;
+        }
+        if let Some(include) = &self.include {
+            options.include = include.clone();
+        }
+        if let Some(exclude) = &self.exclude {
+            options.exclude = exclude.clone();
+        }
+        if let Some(stacks) = &self.stacks {
+            options.stacks = stacks.clone();
+        }
+        if let Some(start_level) = self.start_level {
+            options.start_level = start_level;
+        }
+        if let Some(target_levels) = &self.target_levels {
+            options.target_levels = target_levels.clone();
+        }
+        if let Some(tax_rate) = self.get_tax_rate() {
+            options.tax_rate = tax_rate;
+        }
+        if let Some(min_profit) = self.min_profit {
+            options.min_profit = min_profit;
+        }
+        if self.min_profit_taxed.is_some() {
+            options.min_profit_taxed = self.min_profit_taxed;
+        }
+
+        options
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.tax.is_some() && self.tax_rate.is_some() {
+            return Err(String::from("Only one of tax and tax_rate can be set."));
+        }
+        if let Some(region) = &self.region {
+            validate_region(region)?;
+        }
+        for region in self.regions.iter().flatten() {
+            validate_region(region)?;
+        }
+        if let Some(categories) = &self.categories {
+            for category in categories {
+                category.get_categories()?;
+            }
+        }
+        self.get_scan_options().validate()
+    }
+}
+
+/// Contains every profile from one or more config files.
+///
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    profiles: HashMap<String, Profile>,
+    /// Accounts by region.
+    #[serde(default)]
+    accounts: HashMap<String, Account>,
+}
+
+impl Config {
+    /// Parses and validates a config.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bdo_enhancement_profit_calculator::config::Config;
+    ///
+    /// let config = Config::parse(r#"
+    ///     [profiles.team]
+    ///     region = "eu"
+    ///     stacks = [20, 40, 44, 110]
+    ///
+    ///     [profiles.me]
+    ///     inherits = "team"
+    ///     target_levels = ["TRI"]
+    /// "#).unwrap();
+    ///
+    /// let profile = config.get_profile("me").unwrap();
+    /// assert_eq!(Some("eu"), profile.get_region());
+    /// assert_eq!(vec![3], profile.get_scan_options().target_levels);
+    ///
+    /// // Accounts are looked up by region, in any case.
+    /// let config = Config::parse(r#"
+    ///     [accounts.NA]
+    ///     requests_per_second = 2.0
+    /// "#).unwrap();
+    /// assert_eq!(Some(2.0), config.get_account("na").unwrap().get_requests_per_second());
+    /// assert!(config.get_account("na").unwrap().get_credentials().is_none());
+    ///
+    /// // Profiles and accounts are checked when loaded.
+    /// assert!(Config::parse("[accounts.eu]\ntrade_auth = \"x\"").is_err());
+    /// assert!(Config::parse("[profiles.bad]\ntarget_levels = [9]").is_err());
+    /// assert!(Config::parse("[profiles.bad]\ntarget_levels = [\"+15\"]").is_err());
+    /// assert!(Config::parse("[profiles.bad]\ncategories = [\"rings\"]").is_err());
+    /// ```
+    ///
+    pub fn parse(data: &str) -> Result<Self, String> {
+        let config: Config = toml::from_str(data).map_err(|e| e.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Loads and validates a config file.
+    ///
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        let config: Config = toml::from_str(&data).map_err(|e| format!("Could not parse {}: {}", path.display(), e))?;
+        config.validate().map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(config)
+    }
+
+    /// Loads several config files, with profiles in later files overriding the same profile in earlier ones.
+    ///
+    /// This allows a shared config to be combined with personal overrides.
+    ///
+    pub fn load_all(paths: &[&Path]) -> Result<Self, String> {
+        let mut config = Config::default();
+        for path in paths {
+            let data = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+            let other: Config = toml::from_str(&data).map_err(|e| format!("Could not parse {}: {}", path.display(), e))?;
+            config.merge(other);
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Merges another config into this one, field by field for profiles in both.
+    ///
+    pub fn merge(&mut self, other: Config) {
+        for (name, profile) in other.profiles {
+            self.profiles.entry(name).or_default().merge(&profile);
+        }
+        for (region, account) in other.accounts {
+            self.accounts.entry(region.to_lowercase()).or_default().merge(&account);
+        }
+    }
+
+    pub fn get_profile_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.profiles.keys().map(|k| k.as_str()).collect();
+        names.sort();
+        names
+    }
+
+    /// Gets the account for a region, if the config has one.
+    ///
+    pub fn get_account(&self, region: &str) -> Option<&Account> {
+        self.accounts.iter().find(|(r, _)| r.eq_ignore_ascii_case(region)).map(|(_, account)| account)
+    }
+
+    /// Gets a profile, with everything it inherits filled in.
+    ///
+    pub fn get_profile(&self, name: &str) -> Result<Profile, String> {
+        let mut chain = vec![name.to_owned()];
+        let mut current = self.find(name)?;
+        while let Some(parent) = &current.inherits {
+            if chain.contains(parent) {
+                chain.push(parent.clone());
+                return Err(format!("Profile \"{}\" inherits itself ({}).", name, chain.join(" -> ")));
+            }
+            chain.push(parent.clone());
+            current = self
+                .find(parent)
+                .map_err(|e| format!("Profile \"{}\" inherits a missing profile. {}", chain[chain.len() - 2], e))?;
+        }
+
+        let mut profile = Profile::default();
+        for link in chain.iter().rev() {
+            profile.merge(&self.profiles[link]);
+        }
+        profile.inherits = None;
+        Ok(profile)
+    }
+
+    fn find(&self, name: &str) -> Result<&Profile, String> {
+        self.profiles.get(name).ok_or_else(|| {
+            format!("There is no profile \"{}\" (found: {}).", name, self.get_profile_names().join(", "))
+        })
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for (region, account) in &self.accounts {
+            validate_region(region).and_then(|_| account.validate()).map_err(|e| format!("Account \"{}\": {}", region, e))?;
+        }
+        for name in self.get_profile_names() {
+            let profile = self.get_profile(name)?;
+            profile.validate().map_err(|e| format!("Profile \"{}\": {}", name, e))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,170 @@
+//! # Enhancement Level
+//!
+//! Enhancement Level names an item's level in the scheme its type uses, and converts it to and from the
+//! market's `subKey`.
+//!
+//! | Sub key | Accessory | Gear | Fallen God |
+//! |---|---|---|---|
+//! | 0 | Base | +0 | Base |
+//! | 1 - 5 | PRI - PEN | +1 - +5 | I - V |
+//! | 6 - 15 | | +6 - +15 | |
+//! | 16 - 20 | | PRI - PEN | |
+
+use crate::catalogue::EnhancementType;
+
+use std::fmt;
+
+/// Names of the five levels above +15 for gear, and above base for accessories.
+///
+pub const LEVEL_NAMES: [&str; 5] = ["PRI", "DUO", "TRI", "TET", "PEN"];
+
+const ROMAN_NUMERALS: [&str; 5] = ["I", "II", "III", "IV", "V"];
+
+/// Highest `subKey` any item has.
+///
+pub const MAX_SUB_KEY: u8 = 20;
+
+/// Sub key gear reaches PRI at.
+///
+const GEAR_PRI: u8 = 16;
+
+/// An enhancement level of a particular kind of item.
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::catalogue::EnhancementType;
+/// use bdo_enhancement_profit_calculator::enhancement_level::EnhancementLevel;
+///
+/// let tet = EnhancementLevel::parse("TET", EnhancementType::Accessory).unwrap();
+/// assert_eq!(4, tet.get_sub_key());
+/// assert_eq!("PEN", tet.next().unwrap().to_string());
+/// assert!(tet.next().unwrap().next().is_none());
+///
+/// // The same names stand for different sub keys on gear.
+/// assert_eq!(19, EnhancementLevel::parse("tet", EnhancementType::Gear).unwrap().get_sub_key());
+/// assert_eq!(19, EnhancementLevel::parse("IV", EnhancementType::Gear).unwrap().get_sub_key());
+/// assert_eq!(15, EnhancementLevel::parse("+15", EnhancementType::Gear).unwrap().get_sub_key());
+/// assert_eq!("+15", EnhancementLevel::new(16, EnhancementType::Gear).unwrap().prev().unwrap().to_string());
+///
+/// assert_eq!("IV", EnhancementLevel::new(4, EnhancementType::FallenGod).unwrap().to_string());
+/// assert_eq!("Base", EnhancementLevel::parse("0", EnhancementType::FallenGod).unwrap().to_string());
+///
+/// // Levels are checked against what the item can reach.
+/// assert!(EnhancementLevel::parse("+15", EnhancementType::Accessory).is_err());
+/// assert!(EnhancementLevel::new(6, EnhancementType::Accessory).is_err());
+/// ```
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EnhancementLevel {
+    enhancement_type: EnhancementType,
+    sub_key: u8,
+}
+
+impl EnhancementLevel {
+    /// Creates a level from a market sub key.
+    ///
+    pub fn new(sub_key: u8, enhancement_type: EnhancementType) -> Result<Self, String> {
+        if !EnhancementLevel::is_valid(sub_key, enhancement_type) {
+            return Err(format!(
+                "Level {} is above the highest level ({}) for {:?} items.",
+                sub_key,
+                enhancement_type.get_max_level(),
+                enhancement_type
+            ));
+        }
+        Ok(EnhancementLevel { enhancement_type, sub_key })
+    }
+
+    /// Creates the base level of an item, which every item has.
+    ///
+    pub fn base(enhancement_type: EnhancementType) -> Self {
+        EnhancementLevel { enhancement_type, sub_key: 0 }
+    }
+
+    /// Checks whether an item of a type can be at a sub key.
+    ///
+    pub fn is_valid(sub_key: u8, enhancement_type: EnhancementType) -> bool {
+        sub_key <= enhancement_type.get_max_level()
+    }
+
+    /// Parses a level as written by players, such as `TET`, `IV`, `+15`, `base` or a plain sub key.
+    ///
+    /// Level names and roman numerals count the last five levels, so `TET` and `IV` are the same level.
+    ///
+    pub fn parse(text: &str, enhancement_type: EnhancementType) -> Result<Self, String> {
+        let text = text.trim();
+        let upper = text.to_uppercase();
+        // Sub key of the level before the first named one.
+        let named_from = match enhancement_type {
+            EnhancementType::Accessory | EnhancementType::FallenGod => Some(0),
+            EnhancementType::Gear => Some(GEAR_PRI - 1),
+            EnhancementType::None | EnhancementType::Unknown => None,
+        };
+
+        let named = LEVEL_NAMES
+            .iter()
+            .position(|n| *n == upper)
+            .or_else(|| ROMAN_NUMERALS.iter().position(|n| *n == upper));
+        let sub_key = if upper == "BASE" {
+            Some(0)
+        } else if let Some(position) = named {
+            named_from.map(|from| from + position as u8 + 1)
+        } else if let Some(plus) = text.strip_prefix('+') {
+            // Gear goes from +15 to PRI, there is no +16.
+            match enhancement_type {
+                EnhancementType::Gear => plus.parse::<u8>().ok().filter(|level| *level < GEAR_PRI),
+                _ => None,
+            }
+        } else {
+            text.parse::<u8>().ok()
+        };
+
+        let sub_key = sub_key.ok_or_else(|| format!("Unknown enhancement level \"{}\" for {:?} items.", text, enhancement_type))?;
+        EnhancementLevel::new(sub_key, enhancement_type)
+    }
+
+    pub fn get_sub_key(&self) -> u8 {
+        self.sub_key
+    }
+
+    pub fn get_enhancement_type(&self) -> EnhancementType {
+        self.enhancement_type
+    }
+
+    /// Gets the level above this one, if the item can go higher.
+    ///
+    pub fn next(&self) -> Option<Self> {
+        EnhancementLevel::new(self.sub_key.checked_add(1)?, self.enhancement_type).ok()
+    }
+
+    /// Gets the level below this one, if this isn't the base.
+    ///
+    pub fn prev(&self) -> Option<Self> {
+        EnhancementLevel::new(self.sub_key.checked_sub(1)?, self.enhancement_type).ok()
+    }
+
+    pub fn is_max(&self) -> bool {
+        self.sub_key == self.enhancement_type.get_max_level()
+    }
+}
+
+/// Parses an accessory level, see [`EnhancementLevel::parse`], to its sub key.
+///
+pub fn parse_accessory_level(text: &str) -> Result<u8, String> {
+    EnhancementLevel::parse(text, EnhancementType::Accessory).map(|level| level.get_sub_key())
+}
+
+impl fmt::Display for EnhancementLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let index = self.sub_key as usize;
+        match self.enhancement_type {
+            _ if self.sub_key == 0 && self.enhancement_type != EnhancementType::Gear => write!(f, "Base"),
+            EnhancementType::Accessory => write!(f, "{}", LEVEL_NAMES[index - 1]),
+            EnhancementType::FallenGod => write!(f, "{}", ROMAN_NUMERALS[index - 1]),
+            EnhancementType::Gear if self.sub_key >= GEAR_PRI => write!(f, "{}", LEVEL_NAMES[index - GEAR_PRI as usize]),
+            EnhancementType::Gear => write!(f, "+{}", self.sub_key),
+            EnhancementType::None | EnhancementType::Unknown => write!(f, "{}", self.sub_key),
+        }
+    }
+}
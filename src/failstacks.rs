@@ -0,0 +1,284 @@
+//! # Failstacks
+//!
+//! Failstacks is a collection related to saved failstacks, and how they should be spent.
+
+use crate::accessories::calc_accessory_chance;
+
+/// Where a saved failstack came from.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackSource {
+    /// Built by failing enhancements.
+    Built,
+    /// An Advice of Valks.
+    Valks,
+    /// Stored with Crone's stones.
+    Crone,
+}
+
+/// A single saved failstack.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Failstack {
+    value: u16,
+    source: StackSource,
+}
+
+impl Failstack {
+    pub fn new(value: u16, source: StackSource) -> Self {
+        Failstack { value, source }
+    }
+
+    pub fn get_value(&self) -> u16 {
+        self.value
+    }
+
+    pub fn get_source(&self) -> StackSource {
+        self.source
+    }
+}
+
+/// A single tap that could be made with one of the saved stacks.
+///
+pub struct StackCandidate {
+    item_id: u32,
+    name: String,
+    level: u8,
+    attempt_cost: u64,
+    success_value: u64,
+}
+
+impl StackCandidate {
+    /// Creates a tap of an accessory at `level` (0 for base).
+    ///
+    /// `attempt_cost` is everything lost on a failure (the accessory being tapped and the base used),
+    /// and `success_value` is what the accessory at `level + 1` is worth after tax.
+    ///
+    /// # Panics
+    ///
+    /// If the level is above TET (4).
+    ///
+    pub fn new(item_id: u32, name: &str, level: u8, attempt_cost: u64, success_value: u64) -> Self {
+        if level > 4 {
+            panic!("An enhancement level greater than TET (4) was given.")
+        }
+
+        StackCandidate {
+            item_id,
+            name: name.to_owned(),
+            level,
+            attempt_cost,
+            success_value,
+        }
+    }
+
+    pub fn get_item_id(&self) -> u32 {
+        self.item_id
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_level(&self) -> u8 {
+        self.level
+    }
+
+    /// Expected profit of making this tap with the given stack.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bdo_enhancement_profit_calculator::failstacks::StackCandidate;
+    ///
+    /// let tap = StackCandidate::new(1, "Ring", 0, 1000, 3000);
+    ///
+    /// // 67.5% chance at 17 stacks.
+    /// assert_eq!(1025.0, tap.get_expected_profit(17).round());
+    /// ```
+    ///
+    pub fn get_expected_profit(&self, stack: u16) -> f64 {
+        let chance = calc_accessory_chance(self.level + 1, stack);
+        chance.get() * self.success_value as f64 - self.attempt_cost as f64
+    }
+}
+
+/// A tap and the stack it should be made with.
+///
+pub struct StackAssignment {
+    candidate: usize,
+    stack: Failstack,
+    expected_profit: f64,
+}
+
+impl StackAssignment {
+    /// Index of the tap in the candidates given to [`allocate_failstacks`].
+    ///
+    pub fn get_candidate(&self) -> usize {
+        self.candidate
+    }
+
+    pub fn get_stack(&self) -> Failstack {
+        self.stack
+    }
+
+    pub fn get_expected_profit(&self) -> f64 {
+        self.expected_profit
+    }
+}
+
+/// Contains which stacks should be used on which taps, and which are left idle.
+///
+pub struct StackAllocation {
+    assignments: Vec<StackAssignment>,
+    idle_stacks: Vec<Failstack>,
+}
+
+impl StackAllocation {
+    pub fn get_assignments(&self) -> &[StackAssignment] {
+        &self.assignments
+    }
+
+    pub fn get_idle_stacks(&self) -> &[Failstack] {
+        &self.idle_stacks
+    }
+
+    pub fn get_expected_profit(&self) -> f64 {
+        self.assignments.iter().map(|a| a.expected_profit).sum()
+    }
+}
+
+/// Assigns saved stacks to taps, maximising total expected profit.
+///
+/// Each stack is used at most once and each tap gets at most one stack.
+/// Taps that would lose silver on every stack are left out, and their stacks stay idle.
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::failstacks::{allocate_failstacks, Failstack, StackCandidate, StackSource};
+///
+/// let stacks = vec![Failstack::new(20, StackSource::Built), Failstack::new(110, StackSource::Valks)];
+/// let taps = vec![
+///     StackCandidate::new(1, "Ring", 0, 1000, 3000),
+///     StackCandidate::new(2, "Necklace", 3, 100_000, 2_000_000),
+/// ];
+///
+/// let allocation = allocate_failstacks(&stacks, &taps);
+///
+/// // The Valks goes on the TET tap, and the small stack on the PRI tap.
+/// assert_eq!(2, allocation.get_assignments().len());
+/// for assignment in allocation.get_assignments() {
+///     match assignment.get_candidate() {
+///         0 => assert_eq!(20, assignment.get_stack().get_value()),
+///         _ => assert_eq!(110, assignment.get_stack().get_value()),
+///     }
+/// }
+/// assert!(allocation.get_idle_stacks().is_empty());
+/// ```
+///
+pub fn allocate_failstacks(stacks: &[Failstack], candidates: &[StackCandidate]) -> StackAllocation {
+    // Square cost matrix, padded with zero-profit "do nothing" rows and columns,
+    // so a tap can go without a stack and a stack can go unused.
+    let size = stacks.len().max(candidates.len());
+    let mut cost = vec![vec![0.0_f64; size]; size];
+    for (s, stack) in stacks.iter().enumerate() {
+        for (c, candidate) in candidates.iter().enumerate() {
+            let profit = candidate.get_expected_profit(stack.value);
+            cost[s][c] = -profit.max(0.0);
+        }
+    }
+
+    let assigned = solve_assignment(&cost);
+
+    let mut assignments = Vec::new();
+    let mut idle_stacks = Vec::new();
+    for (s, stack) in stacks.iter().enumerate() {
+        let c = assigned[s];
+        if c < candidates.len() {
+            let expected_profit = candidates[c].get_expected_profit(stack.value);
+            if expected_profit > 0.0 {
+                assignments.push(StackAssignment {
+                    candidate: c,
+                    stack: *stack,
+                    expected_profit,
+                });
+                continue;
+            }
+        }
+        idle_stacks.push(*stack);
+    }
+
+    StackAllocation {
+        assignments,
+        idle_stacks,
+    }
+}
+
+/// Solves a square assignment problem with the Hungarian algorithm, minimising total cost.
+///
+/// Returns the column assigned to each row.
+///
+fn solve_assignment(cost: &[Vec<f64>]) -> Vec<usize> {
+    let n = cost.len();
+    // 1-indexed potentials and matching, with index 0 as the free column.
+    let mut u = vec![0.0; n + 1];
+    let mut v = vec![0.0; n + 1];
+    let mut row_of = vec![0_usize; n + 1];
+    let mut way = vec![0_usize; n + 1];
+
+    for row in 1..=n {
+        row_of[0] = row;
+        let mut col = 0;
+        let mut min_to = vec![f64::INFINITY; n + 1];
+        let mut used = vec![false; n + 1];
+        loop {
+            used[col] = true;
+            let r = row_of[col];
+            let mut delta = f64::INFINITY;
+            let mut next_col = 0;
+            for c in 1..=n {
+                if used[c] {
+                    continue;
+                }
+                let reduced = cost[r - 1][c - 1] - u[r] - v[c];
+                if reduced < min_to[c] {
+                    min_to[c] = reduced;
+                    way[c] = col;
+                }
+                if min_to[c] < delta {
+                    delta = min_to[c];
+                    next_col = c;
+                }
+            }
+            for c in 0..=n {
+                if used[c] {
+                    u[row_of[c]] += delta;
+                    v[c] -= delta;
+                } else {
+                    min_to[c] -= delta;
+                }
+            }
+            col = next_col;
+            if row_of[col] == 0 {
+                break;
+            }
+        }
+        loop {
+            let prev = way[col];
+            row_of[col] = row_of[prev];
+            col = prev;
+            if col == 0 {
+                break;
+            }
+        }
+    }
+
+    let mut assigned = vec![0_usize; n];
+    for c in 1..=n {
+        if row_of[c] > 0 {
+            assigned[row_of[c] - 1] = c - 1;
+        }
+    }
+    assigned
+}
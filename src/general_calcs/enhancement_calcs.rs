@@ -50,11 +50,36 @@ pub fn calc_total_chance(chance: f64, attempt_amount: i32) -> f64 {
 /// ```
 ///
 pub fn simulate_enhancement(chance: f64) -> bool {
+    simulate_enhancement_with(chance, &mut rand::thread_rng())
+}
+
+/// Simulates an enhancement like [`simulate_enhancement`], drawing from `rng` so results can be repeated.
+///
+/// # Panics
+///
+/// If chance > 1
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::general_calcs::enhancement_calcs::simulate_enhancement_with;
+/// use rand::rngs::StdRng;
+/// use rand::SeedableRng;
+///
+/// let draws = |seed| {
+///     let mut rng = StdRng::seed_from_u64(seed);
+///     (0..10).map(|_| simulate_enhancement_with(0.5, &mut rng)).collect::<Vec<bool>>()
+/// };
+///
+/// assert_eq!(draws(7), draws(7));
+/// ```
+///
+pub fn simulate_enhancement_with<R: Rng>(chance: f64, rng: &mut R) -> bool {
     if chance > 1.0 {
         panic!("Chance is greater than 100%! Input chances as decimals.")
     }
 
-    let simulated_chance: f64 = rand::thread_rng().gen();
+    let simulated_chance: f64 = rng.gen();
     if simulated_chance < chance {
         return true;
     } else {
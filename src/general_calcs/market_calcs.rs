@@ -1,6 +1,13 @@
 //! # Market Calculations
 //!
 //! These calculations work out profit from selling on the market.
+//!
+//! Silver is rounded as described in [`units`](crate::units).
+
+use crate::bdo_market_requests::BiddingInfo;
+use crate::units::{Probability, Silver};
+
+use serde::Serialize;
 
 /// Gets the amount that should be taxed.
 ///
@@ -45,34 +52,319 @@ pub fn get_market_tax(fam_fame: u16, value_pack: bool, merch_ring: bool) -> f64
 ///
 /// ```
 /// use bdo_enhancement_profit_calculator::general_calcs::market_calcs::calc_profit;
+/// use bdo_enhancement_profit_calculator::units::Silver;
 ///
-/// let profit = calc_profit(1000, 950);
+/// let profit = calc_profit(Silver::new(1000), Silver::new(950));
 ///
-/// assert_eq!(-50, profit);
+/// assert_eq!(Silver::new(-50), profit);
 /// ```
 ///
-pub fn calc_profit(cost_to_make: u64, item_value: u64) -> i64 {
-    let cost_to_make = cost_to_make as i64;
-    let item_value = item_value as i64;
-    return item_value - cost_to_make;
+pub fn calc_profit(cost_to_make: Silver, item_value: Silver) -> Silver {
+    item_value - cost_to_make
 }
 
-/// Calculates the profit made from an item after tax.
+/// Calculates the profit made from an item after tax, which is rounded down like the game.
 ///
 /// # Example
 ///
 /// ```
 /// use bdo_enhancement_profit_calculator::general_calcs::market_calcs::calc_profit_taxed;
+/// use bdo_enhancement_profit_calculator::units::Silver;
+///
+/// let tax_rate = 0.8515;
+/// let profit = calc_profit_taxed(Silver::new(1000), Silver::new(1999), tax_rate);
+///
+/// // 1999 pays out 1702.1485, of which 1702 is received.
+/// assert_eq!(Silver::new(702), profit);
+/// ```
+///
+pub fn calc_profit_taxed(cost_to_make: Silver, item_value: Silver, tax_rate: f64) -> Silver {
+    item_value.after_tax(tax_rate) - cost_to_make
+}
+
+/// Where an item's price sits relative to its market price caps.
+///
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CapState {
+    /// Pinned at the maximum price, sales are decided by the pre-order lottery.
+    CappedHigh,
+    /// Pinned at the minimum price, listings can't undercut and have to wait in line.
+    CappedLow,
+    /// Between the caps, listings sell at the market price.
+    FreeFloating,
+    /// The caps aren't a range, as with an empty bid ladder, so there's nothing to sell into.
+    Unlisted,
+}
+
+impl CapState {
+    /// Gets the name written in reports and API responses, such as `capped_high`.
+    ///
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            CapState::CappedHigh => "capped_high",
+            CapState::CappedLow => "capped_low",
+            CapState::FreeFloating => "free_floating",
+            CapState::Unlisted => "unlisted",
+        }
+    }
+}
+
+/// Classifies a price against the market price caps.
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::general_calcs::market_calcs::{get_cap_state, CapState};
+///
+/// assert_eq!(CapState::CappedHigh, get_cap_state(2000, 1000, 2000));
+/// assert_eq!(CapState::CappedLow, get_cap_state(1000, 1000, 2000));
+/// assert_eq!(CapState::FreeFloating, get_cap_state(1500, 1000, 2000));
+/// assert_eq!(CapState::Unlisted, get_cap_state(1500, u64::MAX, 0));
+/// ```
+///
+pub fn get_cap_state(price: u64, price_cap_min: u64, price_cap_max: u64) -> CapState {
+    if price_cap_min > price_cap_max {
+        CapState::Unlisted
+    } else if price_cap_max > 0 && price >= price_cap_max {
+        CapState::CappedHigh
+    } else if price <= price_cap_min {
+        CapState::CappedLow
+    } else {
+        CapState::FreeFloating
+    }
+}
+
+/// Contains the expected outcome of listing an item on the market.
+///
+#[derive(Debug, Clone)]
+pub struct SellEstimate {
+    cap_state: CapState,
+    list_price: Silver,
+    sale_chance: Probability,
+    expected_value: Silver,
+}
+
+impl SellEstimate {
+    pub fn get_cap_state(&self) -> CapState {
+        self.cap_state
+    }
+
+    pub fn get_list_price(&self) -> Silver {
+        self.list_price
+    }
+
+    pub fn get_sale_chance(&self) -> Probability {
+        self.sale_chance
+    }
+
+    /// Gets what the item is worth to the seller, which is the list price unless it can't be listed.
+    ///
+    /// An unsold capped item stays listed until a buyer turns up, so the chance of a sale only decides how long that takes.
+    ///
+    pub fn get_expected_value(&self) -> Silver {
+        self.expected_value
+    }
+}
+
+/// Estimates what listing an item will realistically make, given its price caps and bid ladder.
+///
+/// Free-floating items sell at the market price.
+/// Capped items are listed at the cap, and only sell straight away if a pre-order is left over for
+/// them once the listings already at the cap are filled. This assumes at least one more buyer turns up.
+/// Otherwise they wait their turn, still worth the list price, so the chance of a sale is a measure of
+/// how liquid the item is. Unlisted items can't be sold, and are worth 0.
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::bdo_market_requests::BiddingInfo;
+/// use bdo_enhancement_profit_calculator::general_calcs::market_calcs::{estimate_sell_price, CapState};
+/// use bdo_enhancement_profit_calculator::units::Silver;
 ///
-/// let tax_rate = 0.8;
-/// let profit = calc_profit_taxed(1000, 2000, tax_rate);
+/// // 3 listings are already waiting at the minimum price, with no pre-orders.
+/// let bids = vec![BiddingInfo::new(1000, 3, 0), BiddingInfo::new(1500, 0, 0), BiddingInfo::new(2000, 0, 0)];
+/// let estimate = estimate_sell_price(1000, 1000, 2000, &bids);
 ///
-/// assert_eq!(600, profit);
+/// assert_eq!(CapState::CappedLow, estimate.get_cap_state());
+/// assert_eq!(0.25, estimate.get_sale_chance().get());
+/// assert_eq!(Silver::new(1000), estimate.get_expected_value());
+///
+/// // An empty ladder has no caps to sell between.
+/// let estimate = estimate_sell_price(1000, u64::MAX, 0, &[]);
+///
+/// assert_eq!(CapState::Unlisted, estimate.get_cap_state());
+/// assert_eq!(0.0, estimate.get_sale_chance().get());
+/// assert_eq!(Silver::ZERO, estimate.get_expected_value());
+/// ```
+///
+pub fn estimate_sell_price(
+    price: u64,
+    price_cap_min: u64,
+    price_cap_max: u64,
+    bids: &[BiddingInfo],
+) -> SellEstimate {
+    let cap_state = get_cap_state(price, price_cap_min, price_cap_max);
+
+    let (list_price, capped_bids): (u64, Vec<&BiddingInfo>) = match cap_state {
+        CapState::CappedHigh => (
+            price_cap_max,
+            bids.iter().filter(|b| b.get_bidding_price() >= price_cap_max).collect(),
+        ),
+        CapState::CappedLow => (
+            price_cap_min,
+            bids.iter().filter(|b| b.get_bidding_price() <= price_cap_min).collect(),
+        ),
+        CapState::FreeFloating => (price, Vec::new()),
+        CapState::Unlisted => (0, Vec::new()),
+    };
+
+    let sale_chance = if cap_state == CapState::FreeFloating {
+        Probability::ONE
+    } else if cap_state == CapState::Unlisted {
+        Probability::ZERO
+    } else {
+        let buyers: u64 = capped_bids.iter().map(|b| b.get_buy_count() as u64).sum();
+        let sellers: u64 = capped_bids.iter().map(|b| b.get_sell_count() as u64).sum();
+
+        Probability::clamped((buyers + 1) as f64 / (sellers + 1) as f64)
+    };
+
+    let list_price = Silver::from(list_price);
+
+    SellEstimate {
+        cap_state,
+        list_price,
+        sale_chance,
+        expected_value: list_price,
+    }
+}
+
+/// Contains the expected outcome of buying a quantity of an item.
+///
+#[derive(Debug)]
+pub struct PurchaseEstimate {
+    quantity: u64,
+    bought_listed: u64,
+    pre_ordered: u64,
+    pre_order_price: Silver,
+    queue_ahead: u64,
+    total_cost: Silver,
+}
+
+impl PurchaseEstimate {
+    pub fn get_quantity(&self) -> u64 {
+        self.quantity
+    }
+
+    pub fn get_bought_listed(&self) -> u64 {
+        self.bought_listed
+    }
+
+    pub fn get_pre_ordered(&self) -> u64 {
+        self.pre_ordered
+    }
+
+    pub fn get_pre_order_price(&self) -> Silver {
+        self.pre_order_price
+    }
+
+    pub fn get_queue_ahead(&self) -> u64 {
+        self.queue_ahead
+    }
+
+    pub fn get_total_cost(&self) -> Silver {
+        self.total_cost
+    }
+
+    /// Gets the exact average price, which is rounded once it's part of a cost.
+    ///
+    pub fn get_average_price(&self) -> f64 {
+        if self.quantity == 0 {
+            return 0.0;
+        }
+        self.total_cost.to_f64() / self.quantity as f64
+    }
+
+    /// Days until every pre-order is filled when the item trades `daily_volume` times a day.
+    ///
+    /// There's no estimate when something is pre-ordered and the volume isn't above 0.
+    ///
+    pub fn get_fill_days(&self, daily_volume: f64) -> Option<f64> {
+        if self.pre_ordered == 0 {
+            Some(0.0)
+        } else if daily_volume > 0.0 {
+            Some((self.queue_ahead + self.pre_ordered) as f64 / daily_volume)
+        } else {
+            None
+        }
+    }
+}
+
+/// Estimates the cost of buying a quantity of an item by walking its bid ladder.
+///
+/// Listings are bought cheapest first. Whatever is left is pre-ordered at the highest price other
+/// buyers are waiting at (or the bottom of the ladder if nobody is), behind every pre-order at that
+/// price or above. See [`PurchaseEstimate::get_fill_days`] for how long those pre-orders take.
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::bdo_market_requests::BiddingInfo;
+/// use bdo_enhancement_profit_calculator::general_calcs::market_calcs::estimate_purchase;
+/// use bdo_enhancement_profit_calculator::units::Silver;
+///
+/// // 2 listed at 1100, and 6 buyers waiting at 1000.
+/// let bids = vec![BiddingInfo::new(1000, 0, 6), BiddingInfo::new(1100, 2, 0)];
+/// let estimate = estimate_purchase(&bids, 4);
+///
+/// assert_eq!(2, estimate.get_bought_listed());
+/// assert_eq!(2, estimate.get_pre_ordered());
+/// assert_eq!(Silver::new(4200), estimate.get_total_cost());
+/// assert_eq!(1050.0, estimate.get_average_price());
+/// assert_eq!(Some(2.0), estimate.get_fill_days(4.0));
+/// assert_eq!(None, estimate.get_fill_days(0.0));
 /// ```
 ///
-pub fn calc_profit_taxed(cost_to_make: u64, item_value: u64, tax_rate: f64) -> i64 {
-    let taxed_item_value = item_value as f64 * tax_rate;
-    let profit = taxed_item_value - cost_to_make as f64;
+pub fn estimate_purchase(bids: &[BiddingInfo], quantity: u64) -> PurchaseEstimate {
+    let mut ladder: Vec<&BiddingInfo> = bids.iter().collect();
+    ladder.sort_by_key(|b| b.get_bidding_price());
+
+    let mut remaining = quantity;
+    let mut total_cost = Silver::ZERO;
+    for level in &ladder {
+        if remaining == 0 {
+            break;
+        }
+        let bought = remaining.min(level.get_sell_count() as u64);
+        total_cost += Silver::from(level.get_bidding_price()).times(bought);
+        remaining -= bought;
+    }
+    let bought_listed = quantity - remaining;
+
+    let pre_order_price = ladder
+        .iter()
+        .rev()
+        .find(|b| b.get_buy_count() > 0)
+        .or(ladder.first())
+        .map(|b| b.get_bidding_price())
+        .unwrap_or(0);
+
+    let queue_ahead: u64 = ladder
+        .iter()
+        .filter(|b| b.get_bidding_price() >= pre_order_price)
+        .map(|b| b.get_buy_count() as u64)
+        .sum();
+
+    let pre_order_price = Silver::from(pre_order_price);
+    total_cost += pre_order_price.times(remaining);
 
-    return profit.floor() as i64;
+    PurchaseEstimate {
+        quantity,
+        bought_listed,
+        pre_ordered: remaining,
+        pre_order_price,
+        queue_ahead,
+        total_cost,
+    }
 }
@@ -1,3 +1,23 @@
 pub mod accessories;
+pub mod backtest;
 pub mod bdo_market_requests;
+pub mod catalogue;
+pub mod compare;
+pub mod config;
+pub mod enhancement_level;
+pub mod failstacks;
 pub mod general_calcs;
+pub mod market_cache;
+pub mod market_category;
+pub mod notify;
+pub mod policy;
+pub mod portfolio;
+pub mod report;
+pub mod scan;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "sqlite")]
+pub mod storage;
+pub mod trends;
+pub mod units;
+pub mod watch;
@@ -1,63 +1,1027 @@
-use bdo_enhancement_profit_calculator::accessories::{self, AccEnhancementDetails, get_tap_profit_mult};
+mod cli;
+#[cfg(feature = "tui")]
+mod tui;
 
-use bdo_enhancement_profit_calculator::bdo_market_requests::CategoryGivenInfo;
+use bdo_enhancement_profit_calculator::accessories::{
+    calc_risk_metrics, simulate_tap_mult, AccEnhancementDetails, AccMarketData,
+};
+use bdo_enhancement_profit_calculator::bdo_market_requests::market_client::{
+    is_console_region, new_market_client, new_market_client_as, MarketClient, RateLimitedClient,
+};
+use bdo_enhancement_profit_calculator::bdo_market_requests::{HasId, ItemID};
+use bdo_enhancement_profit_calculator::compare::{compare_items, scan_regions};
+use bdo_enhancement_profit_calculator::catalogue::{Catalogue, EnhancementType, DEFAULT_LANGUAGE};
+use bdo_enhancement_profit_calculator::config::{Config, Profile};
+use bdo_enhancement_profit_calculator::enhancement_level::EnhancementLevel;
+use bdo_enhancement_profit_calculator::general_calcs::market_calcs::{estimate_sell_price, get_cap_state, CapState};
+use bdo_enhancement_profit_calculator::market_cache::MarketCache;
+use bdo_enhancement_profit_calculator::market_category::MarketCategory;
+use bdo_enhancement_profit_calculator::notify::{ChatFormat, DesktopNotifier, NdjsonNotifier, Notifier, StdoutNotifier, Template, WebhookNotifier};
+use bdo_enhancement_profit_calculator::policy::{get_level_prices, solve_policy, PolicyAction, PolicyState};
+use bdo_enhancement_profit_calculator::portfolio::{max_count_from_liquidity, plan_portfolio, PlanCandidate};
+use bdo_enhancement_profit_calculator::report::{write_comparison, write_ndjson_row, write_report, OutputFormat, ReportRow};
+use bdo_enhancement_profit_calculator::scan::{scan_accessories, ScanOptions, ScanResult};
+#[cfg(feature = "server")]
+use bdo_enhancement_profit_calculator::bdo_market_requests::get_bdo_urls::REGIONS;
+#[cfg(feature = "server")]
+use bdo_enhancement_profit_calculator::server::{serve, ServerOptions};
+#[cfg(feature = "sqlite")]
+use bdo_enhancement_profit_calculator::backtest::{self, load_days, write_equity_csv, write_trades_csv, Strategy, SECONDS_PER_DAY};
+#[cfg(feature = "sqlite")]
+use bdo_enhancement_profit_calculator::storage::{record_snapshot, Storage};
+use bdo_enhancement_profit_calculator::trends::get_movers;
+use bdo_enhancement_profit_calculator::units::{Rounding, Silver};
+use bdo_enhancement_profit_calculator::watch::{AlertRule, Watcher};
 
-use std::io;
+use clap::Parser;
+use cli::{CatalogueCommand, Cli, Command, MarketArgs, NotifyArgs, RouteArgs, ScanArgs};
 
-fn get_region() -> String {
-    let mut inp_region = String::new();
-    println!("Please enter the region for which you wish to the run the commands: ");
-    io::stdin().read_line(&mut inp_region).unwrap();
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::process::ExitCode;
+#[cfg(feature = "server")]
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
-    inp_region.trim().to_lowercase()
+// Exit codes, so runs can be checked from scripts.
+// Bad arguments exit with 2, the same as clap uses for ones it rejects.
+const EXIT_ERROR: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+const EXIT_NOTHING_FOUND: u8 = 3;
+
+/// An error message, and the code the run exits with because of it.
+///
+struct Failure {
+    code: u8,
+    message: String,
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Self {
+        Failure { code: EXIT_ERROR, message }
+    }
+}
+
+/// Marks an error as caused by the arguments, rather than by the market or files.
+///
+fn usage(message: String) -> Failure {
+    Failure { code: EXIT_USAGE, message }
+}
+
+/// Loads the config files given on the command line, if there are any.
+///
+fn load_config(cli: &Cli) -> Result<Option<Config>, String> {
+    if cli.config.is_empty() {
+        return Ok(None);
+    }
+    let paths: Vec<&Path> = cli.config.iter().map(|p| p.as_path()).collect();
+    Ok(Some(Config::load_all(&paths)?))
+}
+
+/// Gets the profile named on the command line, if there is one.
+///
+fn load_profile(cli: &Cli, config: Option<&Config>) -> Result<Option<Profile>, Failure> {
+    match (config, &cli.profile) {
+        (Some(config), Some(name)) => Ok(Some(config.get_profile(name).map_err(usage)?)),
+        _ => Ok(None),
+    }
+}
+
+fn get_region(market: &MarketArgs, profile: Option<&Profile>) -> Result<String, Failure> {
+    market
+        .region
+        .clone()
+        .or_else(|| profile.and_then(|p| p.get_region().map(|r| r.to_owned())))
+        .map(|r| r.to_lowercase())
+        .ok_or_else(|| usage(String::from("A region is required, either with --region or from a profile.")))
+}
+
+/// Creates the market client for a region, with the catalogue naming items on console markets.
+///
+/// The region's account in the config, if it has one, sets the login and how fast requests are made.
+///
+fn new_client(cli: &Cli, config: Option<&Config>, region: &str) -> Result<Box<dyn MarketClient>, String> {
+    let catalogue = Catalogue::load_with_bundled(cli.catalogue.as_deref())?;
+    let account = config.and_then(|c| c.get_account(region));
+    let client = match account.and_then(|a| a.get_credentials()) {
+        Some(credentials) => new_market_client_as(region, catalogue, credentials),
+        None => new_market_client(region, catalogue),
+    };
+    match account.and_then(|a| a.get_requests_per_second()) {
+        Some(requests_per_second) => Ok(Box::new(RateLimitedClient::new(client, requests_per_second)?)),
+        None => Ok(client),
+    }
+}
+
+fn get_client(cli: &Cli, config: Option<&Config>, market: &MarketArgs, profile: Option<&Profile>) -> Result<Box<dyn MarketClient>, Failure> {
+    Ok(new_client(cli, config, &get_region(market, profile)?)?)
+}
+
+/// Builds the scan options, from the defaults, then the profile, then the flags.
+///
+fn get_options(profile: Option<&Profile>, scan: Option<&ScanArgs>, route: &RouteArgs) -> Result<ScanOptions, Failure> {
+    let mut options = match profile {
+        Some(p) => p.get_scan_options(),
+        None => ScanOptions::default(),
+    };
+
+    if let Some(stacks) = &route.stacks {
+        options.stacks = stacks.clone();
+    }
+    if let Some(start_level) = route.start_level {
+        options.start_level = start_level;
+    }
+    if let Some(target_levels) = &route.target_levels {
+        options.target_levels = target_levels.clone();
+    }
+    if let Some(tax) = route.tax {
+        options.tax_rate = tax;
+    }
+
+    if let Some(args) = scan {
+        if let Some(categories) = &args.categories {
+            options.categories = MarketCategory::parse_list(categories).map_err(usage)?;
+        }
+        if args.main_category.is_some() || args.sub_categories.is_some() {
+            let main_category = args.main_category.unwrap_or(20);
+            let sub_categories = args.sub_categories.clone().unwrap_or(vec![1, 2, 3, 4]);
+            options.categories = sub_categories.into_iter().map(|sub| (main_category, sub)).collect();
+        }
+        if let Some(grade) = args.grade {
+            options.grade = grade;
+        }
+        if let Some(min_price) = args.min_price {
+            options.min_price = min_price;
+        }
+        if let Some(max_price) = args.max_price {
+            options.max_price = max_price;
+        }
+        if let Some(include) = &args.include {
+            options.include = include.clone();
+        }
+        if let Some(exclude) = &args.exclude {
+            options.exclude = exclude.clone();
+        }
+        if let Some(min_profit) = args.min_profit {
+            options.min_profit = min_profit;
+        }
+        if args.min_profit_taxed.is_some() {
+            options.min_profit_taxed = args.min_profit_taxed;
+        }
+    }
+
+    options.validate().map_err(usage)?;
+    Ok(options)
+}
+
+/// Names an accessory level, falling back to the number for levels accessories don't have.
+///
+fn accessory_level(sub_key: u8) -> String {
+    match EnhancementLevel::new(sub_key, EnhancementType::Accessory) {
+        Ok(v) => v.to_string(),
+        Err(_) => sub_key.to_string(),
+    }
+}
+
+fn print_result(result: &ScanResult) {
+    let profit_details = result.get_profit_details();
+    println!("---------------------------------------------------");
+    println!("Name: {}", result.get_name());
+    println!(
+        "Buy at: {} || Sell at : {}",
+        Silver::from(result.get_base_price()),
+        profit_details.get_actual_value()
+    );
+    println!(
+        "Profit: {}",
+        profit_details.get_profit()
+    );
+    println!(
+        "Profit after tax: {}",
+        profit_details.get_profit_taxed()
+    );
+    println!("---------------------------------------------------");
+}
+
+fn run_scan(client: &dyn MarketClient, options: &ScanOptions) -> Result<u8, Failure> {
+    let results = scan_accessories(client, options, |result| {
+        println!("Checking {} ({} -> {})", result.get_name(), accessory_level(result.get_start_level()), accessory_level(result.get_end_level()));
+        if result.is_profitable(options) {
+            print_result(result);
+        }
+    })?;
+    println!("Done");
+
+    if results.is_empty() {
+        return Ok(EXIT_NOTHING_FOUND);
+    }
+    Ok(0)
 }
 
-fn main() {
+/// Scans, writing the profitable results in a machine-readable format.
+///
+/// Progress goes to stderr, so stdout only has the results.
+///
+fn run_scan_report(client: &dyn MarketClient, options: &ScanOptions, format: OutputFormat, output: Option<&Path>) -> Result<u8, Failure> {
+    let region = client.get_region();
+    let mut writer: Box<dyn Write> = match output {
+        Some(path) => Box::new(BufWriter::new(
+            File::create(path).map_err(|e| format!("Could not create {}: {}", path.display(), e))?,
+        )),
+        None => Box::new(io::stdout().lock()),
+    };
 
-    let str_inp_region = get_region();
-    let str_inp_region = str_inp_region.as_str();
+    let mut write_error = None;
+    let results = scan_accessories(client, options, |result| {
+        eprintln!("Checking {} ({} -> {})", result.get_name(), accessory_level(result.get_start_level()), accessory_level(result.get_end_level()));
+        // NDJSON is streamed, so results aren't lost if a long scan fails part way.
+        if format == OutputFormat::Ndjson && result.is_profitable(options) {
+            if let Err(e) = write_ndjson_row(&mut writer, region, &ReportRow::from(result)) {
+                write_error.get_or_insert(e);
+            }
+        }
+    })?;
 
-    let mut rings = CategoryGivenInfo::from_post(str_inp_region, 20, 1);
-    let mut necklaces = CategoryGivenInfo::from_post(str_inp_region, 20, 2);
-    let mut earrings = CategoryGivenInfo::from_post(str_inp_region, 20, 3);
-    let mut belts = CategoryGivenInfo::from_post(str_inp_region, 20, 4);
+    if format != OutputFormat::Ndjson {
+        let rows: Vec<ReportRow> = results.iter().map(ReportRow::from).collect();
+        if let Err(e) = write_report(&mut writer, format, region, &rows) {
+            write_error.get_or_insert(e);
+        }
+    }
+    if let Some(e) = write_error.or_else(|| writer.flush().err()) {
+        return Err(format!("Could not write the results: {}", e).into());
+    }
+    eprintln!("Done");
 
-    let mut accessories = Vec::new();
-    accessories.append(&mut rings);
-    accessories.append(&mut necklaces);
-    accessories.append(&mut earrings);
-    accessories.append(&mut belts);
+    if results.is_empty() {
+        return Ok(EXIT_NOTHING_FOUND);
+    }
+    Ok(0)
+}
 
-    let accessories = accessories::filter_accessories_category(accessories, 3, 1000000, u64::MAX);
-    let accessories: Vec<CategoryGivenInfo> = accessories.into_iter().filter(|acc| !acc.get_item_name().contains("Manos")).collect();
-    for acc in accessories {
+/// Scans every region at once, then writes the items found side by side.
+///
+/// A region that fails is reported, and the others are still compared.
+///
+fn run_compare(clients: Vec<Box<dyn MarketClient>>, options: &ScanOptions, format: OutputFormat, output: Option<&Path>) -> Result<u8, Failure> {
+    let regions: Vec<&str> = clients.iter().map(|c| c.get_region()).collect();
+    eprintln!("Scanning {}", regions.join(", "));
 
-        let name = acc.get_item_name().to_owned();
-        println!("Checking {}", name);
-        let base_price = acc.get_base_price();
+    let mut items = Vec::new();
+    let mut failed = 0;
+    for (region, scan) in scan_regions(&clients, options) {
+        match scan {
+            Ok(mut found) => {
+                eprintln!("{}: {} items", region, found.len());
+                items.append(&mut found);
+            }
+            Err(e) => {
+                eprintln!("{}: {}", region, e);
+                failed += 1;
+            }
+        }
+    }
+    if failed == clients.len() {
+        return Err(String::from("Every region failed to scan.").into());
+    }
 
-        let details = AccEnhancementDetails::new(acc, 0, Some(vec![20, 40, 44, 110]));
-        let profit_details = get_tap_profit_mult(details, 4, 0.8515, str_inp_region);
-        let p = profit_details.get_profit();
-        if p != -1 && p > 50000000 {
-            println!("---------------------------------------------------");
-            println!("Name: {}", name);
+    let rows = compare_items(items);
+    let mut writer: Box<dyn Write> = match output {
+        Some(path) => Box::new(BufWriter::new(
+            File::create(path).map_err(|e| format!("Could not create {}: {}", path.display(), e))?,
+        )),
+        None => Box::new(io::stdout().lock()),
+    };
+    write_comparison(&mut writer, format, &rows)
+        .and_then(|_| writer.flush())
+        .map_err(|e| format!("Could not write the comparison: {}", e))?;
+
+    if rows.is_empty() {
+        return Ok(EXIT_NOTHING_FOUND);
+    }
+    Ok(0)
+}
+
+fn run_item(id: u32, client: &dyn MarketClient, options: &ScanOptions, base_volume: Option<f64>) -> Result<u8, Failure> {
+    let mut market = AccMarketData::from_post(client, id)?;
+
+    println!("Level | Base price | Stock | Min price | Max price | Trades | Expected sale");
+    for level in 0..=5 {
+        let info = match market.get_level(level) {
+            Some(v) => v,
+            None => continue,
+        };
+        let (base_price, cap_min, cap_max) = (info.get_base_price(), info.get_price_cap_min(), info.get_price_cap_max());
+        let row = format!(
+            "{} | {} | {} | {} | {} | {}",
+            accessory_level(level),
+            base_price,
+            info.get_stock(),
+            cap_min,
+            cap_max,
+            info.get_total_trades()
+        );
+        // Only items at a price cap need their ladder to tell whether they'd sell.
+        let bids = match get_cap_state(base_price, cap_min, cap_max) {
+            CapState::FreeFloating => &[][..],
+            _ => market.get_ladder(level).map(|l| l.get_bids()).unwrap_or(&[]),
+        };
+        let estimate = estimate_sell_price(base_price, cap_min, cap_max, bids);
+        println!(
+            "{} | {} ({:?}, {})",
+            row,
+            estimate.get_expected_value(),
+            estimate.get_cap_state(),
+            estimate.get_sale_chance()
+        );
+    }
+
+    for target_level in &options.target_levels {
+        let details = AccEnhancementDetails::new(ItemID::new(id), options.start_level, Some(options.stacks.clone()));
+        println!("---------------------------------------------------");
+        println!("{} -> {}", accessory_level(options.start_level), accessory_level(*target_level));
+        let breakdown = match market.get_tap_breakdown(&details, *target_level, options.tax_rate) {
+            Ok(v) => v,
+            Err(e) => {
+                println!("{}", e);
+                continue;
+            }
+        };
+        if !breakdown.get_unused_stacks().is_empty() {
+            println!("Unused stacks: {:?}", breakdown.get_unused_stacks());
+        }
+        for step in breakdown.get_steps() {
             println!(
-                "Buy at: {} || Sell at : {}",
-                base_price,
-                profit_details.get_actual_value()
+                "{} -> {} at {} stacks: {} chance, {:.2} attempts, {:.2} bases used, {} so far (market {})",
+                accessory_level(step.get_from_level()),
+                accessory_level(step.get_to_level()),
+                step.get_stack(),
+                step.get_chance(),
+                step.get_expected_attempts(),
+                step.get_expected_bases(),
+                step.get_cumulative_cost(),
+                step.get_market_value().map(|v| v.to_string()).unwrap_or_else(|| String::from("-"))
             );
+        }
+        // Too few bases are listed, so some have to be pre-ordered behind other buyers.
+        if let Some(purchase) = breakdown.get_base_purchase() {
             println!(
-                "Profit: {}",
-                profit_details.get_profit()
+                "Bases: {} bought listed, {} pre-ordered at {} behind {} others, {} on average",
+                purchase.get_bought_listed(),
+                purchase.get_pre_ordered(),
+                purchase.get_pre_order_price(),
+                purchase.get_queue_ahead(),
+                breakdown.get_base_cost()
             );
+            match base_volume.and_then(|v| purchase.get_fill_days(v)) {
+                Some(days) => println!("Pre-orders fill in about {:.1} days", days),
+                None => println!("Pre-orders fill in an unknown time, give --base-volume to estimate it"),
+            }
+        }
+        let profit_details = breakdown.get_profit_details();
+        println!("Make cost: {}", profit_details.get_make_cost());
+        println!("Sell at: {}", profit_details.get_actual_value());
+        println!("Profit: {}", profit_details.get_profit());
+        println!("Profit after tax: {}", profit_details.get_profit_taxed());
+    }
+    Ok(0)
+}
+
+fn run_simulate(id: u32, client: &dyn MarketClient, options: &ScanOptions, runs: u32) -> Result<u8, Failure> {
+    if runs == 0 {
+        return Err(usage(String::from("At least 1 run is required.")));
+    }
+
+    let start = client.get_bids(id, options.start_level)?;
+    let base = client.get_bids(id, 0)?;
+
+    for target_level in &options.target_levels {
+        let target = client.get_bids(id, *target_level)?;
+        let actual_value = target.get_sell_estimate().get_expected_value();
+
+        let details = AccEnhancementDetails::new(ItemID::new(id), options.start_level, Some(options.stacks.clone()));
+        let (start_cost, base_cost) = (Silver::from(start.get_lowest_listed()), Silver::from(base.get_lowest_listed()));
+        let simulation = simulate_tap_mult(&details, *target_level, start_cost, base_cost, runs);
+        let metrics = calc_risk_metrics(&details, *target_level, start_cost, base_cost, actual_value, options.tax_rate);
+
+        println!("---------------------------------------------------");
+        println!("{} -> {}", accessory_level(options.start_level), accessory_level(*target_level));
+        println!("Runs: {}", simulation.get_runs());
+        println!("Sell at: {}", actual_value);
+        println!(
+            "Mean cost: {} (expected {})",
+            Silver::from_f64(simulation.get_mean_cost(), Rounding::Nearest),
+            metrics.get_profit_details().get_make_cost()
+        );
+        println!("Min cost: {}", simulation.get_min_cost());
+        println!("Median cost: {}", simulation.get_percentile_cost(50.0));
+        println!("90th percentile cost: {}", simulation.get_percentile_cost(90.0));
+        println!("Max cost: {}", simulation.get_max_cost());
+        println!("Mean base accessories used: {:.1}", simulation.get_mean_bases_used());
+        println!(
+            "Chance of profit after tax: {} (expected {})",
+            simulation.get_profit_chance(actual_value, options.tax_rate),
+            metrics.get_profit_chance()
+        );
+    }
+    Ok(0)
+}
+
+fn run_policy(id: u32, client: &dyn MarketClient, options: &ScanOptions, max_stack: u16) -> Result<u8, Failure> {
+    options.validate().map_err(usage)?;
+    let mut market = AccMarketData::from_post(client, id)?;
+    let top_level = *options.target_levels.iter().max().unwrap_or(&options.start_level);
+    let prices = get_level_prices(&mut market, top_level);
+    let policy = solve_policy(&prices, options, max_stack)?;
+
+    println!("Policy: {} expected profit after tax", policy.get_expected_profit());
+    for plan in policy.get_fixed_plans() {
+        println!(
+            "{} -> {}: {}",
+            accessory_level(options.start_level),
+            accessory_level(plan.end_level),
+            plan.expected_profit.map(|v| v.to_string()).unwrap_or_else(|| String::from("can't be sold"))
+        );
+    }
+
+    // Neighbouring stacks with the same choice are shown as a range.
+    let states = policy.get_states();
+    let mut start = 0;
+    for i in 0..states.len() {
+        let state = &states[i];
+        let next = states.get(i + 1);
+        let same = |n: &&PolicyState| n.level == state.level && n.action == state.action && n.rebuy_on_failure == state.rebuy_on_failure;
+        if next.filter(same).is_some() {
+            continue;
+        }
+        let action = match (state.action, state.rebuy_on_failure) {
+            (PolicyAction::Sell, _) => "sell",
+            (PolicyAction::Tap, true) => "tap, buy another on failure",
+            (PolicyAction::Tap, false) => "tap, stop on failure",
+        };
+        println!(
+            "{} at {}-{} stacks: {} (worth {} to {})",
+            accessory_level(state.level),
+            states[start].stack,
+            state.stack,
+            action,
+            states[start].value.min(state.value),
+            states[start].value.max(state.value)
+        );
+        start = i + 1;
+    }
+    Ok(0)
+}
+
+fn run_history(id: u32, client: &dyn MarketClient, level: &str, catalogue: &Catalogue) -> Result<u8, Failure> {
+    let enhancement_type = catalogue.get(id).map(|e| e.enhancement_type).unwrap_or(EnhancementType::Unknown);
+    let level = EnhancementLevel::parse(level, enhancement_type).map_err(usage)?;
+    let history = client.get_history(id, level.get_sub_key())?;
+    for price in history.get_prices() {
+        println!("{}", price);
+    }
+    if let Some(average) = history.get_average(7) {
+        println!("7 day average: {:.0}", average);
+    }
+    if let Some(change) = history.get_change_percent(7) {
+        println!("Change against 7 day average: {:.1}%", change);
+    }
+    Ok(0)
+}
+
+/// Names an item's level for its type in the catalogue, falling back to the number.
+///
+fn level_name(catalogue: &Catalogue, item_id: u32, sub_key: u8) -> String {
+    let enhancement_type = catalogue.get(item_id).map(|e| e.enhancement_type).unwrap_or(EnhancementType::Unknown);
+    match EnhancementLevel::new(sub_key, enhancement_type) {
+        Ok(v) => v.to_string(),
+        Err(_) => sub_key.to_string(),
+    }
+}
+
+fn run_queue(client: &dyn MarketClient, catalogue: &Catalogue) -> Result<u8, Failure> {
+    let queue = client.get_registration_queue()?;
+    for item in &queue {
+        println!(
+            "{} | {} | {} | {} | {}",
+            item.get_item_id(),
+            catalogue.get_name(item.get_item_id(), DEFAULT_LANGUAGE).unwrap_or("?"),
+            level_name(catalogue, item.get_item_id(), item.get_enhancement_level()),
+            item.get_listed_price(),
+            item.get_registered_timestamp()
+        );
+    }
+    Ok(0)
+}
+
+fn run_movers(client: &dyn MarketClient, days: usize, limit: usize, catalogue: &Catalogue) -> Result<u8, Failure> {
+    let cache = MarketCache::new(Duration::from_secs(60 * 60));
+    let movers = get_movers(client, &cache, days)?;
+
+    println!("Rank | Id | Name | Level | Price | {} day change | Hot | Queued | Score", days);
+    for (rank, mover) in movers.iter().take(limit).enumerate() {
+        println!(
+            "{} | {} | {} | {} | {} | {} | {} | {} | {:.1}",
+            rank + 1,
+            mover.item_id,
+            catalogue.get_name(mover.item_id, DEFAULT_LANGUAGE).unwrap_or("?"),
+            level_name(catalogue, mover.item_id, mover.enhancement_level),
+            mover.base_price,
+            mover.change_percent.map(|c| format!("{:+.1}%", c)).unwrap_or_else(|| String::from("-")),
+            if mover.hot { format!("{:+}", mover.hot_change) } else { String::from("-") },
+            mover.queued,
+            mover.score
+        );
+    }
+
+    if movers.is_empty() {
+        return Ok(EXIT_NOTHING_FOUND);
+    }
+    Ok(0)
+}
+
+fn run_prices(ids: &[u32], client: &dyn MarketClient, catalogue: &Catalogue) -> Result<u8, Failure> {
+    let cache = MarketCache::new(Duration::from_secs(60));
+    let item_ids: Vec<ItemID> = ids.iter().map(|id| ItemID::new(*id)).collect();
+    let refresh = cache.refresh_prices(client, &item_ids)?;
+
+    for id in ids {
+        if let Some(item) = cache.get_price(client.get_region(), *id) {
             println!(
-                "Profit after tax: {}",
-                profit_details.get_profit_taxed()
+                "{} | {} | {} | stock {} | {} trades",
+                id,
+                catalogue.get_name(*id, DEFAULT_LANGUAGE).unwrap_or("?"),
+                item.get_base_price(),
+                item.get_stock(),
+                item.get_total_trades()
             );
-            println!("---------------------------------------------------");
         }
     }
-    println!("Done");
-    io::stdin().read_line(&mut String::new()).unwrap();
+    eprintln!("{} requests", refresh.get_requests());
+    if !refresh.get_missing().is_empty() {
+        let missing: Vec<String> = refresh.get_missing().iter().map(|id| id.to_string()).collect();
+        eprintln!("Not on the market: {}", missing.join(", "));
+    }
+
+    if refresh.get_updated() == 0 {
+        return Ok(EXIT_NOTHING_FOUND);
+    }
+    Ok(0)
+}
+
+fn run_profiles(cli: &Cli) -> Result<u8, Failure> {
+    let paths: Vec<&Path> = cli.config.iter().map(|p| p.as_path()).collect();
+    let config = Config::load_all(&paths)?;
+    for name in config.get_profile_names() {
+        println!("{}", name);
+    }
+    Ok(0)
+}
+
+fn run_categories() -> Result<u8, Failure> {
+    for category in MarketCategory::all() {
+        let (main, sub) = category.get_ids();
+        println!("{}/{} | {} | {:?}", main, sub, category.get_path(), category.get_enhancement_type());
+    }
+    Ok(0)
+}
+
+fn run_plan(
+    client: &dyn MarketClient,
+    options: &ScanOptions,
+    budget: u64,
+    risk_aversion: f64,
+    liquidity_share: f64,
+    max_per_item: Option<u32>,
+) -> Result<u8, Failure> {
+    if !(liquidity_share > 0.0 && liquidity_share.is_finite()) {
+        return Err(usage(format!("The liquidity share should be above 0, not {}.", liquidity_share)));
+    }
+    let results = scan_accessories(client, options, |result| {
+        println!("Checking {}", result.get_name());
+    })?;
+
+    let candidates = results
+        .iter()
+        .map(|result| {
+            // Only make as many as the target level's market can take.
+            let max_count = max_count_from_liquidity(result.get_target_stock().unwrap_or(0), liquidity_share);
+            let mut candidate = PlanCandidate::new(
+                result.get_item_id(),
+                result.get_name(),
+                result.get_start_level(),
+                result.get_end_level(),
+                result.get_stacks().to_vec(),
+                result.get_profit_details(),
+                max_per_item.map_or(max_count, |max| max_count.min(max)),
+            );
+            // Risk is priced at the same start and base costs as the profit, whatever level the route starts at.
+            if let (Some(start_cost), Some(base_cost)) = (result.get_start_cost(), result.get_base_cost()) {
+                let details = AccEnhancementDetails::new(ItemID::new(result.get_item_id()), result.get_start_level(), Some(result.get_stacks().to_vec()));
+                let metrics = calc_risk_metrics(
+                    &details,
+                    result.get_end_level(),
+                    start_cost,
+                    base_cost,
+                    result.get_profit_details().get_actual_value(),
+                    options.tax_rate,
+                );
+                candidate.set_risk(metrics.get_cost_std_dev());
+            }
+            candidate
+        })
+        .collect();
+
+    let plan = plan_portfolio(candidates, budget, risk_aversion);
+    if plan.get_allocations().is_empty() {
+        println!("Nothing is worth making with this budget.");
+        return Ok(EXIT_NOTHING_FOUND);
+    }
+
+    println!("---------------------------------------------------");
+    for allocation in plan.get_allocations() {
+        println!(
+            "{} x{} ({} -> {}) || Cost: {} || Profit after tax: {}",
+            allocation.get_name(),
+            allocation.get_count(),
+            allocation.get_start_level(),
+            allocation.get_end_level(),
+            allocation.get_cost(),
+            allocation.get_expected_profit()
+        );
+    }
+    println!("Total cost: {} || Total profit after tax: {}", plan.get_total_cost(), plan.get_expected_profit());
+    println!("---------------------------------------------------");
+    println!("Shopping list:");
+    for item in plan.get_shopping_list() {
+        print!("{}: {} base", item.get_name(), item.get_base_accessories());
+        if item.get_start_items() > 0 {
+            print!(", {} at level {}", item.get_start_items(), item.get_start_level());
+        }
+        println!();
+    }
+    println!("---------------------------------------------------");
+    println!("Tap plan:");
+    for step in plan.get_tap_plan() {
+        println!(
+            "{}: {} -> {} at {} stacks ({:.1}%), ~{:.1} attempts",
+            step.get_name(),
+            step.get_from_level(),
+            step.get_to_level(),
+            step.get_stack(),
+            step.get_chance() * 100.0,
+            step.get_expected_attempts()
+        );
+    }
+    Ok(0)
+}
+
+fn get_notifiers(args: &NotifyArgs) -> Result<Vec<Box<dyn Notifier>>, Failure> {
+    let template = || match &args.template {
+        Some(t) => Template::parse(t).map_err(usage),
+        None => Ok(Template::default()),
+    };
+
+    let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+    if !args.quiet {
+        notifiers.push(Box::new(StdoutNotifier::new(template()?)));
+    }
+    if args.desktop {
+        notifiers.push(Box::new(DesktopNotifier::new(template()?)));
+    }
+    if let Some(path) = &args.log {
+        notifiers.push(Box::new(NdjsonNotifier::append(path)?));
+    }
+    for url in &args.webhook {
+        notifiers.push(Box::new(WebhookNotifier::json(url)));
+    }
+    for url in &args.discord {
+        notifiers.push(Box::new(WebhookNotifier::chat(url, ChatFormat::Discord, template()?)));
+    }
+    for url in &args.slack {
+        notifiers.push(Box::new(WebhookNotifier::chat(url, ChatFormat::Slack, template()?)));
+    }
+
+    if notifiers.is_empty() {
+        return Err(usage(String::from("Alerts would go nowhere, give --log or a webhook when using --quiet.")));
+    }
+    Ok(notifiers)
+}
+
+fn run_watch(
+    client: Box<dyn MarketClient>,
+    options: ScanOptions,
+    rules: Vec<AlertRule>,
+    interval: Duration,
+    cooldown: Duration,
+    mut notifiers: Vec<Box<dyn Notifier>>,
+) -> Result<u8, Failure> {
+    let region = client.get_region().to_owned();
+    let mut watcher = Watcher::new(client, options, rules, cooldown)?;
+    let names: Vec<&str> = watcher.get_rules().iter().map(|r| r.get_name()).collect();
+    eprintln!("Watching {} for: {}", region, names.join(", "));
+
+    loop {
+        // A failed check or notification is reported, and the watch keeps running.
+        // A panic from a malformed market response counts as a failed check.
+        let checked = panic::catch_unwind(AssertUnwindSafe(|| watcher.check()))
+            .unwrap_or_else(|_| Err(String::from("The market response couldn't be read.")));
+        match checked {
+            Ok(alerts) => {
+                for alert in &alerts {
+                    for notifier in notifiers.iter_mut() {
+                        if let Err(e) = notifier.notify(alert) {
+                            eprintln!("Could not send an alert: {}", e);
+                        }
+                    }
+                }
+            }
+            Err(e) => eprintln!("Check failed: {}", e),
+        }
+        thread::sleep(interval);
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn run_record(client: &dyn MarketClient, options: &ScanOptions, db: &Path, interval: Option<Duration>) -> Result<u8, Failure> {
+    let mut storage = Storage::open(db)?;
+
+    loop {
+        // A single snapshot reports its failure, but a recording loop keeps going to the next one.
+        match (record_snapshot(&mut storage, client, options), interval) {
+            (Ok(summary), _) => {
+                eprintln!(
+                    "Recorded {} items, {} bid ladders and {} queued items at {}.",
+                    summary.items, summary.ladders, summary.queued, summary.observed_at
+                );
+                for failure in &summary.failed {
+                    eprintln!("Skipped {}", failure);
+                }
+            }
+            (Err(e), Some(_)) => eprintln!("Snapshot failed: {}", e),
+            (Err(e), None) => return Err(e.into()),
+        }
+
+        match interval {
+            Some(v) => thread::sleep(v),
+            None => return Ok(0),
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn run_backtest(region: &str, db: &Path, days: u64, strategy: &Strategy, equity: Option<&Path>, trades: Option<&Path>) -> Result<u8, Failure> {
+    let storage = Storage::open(db)?;
+    let last = storage
+        .get_last_observed(region)?
+        .ok_or_else(|| format!("Nothing has been recorded for {} in {}.", region, db.display()))?;
+    let from = (last / SECONDS_PER_DAY).saturating_sub(days.saturating_sub(1)) * SECONDS_PER_DAY;
+
+    let market_days = load_days(&storage, region, from, last)?;
+    let result = backtest::run_backtest(&market_days, strategy)?;
+
+    let write_csv = |path: &Path, write: &dyn Fn(&mut BufWriter<File>) -> io::Result<()>| {
+        let file = File::create(path).map_err(|e| format!("Could not create {}: {}", path.display(), e))?;
+        let mut writer = BufWriter::new(file);
+        write(&mut writer).and_then(|_| writer.flush()).map_err(|e| format!("Could not write {}: {}", path.display(), e))
+    };
+    if let Some(path) = equity {
+        write_csv(path, &|w| write_equity_csv(w, &result))?;
+    }
+    if let Some(path) = trades {
+        write_csv(path, &|w| write_trades_csv(w, &result))?;
+    }
+
+    println!("Days replayed: {}", market_days.len());
+    println!("Trades: {} ({} sold)", result.get_trades().len(), result.get_trades().iter().filter(|t| t.sold_on.is_some()).count());
+    println!("Realised profit: {}", result.get_realised_profit());
+    println!("Final equity: {} (from {})", result.get_final_equity(), Silver::from(strategy.budget));
+    println!("Max drawdown: {:.1}%", result.get_max_drawdown() * 100.0);
+    if equity.is_none() && trades.is_none() {
+        println!();
+        let mut stdout = io::stdout().lock();
+        write_equity_csv(&mut stdout, &result).map_err(|e| e.to_string())?;
+        println!();
+        write_trades_csv(&mut stdout, &result).map_err(|e| e.to_string())?;
+    }
+    Ok(0)
+}
+
+fn run_catalogue(cli: &Cli, config: Option<&Config>, action: &CatalogueCommand, profile: Option<&Profile>) -> Result<u8, Failure> {
+    match action {
+        CatalogueCommand::Import { market, categories, language, output } => {
+            let region = get_region(market, profile)?;
+            // Console category lists are named from the catalogue, so they can't add names to it.
+            if is_console_region(&region) {
+                return Err(usage(format!("Names can only be imported from a PC region, not {}.", region)));
+            }
+            let client = new_client(cli, config, &region)?;
+            let mut catalogue = if output.exists() { Catalogue::load(output)? } else { Catalogue::default() };
+
+            for (main_category, sub_category) in MarketCategory::parse_list(categories).map_err(usage)? {
+                let items = client.get_category(main_category, sub_category)?;
+                let added = catalogue.import_category(main_category, sub_category, &items, language);
+                eprintln!("{}/{}: {} items, {} new", main_category, sub_category, items.len(), added);
+            }
+
+            catalogue.save(output)?;
+            eprintln!("Saved {} items to {}", catalogue.len(), output.display());
+            Ok(0)
+        }
+        CatalogueCommand::Lookup { query } => {
+            let catalogue = Catalogue::load_with_bundled(cli.catalogue.as_deref())?;
+            let entries = match query.parse::<u32>() {
+                Ok(id) => catalogue.get(id).into_iter().collect(),
+                Err(_) => catalogue.find_by_name(query),
+            };
+            for entry in &entries {
+                println!(
+                    "{} | {} | grade {} | category {}/{} | {:?} up to {}",
+                    entry.item_id,
+                    entry.get_name(DEFAULT_LANGUAGE).unwrap_or("?"),
+                    entry.grade,
+                    entry.main_category,
+                    entry.sub_category,
+                    entry.enhancement_type,
+                    entry.max_level
+                );
+            }
+            Ok(if entries.is_empty() { EXIT_NOTHING_FOUND } else { 0 })
+        }
+    }
+}
+
+fn run(cli: &Cli) -> Result<u8, Failure> {
+    let config = load_config(cli)?;
+    let config = config.as_ref();
+    let profile = load_profile(cli, config)?;
+    let profile = profile.as_ref();
+
+    match &cli.command {
+        Command::Scan { market, scan, format, output } => {
+            let client = get_client(cli, config, market, profile)?;
+            let options = get_options(profile, Some(scan), &scan.route)?;
+            match format {
+                Some(format) => run_scan_report(client.as_ref(), &options, *format, output.as_deref()),
+                None => run_scan(client.as_ref(), &options),
+            }
+        }
+        Command::Compare { regions, scan, format, output } => {
+            let regions = match (regions, profile.and_then(|p| p.get_regions())) {
+                (Some(regions), _) => regions.clone(),
+                (None, Some(regions)) => regions.to_vec(),
+                (None, None) => return Err(usage(String::from("Regions are required, either with --regions or from a profile."))),
+            };
+            let mut unique: Vec<String> = Vec::new();
+            for region in regions.iter().map(|r| r.to_lowercase()) {
+                if !unique.contains(&region) {
+                    unique.push(region);
+                }
+            }
+            let clients = unique.iter().map(|r| new_client(cli, config, r)).collect::<Result<Vec<_>, _>>()?;
+            run_compare(clients, &get_options(profile, Some(scan), &scan.route)?, *format, output.as_deref())
+        }
+        #[cfg(feature = "tui")]
+        Command::Tui { market, scan } => {
+            tui::run_tui(get_client(cli, config, market, profile)?, get_options(profile, Some(scan), &scan.route)?)?;
+            Ok(0)
+        }
+        Command::Item { id, market, route, base_volume } => {
+            run_item(*id, get_client(cli, config, market, profile)?.as_ref(), &get_options(profile, None, route)?, *base_volume)
+        }
+        Command::Simulate { id, market, route, runs } => {
+            run_simulate(*id, get_client(cli, config, market, profile)?.as_ref(), &get_options(profile, None, route)?, *runs)
+        }
+        Command::Policy { id, market, route, max_stack } => {
+            run_policy(*id, get_client(cli, config, market, profile)?.as_ref(), &get_options(profile, None, route)?, *max_stack)
+        }
+        Command::Profiles => run_profiles(cli),
+        Command::Categories => run_categories(),
+        Command::History { id, market, level } => run_history(
+            *id,
+            get_client(cli, config, market, profile)?.as_ref(),
+            level,
+            &Catalogue::load_with_bundled(cli.catalogue.as_deref())?,
+        ),
+        Command::Queue { market } => {
+            run_queue(get_client(cli, config, market, profile)?.as_ref(), &Catalogue::load_with_bundled(cli.catalogue.as_deref())?)
+        }
+        Command::Movers { market, days, limit } => run_movers(
+            get_client(cli, config, market, profile)?.as_ref(),
+            *days,
+            *limit,
+            &Catalogue::load_with_bundled(cli.catalogue.as_deref())?,
+        ),
+        Command::Prices { ids, market } => {
+            run_prices(ids, get_client(cli, config, market, profile)?.as_ref(), &Catalogue::load_with_bundled(cli.catalogue.as_deref())?)
+        }
+        Command::Catalogue { action } => run_catalogue(cli, config, action, profile),
+        Command::Plan { market, scan, budget, risk_aversion, liquidity_share, max_per_item } => run_plan(
+            get_client(cli, config, market, profile)?.as_ref(),
+            &get_options(profile, Some(scan), &scan.route)?,
+            *budget,
+            *risk_aversion,
+            *liquidity_share,
+            *max_per_item,
+        ),
+        Command::Watch {
+            market,
+            scan,
+            interval,
+            cooldown,
+            alert_profit,
+            alert_roi,
+            alert_queue,
+            alert_drop,
+            drop_days,
+            notify,
+        } => {
+            let mut rules = Vec::new();
+            if let Some(min_profit_taxed) = alert_profit {
+                rules.push(AlertRule::Profit { min_profit_taxed: *min_profit_taxed, min_roi: *alert_roi });
+            }
+            if *alert_queue {
+                rules.push(AlertRule::NewInQueue);
+            }
+            if let Some(percent) = alert_drop {
+                rules.push(AlertRule::PriceDrop { percent: *percent, days: *drop_days });
+            }
+            if rules.is_empty() {
+                return Err(usage(String::from("At least one of --alert-profit, --alert-queue or --alert-drop is required.")));
+            }
+            run_watch(
+                get_client(cli, config, market, profile)?,
+                get_options(profile, Some(scan), &scan.route)?,
+                rules,
+                Duration::from_secs(*interval),
+                Duration::from_secs(*cooldown),
+                get_notifiers(notify)?,
+            )
+        }
+        #[cfg(feature = "sqlite")]
+        Command::Record { market, scan, db, interval } => run_record(
+            get_client(cli, config, market, profile)?.as_ref(),
+            &get_options(profile, Some(scan), &scan.route)?,
+            db,
+            interval.map(Duration::from_secs),
+        ),
+        #[cfg(feature = "sqlite")]
+        Command::Backtest {
+            market,
+            scan,
+            db,
+            days,
+            budget,
+            sell_delay,
+            max_trades_per_day,
+            simulate,
+            seed,
+            equity,
+            trades,
+        } => {
+            let strategy = Strategy {
+                options: get_options(profile, Some(scan), &scan.route)?,
+                budget: *budget,
+                sell_delay_days: *sell_delay,
+                max_trades_per_day: *max_trades_per_day,
+                simulate: *simulate,
+                seed: *seed,
+            };
+            run_backtest(&get_region(market, profile)?, db, *days, &strategy, equity.as_deref(), trades.as_deref())
+        }
+        #[cfg(feature = "server")]
+        Command::Serve { bind, threads, cache_ttl, max_runs, market, route } => {
+            let options = ServerOptions {
+                bind: bind.clone(),
+                threads: *threads,
+                region: get_region(market, profile)?,
+                route: get_options(profile, None, route)?,
+                max_runs: *max_runs,
+                // One client per region for the whole server, so rate limits and config logins apply to every request.
+                clients: REGIONS
+                    .iter()
+                    .map(|region| Ok((region.to_string(), Arc::from(new_client(cli, config, region)?))))
+                    .collect::<Result<_, String>>()?,
+            };
+            let cache = Arc::new(MarketCache::new(Duration::from_secs(*cache_ttl)));
+            eprintln!("Listening on http://{}", options.bind);
+            serve(options, cache)?;
+            Ok(0)
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    match run(&cli) {
+        Ok(code) => ExitCode::from(code),
+        Err(e) => {
+            eprintln!("Error: {}", e.message);
+            ExitCode::from(e.code)
+        }
+    }
 }
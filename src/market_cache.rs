@@ -0,0 +1,278 @@
+//! # Market cache
+//!
+//! Market cache keeps market responses for a while, so repeated lookups don't each make a request.
+//! Prices of many items can be refreshed together, with a handful of search requests.
+//!
+//! Requests are made through a [`MarketClient`], and responses are kept per region of the client.
+//! It can be shared between threads, for example behind an [`Arc`].
+//! A [`CachedClient`] puts the cache in front of a client, for code that takes a client itself.
+
+use crate::bdo_market_requests::market_client::MarketClient;
+use crate::bdo_market_requests::{
+    CategoryGivenInfo, HasId, HotItem, ItemBuySellInfo, ItemID, PriceHistory, RegQueueItem, SearchedItem, SpecificItemInfo,
+};
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+struct CacheEntry<V> {
+    fetched_at: Instant,
+    value: Arc<V>,
+}
+
+type CacheMap<K, V> = Mutex<HashMap<K, CacheEntry<V>>>;
+
+/// Most ids sent in a single search request. Longer lists are split into several requests.
+///
+pub const SEARCH_CHUNK_SIZE: usize = 100;
+
+/// Contains the outcome of refreshing prices, see [`MarketCache::refresh_prices`].
+///
+#[derive(Debug)]
+pub struct PriceRefresh {
+    requests: usize,
+    updated: usize,
+    missing: Vec<u32>,
+}
+
+impl PriceRefresh {
+    pub fn get_requests(&self) -> usize {
+        self.requests
+    }
+
+    pub fn get_updated(&self) -> usize {
+        self.updated
+    }
+
+    /// Ids the market didn't return, for example because they aren't sold on it.
+    ///
+    pub fn get_missing(&self) -> &[u32] {
+        &self.missing
+    }
+}
+
+/// Caches category listings, bid ladders, levels, prices and price histories per region.
+///
+pub struct MarketCache {
+    ttl: Duration,
+    categories: CacheMap<(String, u16, u16), Vec<CategoryGivenInfo>>,
+    items: CacheMap<(String, u32, u8), ItemBuySellInfo>,
+    levels: CacheMap<(String, u32), Vec<SpecificItemInfo>>,
+    prices: CacheMap<(String, u32), SearchedItem>,
+    histories: CacheMap<(String, u32, u8), PriceHistory>,
+}
+
+impl MarketCache {
+    /// Creates an empty cache, where responses are kept for `ttl`.
+    ///
+    pub fn new(ttl: Duration) -> Self {
+        MarketCache {
+            ttl,
+            categories: Mutex::new(HashMap::new()),
+            items: Mutex::new(HashMap::new()),
+            levels: Mutex::new(HashMap::new()),
+            prices: Mutex::new(HashMap::new()),
+            histories: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get_ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Gets every item in a category, see [`MarketClient::get_category`].
+    ///
+    pub fn get_category(&self, client: &dyn MarketClient, main_category: u16, sub_category: u16) -> Result<Arc<Vec<CategoryGivenInfo>>, String> {
+        let key = (client.get_region().to_owned(), main_category, sub_category);
+        get_or_fetch(&self.categories, key, self.ttl, || client.get_category(main_category, sub_category))
+    }
+
+    /// Gets an item's bid ladder at a level, see [`MarketClient::get_bids`].
+    ///
+    pub fn get_item(&self, client: &dyn MarketClient, item_id: u32, enhancement_level: u8) -> Result<Arc<ItemBuySellInfo>, String> {
+        let key = (client.get_region().to_owned(), item_id, enhancement_level);
+        get_or_fetch(&self.items, key, self.ttl, || client.get_bids(item_id, enhancement_level))
+    }
+
+    /// Gets every enhancement level of an item, see [`MarketClient::get_item_levels`].
+    ///
+    pub fn get_levels(&self, client: &dyn MarketClient, item_id: u32) -> Result<Arc<Vec<SpecificItemInfo>>, String> {
+        let key = (client.get_region().to_owned(), item_id);
+        get_or_fetch(&self.levels, key, self.ttl, || client.get_item_levels(item_id))
+    }
+
+    /// Gets an item's base price, stock and trades, if they were refreshed within the ttl.
+    ///
+    pub fn get_price(&self, region: &str, item_id: u32) -> Option<Arc<SearchedItem>> {
+        let key = (region.to_owned(), item_id);
+        lock(&self.prices)
+            .get(&key)
+            .filter(|entry| entry.fetched_at.elapsed() < self.ttl)
+            .map(|entry| Arc::clone(&entry.value))
+    }
+
+    /// Fetches the prices of many items, a chunk of [`SEARCH_CHUNK_SIZE`] ids per request, and caches them.
+    ///
+    /// Repeated ids are only asked for once.
+    ///
+    pub fn refresh_prices(&self, client: &dyn MarketClient, item_ids: &[ItemID]) -> Result<PriceRefresh, String> {
+        let mut ids: Vec<u32> = Vec::new();
+        for id in item_ids {
+            if !ids.contains(&id.get_item_id()) {
+                ids.push(id.get_item_id());
+            }
+        }
+
+        let mut refresh = PriceRefresh {
+            requests: 0,
+            updated: 0,
+            missing: Vec::new(),
+        };
+        for chunk in ids.chunks(SEARCH_CHUNK_SIZE) {
+            let found = client.search_items(chunk)?;
+            refresh.requests += 1;
+            refresh.updated += found.len();
+            refresh.missing.extend(self.insert_prices(client.get_region(), chunk, found));
+        }
+        Ok(refresh)
+    }
+
+    /// Caches prices that were searched for, and returns the requested ids that weren't found.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bdo_enhancement_profit_calculator::bdo_market_requests::SearchedItem;
+    /// use bdo_enhancement_profit_calculator::market_cache::MarketCache;
+    /// use std::time::Duration;
+    ///
+    /// let cache = MarketCache::new(Duration::from_secs(60));
+    /// let found = SearchedItem::build_vec(String::from("11607-3-250000000-9000|11853-0-90000000-400"));
+    ///
+    /// let missing = cache.insert_prices("eu", &[11607, 11853, 1], found);
+    ///
+    /// assert_eq!(vec![1], missing);
+    /// assert_eq!(250000000, cache.get_price("eu", 11607).unwrap().get_base_price());
+    /// assert!(cache.get_price("na", 11607).is_none());
+    /// ```
+    ///
+    pub fn insert_prices(&self, region: &str, requested: &[u32], found: Vec<SearchedItem>) -> Vec<u32> {
+        let mut prices = lock(&self.prices);
+        let fetched_at = Instant::now();
+        let found_ids: Vec<u32> = found.iter().map(|item| item.get_item_id()).collect();
+        for item in found {
+            let key = (region.to_owned(), item.get_item_id());
+            prices.insert(key, CacheEntry { fetched_at, value: Arc::new(item) });
+        }
+        requested.iter().filter(|id| !found_ids.contains(id)).copied().collect()
+    }
+
+    /// Gets an item's price history at a level, see [`MarketClient::get_history`].
+    ///
+    pub fn get_history(&self, client: &dyn MarketClient, item_id: u32, enhancement_level: u8) -> Result<Arc<PriceHistory>, String> {
+        let key = (client.get_region().to_owned(), item_id, enhancement_level);
+        get_or_fetch(&self.histories, key, self.ttl, || client.get_history(item_id, enhancement_level))
+    }
+
+    /// Drops every response older than the ttl.
+    ///
+    pub fn remove_expired(&self) {
+        remove_expired(&self.categories, self.ttl);
+        remove_expired(&self.items, self.ttl);
+        remove_expired(&self.levels, self.ttl);
+        remove_expired(&self.prices, self.ttl);
+        remove_expired(&self.histories, self.ttl);
+    }
+}
+
+/// A client that answers from a [`MarketCache`] where it can, and makes the request through `client` otherwise.
+///
+/// The registration queue, hot list and searches aren't cached, so they always make a request.
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::bdo_market_requests::market_client::{MarketClient, PcMarket};
+/// use bdo_enhancement_profit_calculator::market_cache::{CachedClient, MarketCache};
+/// use std::time::Duration;
+///
+/// let cache = MarketCache::new(Duration::from_secs(60));
+/// let client = PcMarket::new("eu");
+/// let cached = CachedClient::new(&client, &cache);
+///
+/// assert_eq!("eu", cached.get_region());
+/// ```
+///
+pub struct CachedClient<'a> {
+    client: &'a dyn MarketClient,
+    cache: &'a MarketCache,
+}
+
+impl<'a> CachedClient<'a> {
+    pub fn new(client: &'a dyn MarketClient, cache: &'a MarketCache) -> Self {
+        CachedClient { client, cache }
+    }
+}
+
+impl MarketClient for CachedClient<'_> {
+    fn get_region(&self) -> &str {
+        self.client.get_region()
+    }
+
+    fn get_category(&self, main_category: u16, sub_category: u16) -> Result<Vec<CategoryGivenInfo>, String> {
+        Ok(self.cache.get_category(self.client, main_category, sub_category)?.to_vec())
+    }
+
+    fn get_item_levels(&self, item_id: u32) -> Result<Vec<SpecificItemInfo>, String> {
+        Ok(self.cache.get_levels(self.client, item_id)?.to_vec())
+    }
+
+    fn get_bids(&self, item_id: u32, enhancement_level: u8) -> Result<ItemBuySellInfo, String> {
+        Ok(self.cache.get_item(self.client, item_id, enhancement_level)?.as_ref().clone())
+    }
+
+    fn get_history(&self, item_id: u32, enhancement_level: u8) -> Result<PriceHistory, String> {
+        Ok(self.cache.get_history(self.client, item_id, enhancement_level)?.as_ref().clone())
+    }
+
+    fn get_registration_queue(&self) -> Result<Vec<RegQueueItem>, String> {
+        self.client.get_registration_queue()
+    }
+
+    fn get_hot_list(&self) -> Result<Vec<HotItem>, String> {
+        self.client.get_hot_list()
+    }
+
+    fn search_items(&self, item_ids: &[u32]) -> Result<Vec<SearchedItem>, String> {
+        self.client.search_items(item_ids)
+    }
+}
+
+fn get_or_fetch<K, V, F>(map: &CacheMap<K, V>, key: K, ttl: Duration, fetch: F) -> Result<Arc<V>, String>
+where
+    K: Eq + Hash,
+    F: FnOnce() -> Result<V, String>,
+{
+    if let Some(entry) = lock(map).get(&key) {
+        if entry.fetched_at.elapsed() < ttl {
+            return Ok(Arc::clone(&entry.value));
+        }
+    }
+
+    // Fetched without holding the lock, so a slow request doesn't hold up other lookups.
+    let value = Arc::new(fetch()?);
+    lock(map).insert(key, CacheEntry { fetched_at: Instant::now(), value: Arc::clone(&value) });
+    Ok(value)
+}
+
+fn remove_expired<K, V>(map: &CacheMap<K, V>, ttl: Duration) {
+    lock(map).retain(|_, entry| entry.fetched_at.elapsed() < ttl);
+}
+
+/// Locks a map, ignoring poisoning. Entries are only ever inserted whole, so a panic can't leave one half written.
+///
+fn lock<K, V>(map: &CacheMap<K, V>) -> std::sync::MutexGuard<'_, HashMap<K, CacheEntry<V>>> {
+    map.lock().unwrap_or_else(|e| e.into_inner())
+}
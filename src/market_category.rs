@@ -0,0 +1,410 @@
+//! # Market Category
+//!
+//! Market Category names the central market's main and sub categories, and how the items in each enhance.
+//!
+//! The numbers are the market's own `mainCategory` and `subCategory` values, so categories can be chosen
+//! by name (`ring`, `accessories`, `armor/helmet`) instead of by number.
+//! Pairs missing from the table can still be given as numbers, such as `20/1`.
+
+use crate::catalogue::EnhancementType;
+
+/// A top level category, and the sub categories in it.
+///
+#[derive(Debug)]
+pub struct MainCategory {
+    id: u16,
+    name: &'static str,
+    enhancement_type: EnhancementType,
+    sub_categories: &'static [(u16, &'static str)],
+}
+
+impl MainCategory {
+    pub fn get_id(&self) -> u16 {
+        self.id
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Gets how items in this category are usually enhanced.
+    ///
+    pub fn get_enhancement_type(&self) -> EnhancementType {
+        self.enhancement_type
+    }
+
+    pub fn get_sub_categories(&'static self) -> Vec<MarketCategory> {
+        self.sub_categories
+            .iter()
+            .map(|(sub, name)| MarketCategory { main: self, sub: *sub, name })
+            .collect()
+    }
+}
+
+/// Every main category on the central market.
+///
+pub const MAIN_CATEGORIES: [MainCategory; 16] = [
+    MainCategory {
+        id: 1,
+        name: "Main Weapon",
+        enhancement_type: EnhancementType::Gear,
+        sub_categories: &[
+            (1, "Longsword"),
+            (2, "Longbow"),
+            (3, "Amulet"),
+            (4, "Axe"),
+            (5, "Shortsword"),
+            (6, "Blade"),
+            (7, "Staff"),
+            (8, "Kriegsmesser"),
+            (9, "Gauntlet"),
+            (10, "Crescent Pendulum"),
+            (11, "Crossbow"),
+            (12, "Florang"),
+            (13, "Battle Axe"),
+            (14, "Shamshir"),
+            (15, "Morning Star"),
+            (16, "Kyve"),
+            (17, "Serenaca"),
+        ],
+    },
+    MainCategory {
+        id: 5,
+        name: "Sub Weapon",
+        enhancement_type: EnhancementType::Gear,
+        sub_categories: &[
+            (1, "Shield"),
+            (2, "Dagger"),
+            (3, "Talisman"),
+            (4, "Ornamental Knot"),
+            (5, "Trinket"),
+            (6, "Horn Bow"),
+            (7, "Kunai"),
+            (8, "Shuriken"),
+            (9, "Vambrace"),
+            (10, "Noble Sword"),
+            (11, "Ra'ghon"),
+            (12, "Vitclari"),
+            (13, "Haladie"),
+        ],
+    },
+    MainCategory {
+        id: 10,
+        name: "Awakening Weapon",
+        enhancement_type: EnhancementType::Gear,
+        sub_categories: &[
+            (1, "Great Sword"),
+            (2, "Scythe"),
+            (3, "Iron Buster"),
+            (4, "Kamasylven Sword"),
+            (5, "Celestial Bo Staff"),
+            (6, "Lancia"),
+            (7, "Crescent Blade"),
+            (8, "Kerispear"),
+            (9, "Sura Katana"),
+            (10, "Sah Chakram"),
+            (11, "Aad Sphera"),
+            (12, "Godr Sphera"),
+            (13, "Vediant"),
+            (14, "Gardbrace"),
+            (15, "Cestus"),
+            (16, "Crimson Glaives"),
+            (17, "Greatbow"),
+            (18, "Jordun"),
+            (19, "Dual Glaives"),
+        ],
+    },
+    MainCategory {
+        id: 15,
+        name: "Armor",
+        enhancement_type: EnhancementType::Gear,
+        sub_categories: &[(1, "Helmet"), (2, "Armor"), (3, "Gloves"), (4, "Shoes"), (6, "Versatile")],
+    },
+    MainCategory {
+        id: 20,
+        name: "Accessories",
+        enhancement_type: EnhancementType::Accessory,
+        sub_categories: &[(1, "Ring"), (2, "Necklace"), (3, "Earring"), (4, "Belt")],
+    },
+    MainCategory {
+        id: 25,
+        name: "Material",
+        enhancement_type: EnhancementType::None,
+        sub_categories: &[
+            (1, "Ore and Gem"),
+            (2, "Plants"),
+            (3, "Seed and Fruit"),
+            (4, "Leather"),
+            (5, "Blood"),
+            (6, "Meat"),
+            (7, "Seafood"),
+            (8, "Misc"),
+        ],
+    },
+    MainCategory {
+        id: 30,
+        name: "Enhancement",
+        enhancement_type: EnhancementType::None,
+        sub_categories: &[(1, "Black Stone"), (2, "Upgrade")],
+    },
+    MainCategory {
+        id: 35,
+        name: "Consumables",
+        enhancement_type: EnhancementType::None,
+        sub_categories: &[
+            (1, "Offensive Elixir"),
+            (2, "Defensive Elixir"),
+            (3, "Functional Elixir"),
+            (4, "Food"),
+            (5, "Potion"),
+            (6, "Siege Items"),
+            (7, "Item Parts"),
+            (8, "Other Consumables"),
+        ],
+    },
+    MainCategory {
+        id: 40,
+        name: "Life Tools",
+        enhancement_type: EnhancementType::Gear,
+        sub_categories: &[
+            (1, "Lumbering Axe"),
+            (2, "Fluid Collector"),
+            (3, "Butcher Knife"),
+            (4, "Pickaxe"),
+            (5, "Hoe"),
+            (6, "Tanning Knife"),
+            (7, "Fishing Tools"),
+            (8, "Matchlock"),
+            (9, "Alchemy Tools"),
+            (10, "Cooking Tools"),
+        ],
+    },
+    MainCategory {
+        id: 45,
+        name: "Alchemy Stone",
+        enhancement_type: EnhancementType::None,
+        sub_categories: &[(1, "Destruction"), (2, "Protection"), (3, "Life"), (4, "Magic Crystal")],
+    },
+    MainCategory {
+        id: 50,
+        name: "Crystal",
+        enhancement_type: EnhancementType::None,
+        sub_categories: &[
+            (1, "Main Weapon"),
+            (2, "Sub Weapon"),
+            (3, "Helmet"),
+            (4, "Armor"),
+            (5, "Gloves"),
+            (6, "Shoes"),
+            (7, "Versatile"),
+        ],
+    },
+    MainCategory {
+        id: 55,
+        name: "Mount",
+        enhancement_type: EnhancementType::None,
+        sub_categories: &[
+            (1, "Registration"),
+            (2, "Feed"),
+            (3, "Champron"),
+            (4, "Barding"),
+            (5, "Saddle"),
+            (6, "Stirrups"),
+            (7, "Horseshoe"),
+        ],
+    },
+    MainCategory {
+        id: 60,
+        name: "Ship",
+        enhancement_type: EnhancementType::None,
+        sub_categories: &[
+            (1, "Registration"),
+            (2, "Cargo"),
+            (3, "Prow"),
+            (4, "Plating"),
+            (5, "Cannon"),
+            (6, "Sail"),
+        ],
+    },
+    MainCategory {
+        id: 65,
+        name: "Wagon",
+        enhancement_type: EnhancementType::None,
+        sub_categories: &[(1, "Registration"), (2, "Wheel"), (3, "Cover"), (4, "Flag"), (5, "Emblem"), (6, "Lamp")],
+    },
+    MainCategory {
+        id: 70,
+        name: "Furniture",
+        enhancement_type: EnhancementType::None,
+        sub_categories: &[
+            (1, "Bed"),
+            (2, "Table"),
+            (3, "Chair"),
+            (4, "Cabinet"),
+            (5, "Bookcase"),
+            (6, "Wardrobe"),
+            (7, "Lamp"),
+            (8, "Floor"),
+            (9, "Wall"),
+            (10, "Ornament"),
+        ],
+    },
+    MainCategory {
+        id: 75,
+        name: "Lightstone",
+        enhancement_type: EnhancementType::None,
+        sub_categories: &[(1, "Flame"), (2, "Earth"), (3, "Wind"), (4, "Lightning"), (5, "Combo")],
+    },
+];
+
+/// A main category and one of its sub categories.
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::catalogue::EnhancementType;
+/// use bdo_enhancement_profit_calculator::market_category::MarketCategory;
+///
+/// let ring = MarketCategory::find(20, 1).unwrap();
+/// assert_eq!("Accessories/Ring", ring.get_path());
+/// assert_eq!(EnhancementType::Accessory, ring.get_enhancement_type());
+///
+/// let ids: Vec<(u16, u16)> = MarketCategory::accessories().iter().map(|c| c.get_ids()).collect();
+/// assert_eq!(vec![(20, 1), (20, 2), (20, 3), (20, 4)], ids);
+///
+/// // Names ignore case and spaces, and a main category stands for all of its sub categories.
+/// assert_eq!(vec![(20, 2), (20, 4)], MarketCategory::parse_list(&["necklace", "Accessories/BELT"]).unwrap());
+/// assert_eq!(4, MarketCategory::parse_list(&["accessories"]).unwrap().len());
+/// assert_eq!(vec![(20, 9)], MarketCategory::parse_list(&["20/9"]).unwrap());
+///
+/// // "Helmet" is both armour and a crystal.
+/// assert!(MarketCategory::parse_list(&["helmet"]).is_err());
+/// assert!(MarketCategory::parse_list(&["armor/helmet"]).is_ok());
+/// ```
+///
+#[derive(Debug, Clone, Copy)]
+pub struct MarketCategory {
+    main: &'static MainCategory,
+    sub: u16,
+    name: &'static str,
+}
+
+impl PartialEq for MarketCategory {
+    fn eq(&self, other: &Self) -> bool {
+        self.get_ids() == other.get_ids()
+    }
+}
+
+impl Eq for MarketCategory {}
+
+impl MarketCategory {
+    /// Gets every sub category in the table.
+    ///
+    pub fn all() -> Vec<MarketCategory> {
+        MAIN_CATEGORIES.iter().flat_map(|m| m.get_sub_categories()).collect()
+    }
+
+    /// Gets the ring, necklace, earring and belt categories.
+    ///
+    pub fn accessories() -> Vec<MarketCategory> {
+        MarketCategory::all()
+            .into_iter()
+            .filter(|c| c.get_enhancement_type() == EnhancementType::Accessory)
+            .collect()
+    }
+
+    pub fn find(main: u16, sub: u16) -> Option<MarketCategory> {
+        MarketCategory::all().into_iter().find(|c| c.get_ids() == (main, sub))
+    }
+
+    pub fn find_main(main: u16) -> Option<&'static MainCategory> {
+        MAIN_CATEGORIES.iter().find(|m| m.id == main)
+    }
+
+    /// Finds the categories a name stands for.
+    ///
+    /// A name can be a main category (every sub category in it), a sub category if only one has that name,
+    /// `main/sub` with either part a name or a number, or a pair of numbers missing from the table.
+    ///
+    pub fn parse(text: &str) -> Result<Vec<(u16, u16)>, String> {
+        if let Some((main, sub)) = text.split_once('/') {
+            if let (Ok(main), Ok(sub)) = (main.trim().parse::<u16>(), sub.trim().parse::<u16>()) {
+                return Ok(vec![(main, sub)]);
+            }
+            let main = MAIN_CATEGORIES
+                .iter()
+                .find(|m| main.trim().parse::<u16>() == Ok(m.id) || names_match(m.name, main))
+                .ok_or_else(|| format!("Unknown main category \"{}\".", main))?;
+            let category = main
+                .get_sub_categories()
+                .into_iter()
+                .find(|c| sub.trim().parse::<u16>() == Ok(c.sub) || names_match(c.name, sub))
+                .ok_or_else(|| format!("{} has no sub category \"{}\".", main.name, sub))?;
+            return Ok(vec![category.get_ids()]);
+        }
+
+        if let Some(main) = MAIN_CATEGORIES.iter().find(|m| names_match(m.name, text)) {
+            return Ok(main.get_sub_categories().iter().map(|c| c.get_ids()).collect());
+        }
+        let found: Vec<MarketCategory> = MarketCategory::all().into_iter().filter(|c| names_match(c.name, text)).collect();
+        match found.as_slice() {
+            [] => Err(format!("Unknown category \"{}\", run with `categories` to list them.", text)),
+            [category] => Ok(vec![category.get_ids()]),
+            _ => Err(format!(
+                "\"{}\" could be any of: {}.",
+                text,
+                found.iter().map(|c| c.get_path()).collect::<Vec<String>>().join(", ")
+            )),
+        }
+    }
+
+    /// Parses several names, see [`MarketCategory::parse`], without repeating a category.
+    ///
+    pub fn parse_list<S: AsRef<str>>(texts: &[S]) -> Result<Vec<(u16, u16)>, String> {
+        let mut categories = Vec::new();
+        for text in texts {
+            for category in MarketCategory::parse(text.as_ref())? {
+                if !categories.contains(&category) {
+                    categories.push(category);
+                }
+            }
+        }
+        Ok(categories)
+    }
+
+    /// Gets the main and sub category numbers, as the market expects them.
+    ///
+    pub fn get_ids(&self) -> (u16, u16) {
+        (self.main.id, self.sub)
+    }
+
+    pub fn get_parent(&self) -> &'static MainCategory {
+        self.main
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Gets the name with its parent's, such as `Accessories/Ring`.
+    ///
+    pub fn get_path(&self) -> String {
+        format!("{}/{}", self.main.name, self.name)
+    }
+
+    pub fn get_enhancement_type(&self) -> EnhancementType {
+        self.main.enhancement_type
+    }
+}
+
+/// Compares names ignoring case, spaces, dashes and underscores.
+///
+fn names_match(name: &str, text: &str) -> bool {
+    let normalise = |s: &str| -> String {
+        s.chars()
+            .filter(|c| !matches!(c, ' ' | '-' | '_'))
+            .flat_map(|c| c.to_lowercase())
+            .collect()
+    };
+    normalise(name) == normalise(text)
+}
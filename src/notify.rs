@@ -0,0 +1,413 @@
+//! # Notify
+//!
+//! Notify sends [`Alert`]s somewhere they'll be seen: stdout, the desktop, an NDJSON log file, or a webhook.
+//!
+//! Message bodies can be written as a [`Template`], with the alert's fields in braces.
+
+use crate::report::SCHEMA_VERSION;
+use crate::watch::Alert;
+
+use reqwest::blocking::Client;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+/// Something alerts can be sent to.
+///
+pub trait Notifier {
+    fn notify(&mut self, alert: &Alert) -> Result<(), String>;
+}
+
+/// Fields a template can use. The profit fields are empty for alerts without a scan result.
+///
+pub const TEMPLATE_FIELDS: [&str; 17] = [
+    "rule", "region", "item_id", "name", "message", "fired_at", "start_level", "end_level", "stacks",
+    "base_price", "make_cost", "actual_value", "profit", "profit_taxed", "roi", "roi_percent", "stock",
+];
+
+enum TemplatePart {
+    Text(String),
+    Field(String),
+}
+
+/// A message body, with fields written as `{name}` and a literal brace as `{{` or `}}`.
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::notify::Template;
+/// use bdo_enhancement_profit_calculator::report::ReportRow;
+/// use bdo_enhancement_profit_calculator::watch::Alert;
+///
+/// let template = Template::parse("{name} -> {end_level}: {profit_taxed} ({roi_percent}%) {{{region}}}").unwrap();
+///
+/// let alert = Alert {
+///     rule: String::from("profit"),
+///     region: String::from("eu"),
+///     item_id: 11607,
+///     level: 4,
+///     name: String::from("Ring"),
+///     message: String::from("Ring makes 25000 after tax."),
+///     fired_at: 0,
+///     result: Some(ReportRow {
+///         item_id: 11607,
+///         name: String::from("Ring"),
+///         start_level: 0,
+///         end_level: 4,
+///         stacks: vec![20, 40, 44, 110],
+///         base_price: 1000,
+///         make_cost: 100000,
+///         actual_value: 150000,
+///         profit: 50000,
+///         profit_taxed: 27725,
+///         stock: 3,
+///         sale_chance: None,
+///         cap_state: None,
+///     }),
+/// };
+/// assert_eq!("Ring -> 4: 27725 (27.7%) {eu}", template.render(&alert));
+///
+/// assert!(Template::parse("{colour}").is_err());
+/// assert!(Template::parse("{name").is_err());
+/// ```
+///
+pub struct Template {
+    parts: Vec<TemplatePart>,
+}
+
+impl Template {
+    /// Parses a template, checking every field is one of [`TEMPLATE_FIELDS`].
+    ///
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut field = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => field.push(c),
+                            None => return Err(format!("The template \"{}\" has an unclosed {{.", template)),
+                        }
+                    }
+                    if !TEMPLATE_FIELDS.contains(&field.as_str()) {
+                        return Err(format!("Unknown template field \"{}\", expected one of: {}.", field, TEMPLATE_FIELDS.join(", ")));
+                    }
+                    if !text.is_empty() {
+                        parts.push(TemplatePart::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(TemplatePart::Field(field));
+                }
+                '}' => return Err(format!("The template \"{}\" has an unmatched }}, write }}}} for a literal one.", template)),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(TemplatePart::Text(text));
+        }
+
+        Ok(Template { parts })
+    }
+
+    pub fn render(&self, alert: &Alert) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                TemplatePart::Text(text) => text.clone(),
+                TemplatePart::Field(field) => get_field(alert, field),
+            })
+            .collect()
+    }
+}
+
+impl Default for Template {
+    fn default() -> Self {
+        Template::parse("[{fired_at}] {rule}: {message}").unwrap() // Only uses known fields.
+    }
+}
+
+fn get_field(alert: &Alert, field: &str) -> String {
+    match field {
+        "rule" => return alert.rule.clone(),
+        "region" => return alert.region.clone(),
+        "item_id" => return alert.item_id.to_string(),
+        "name" => return alert.name.clone(),
+        "message" => return alert.message.clone(),
+        "fired_at" => return alert.fired_at.to_string(),
+        _ => {}
+    }
+
+    let row = match &alert.result {
+        Some(v) => v,
+        None => return String::new(),
+    };
+    let roi = if row.make_cost == 0 { 0.0 } else { row.profit_taxed as f64 / row.make_cost as f64 };
+    match field {
+        "start_level" => row.start_level.to_string(),
+        "end_level" => row.end_level.to_string(),
+        "stacks" => row.stacks.iter().map(|s| s.to_string()).collect::<Vec<String>>().join(","),
+        "base_price" => row.base_price.to_string(),
+        "make_cost" => row.make_cost.to_string(),
+        "actual_value" => row.actual_value.to_string(),
+        "profit" => row.profit.to_string(),
+        "profit_taxed" => row.profit_taxed.to_string(),
+        "roi" => format!("{:.3}", roi),
+        "roi_percent" => format!("{:.1}", roi * 100.0),
+        "stock" => row.stock.to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Prints each alert on its own line.
+///
+pub struct StdoutNotifier {
+    template: Template,
+}
+
+impl StdoutNotifier {
+    pub fn new(template: Template) -> Self {
+        StdoutNotifier { template }
+    }
+}
+
+impl Notifier for StdoutNotifier {
+    fn notify(&mut self, alert: &Alert) -> Result<(), String> {
+        let mut stdout = io::stdout().lock();
+        writeln!(stdout, "{}", self.template.render(alert)).map_err(|e| e.to_string())
+    }
+}
+
+/// Shows each alert as a desktop notification, titled with the item's name, through `notify-send`.
+///
+/// Needs a notification daemon, as found on most Linux desktops.
+///
+pub struct DesktopNotifier {
+    template: Template,
+}
+
+impl DesktopNotifier {
+    pub fn new(template: Template) -> Self {
+        DesktopNotifier { template }
+    }
+}
+
+impl Notifier for DesktopNotifier {
+    fn notify(&mut self, alert: &Alert) -> Result<(), String> {
+        let status = Command::new("notify-send")
+            .arg("--app-name=bdo_enhancement_profit_calculator")
+            .arg(&alert.name)
+            .arg(self.template.render(alert))
+            .status()
+            .map_err(|e| format!("Could not run notify-send: {}", e))?;
+        match status.success() {
+            true => Ok(()),
+            false => Err(format!("notify-send failed with {}", status)),
+        }
+    }
+}
+
+/// Writes each alert as a line of JSON, in the same versioned format as [`report`](crate::report).
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::notify::{NdjsonNotifier, Notifier};
+/// use bdo_enhancement_profit_calculator::watch::Alert;
+///
+/// let alert = Alert {
+///     rule: String::from("queue"),
+///     region: String::from("eu"),
+///     item_id: 11607,
+///     level: 4,
+///     name: String::from("Ring"),
+///     message: String::from("Ring at level 4 is waiting to be registered for 250000000."),
+///     fired_at: 0,
+///     result: None,
+/// };
+///
+/// let mut notifier = NdjsonNotifier::new(Vec::new());
+/// notifier.notify(&alert).unwrap();
+///
+/// let line = String::from_utf8(notifier.into_inner()).unwrap();
+/// assert!(line.starts_with("{\"schema_version\":1,\"rule\":\"queue\""));
+/// assert!(line.ends_with("\"result\":null}\n"));
+/// ```
+///
+pub struct NdjsonNotifier<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> NdjsonNotifier<W> {
+    pub fn new(writer: W) -> Self {
+        NdjsonNotifier { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl NdjsonNotifier<BufWriter<File>> {
+    /// Opens a log file to append alerts to, creating it if it doesn't exist.
+    ///
+    pub fn append(path: &Path) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+        Ok(NdjsonNotifier::new(BufWriter::new(file)))
+    }
+}
+
+/// An alert with the schema version, as written to logs and plain webhooks.
+///
+#[derive(Serialize)]
+struct NdjsonAlert<'a> {
+    schema_version: u32,
+    #[serde(flatten)]
+    alert: &'a Alert,
+}
+
+impl<'a> NdjsonAlert<'a> {
+    fn new(alert: &'a Alert) -> Self {
+        NdjsonAlert {
+            schema_version: SCHEMA_VERSION,
+            alert,
+        }
+    }
+}
+
+impl<W: Write> Notifier for NdjsonNotifier<W> {
+    fn notify(&mut self, alert: &Alert) -> Result<(), String> {
+        serde_json::to_writer(&mut self.writer, &NdjsonAlert::new(alert)).map_err(|e| e.to_string())?;
+        writeln!(self.writer).map_err(|e| e.to_string())?;
+        // Flushed every time, so alerts can be followed with `tail -f`.
+        self.writer.flush().map_err(|e| e.to_string())
+    }
+}
+
+/// Payload shapes chat services accept on their incoming webhooks.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatFormat {
+    /// `{"content": "..."}`
+    Discord,
+    /// `{"text": "..."}`
+    Slack,
+}
+
+/// Posts alerts to a webhook.
+///
+/// A plain webhook gets the whole alert as JSON, in the same versioned format as [`NdjsonNotifier`]. A chat webhook gets the rendered template,
+/// in the shape its service expects.
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::notify::{ChatFormat, Notifier, Template, WebhookNotifier};
+/// use bdo_enhancement_profit_calculator::watch::Alert;
+/// use std::io::Read;
+///
+/// // A local stand-in for the chat service.
+/// let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+/// let url = format!("http://{}/hook", server.server_addr().to_ip().unwrap());
+/// let received = std::thread::spawn(move || {
+///     let mut request = server.recv().unwrap();
+///     let mut body = String::new();
+///     request.as_reader().read_to_string(&mut body).unwrap();
+///     request.respond(tiny_http::Response::empty(204)).unwrap();
+///     body
+/// });
+///
+/// let alert = Alert {
+///     rule: String::from("price_drop"),
+///     region: String::from("eu"),
+///     item_id: 11607,
+///     level: 4,
+///     name: String::from("Ring"),
+///     message: String::from("Ring is at 800, 20.0% below its 7 day average of 1000."),
+///     fired_at: 0,
+///     result: None,
+/// };
+///
+/// let mut notifier = WebhookNotifier::chat(&url, ChatFormat::Discord, Template::parse("{name}: {message}").unwrap());
+/// notifier.notify(&alert).unwrap();
+///
+/// assert_eq!(
+///     r#"{"content":"Ring: Ring is at 800, 20.0% below its 7 day average of 1000."}"#,
+///     received.join().unwrap()
+/// );
+/// ```
+///
+pub struct WebhookNotifier {
+    url: String,
+    chat: Option<(ChatFormat, Template)>,
+    client: Client,
+}
+
+impl WebhookNotifier {
+    /// Creates a notifier that posts the whole alert as JSON.
+    ///
+    pub fn json(url: &str) -> Self {
+        WebhookNotifier {
+            url: url.to_owned(),
+            chat: None,
+            client: new_client(),
+        }
+    }
+
+    /// Creates a notifier that posts the rendered template as a chat message.
+    ///
+    pub fn chat(url: &str, format: ChatFormat, template: Template) -> Self {
+        WebhookNotifier {
+            url: url.to_owned(),
+            chat: Some((format, template)),
+            client: new_client(),
+        }
+    }
+
+    fn get_payload(&self, alert: &Alert) -> Result<Value, String> {
+        match &self.chat {
+            Some((ChatFormat::Discord, template)) => Ok(json!({ "content": template.render(alert) })),
+            Some((ChatFormat::Slack, template)) => Ok(json!({ "text": template.render(alert) })),
+            None => serde_json::to_value(NdjsonAlert::new(alert)).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn notify(&mut self, alert: &Alert) -> Result<(), String> {
+        let payload = self.get_payload(alert)?;
+        self.client
+            .post(&self.url)
+            .json(&payload)
+            .send()
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("Could not post to {}: {}", self.url, e))?;
+        Ok(())
+    }
+}
+
+fn new_client() -> Client {
+    Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap_or_else(|_| Client::new())
+}
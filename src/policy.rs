@@ -0,0 +1,315 @@
+//! # Policy
+//!
+//! Policy works out, after every tap, whether an accessory is worth more sold or tapped again.
+//!
+//! A fixed route commits to its end level up front, but a player holding a fresh TRI can still sell it.
+//! [`solve_policy`] finds the best choice for every state of the chain, a level held and the stack that would
+//! be used on it, by working back from the highest level:
+//!
+//! - Selling is worth the level's expected sale value after tax, so levels that rarely sell are worth less.
+//! - Tapping uses a base accessory, and succeeds into the next level at its planned stack. A failure loses the
+//!   accessory and adds to the stack (see [`FAILSTACK_GAINS`]), after which another is bought at the market price,
+//!   but only when the state it leads to is worth more than it costs.
+//!
+//! Stacks stop growing at a maximum, where the tap is repeated at the same stack.
+//! Every fixed target is priced in the same way, always tapping and buying again after a failure,
+//! so the policy can be compared with them.
+
+use crate::accessories::{calc_accessory_chance, AccMarketData};
+use crate::general_calcs::market_calcs::{estimate_sell_price, get_cap_state, CapState};
+use crate::scan::ScanOptions;
+use crate::units::{Rounding, Silver};
+
+/// Stacks gained by failing a tap from each level, base first.
+pub const FAILSTACK_GAINS: [u16; 5] = [2, 3, 4, 5, 6];
+
+/// What an accessory at a level can be bought and sold for.
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LevelPrices {
+    /// Cost of buying one, `None` if it isn't on the market.
+    pub buy_price: Option<Silver>,
+    /// Expected sale value before tax, see [`SellEstimate`](crate::general_calcs::market_calcs::SellEstimate).
+    /// `None` if it can't be sold.
+    pub sell_value: Option<Silver>,
+}
+
+/// Gets the prices of every level from the base to `max_level`, requesting the ladders of capped levels.
+///
+pub fn get_level_prices(market: &mut AccMarketData, max_level: u8) -> Vec<LevelPrices> {
+    (0..=max_level)
+        .map(|level| {
+            let (price, cap_min, cap_max, buy_price) = match market.get_level(level) {
+                Some(v) => (v.get_base_price(), v.get_price_cap_min(), v.get_price_cap_max(), v.get_lowest_listed()),
+                None => return LevelPrices::default(),
+            };
+            let bids = match get_cap_state(price, cap_min, cap_max) {
+                CapState::FreeFloating => &[][..],
+                _ => match market.get_ladder(level) {
+                    Some(v) => v.get_bids(),
+                    None => return LevelPrices { buy_price: Some(Silver::from(buy_price)), sell_value: None },
+                },
+            };
+            LevelPrices {
+                buy_price: Some(Silver::from(buy_price)),
+                sell_value: Some(estimate_sell_price(price, cap_min, cap_max, bids).get_expected_value()),
+            }
+        })
+        .collect()
+}
+
+/// The choice to make while holding an accessory.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyAction {
+    /// Sell it, or keep it if it can't be sold.
+    Sell,
+    /// Tap it to the next level.
+    Tap,
+}
+
+/// The best choice while holding an accessory at a level, with a stack ready for it.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PolicyState {
+    pub level: u8,
+    pub stack: u16,
+    pub action: PolicyAction,
+    /// Whether to buy another accessory at this level after a failed tap.
+    pub rebuy_on_failure: bool,
+    /// Expected silver the accessory ends up as, after tax.
+    pub value: Silver,
+}
+
+/// A fixed route, priced the same way as the policy.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FixedTargetPlan {
+    pub end_level: u8,
+    /// Expected profit after tax, `None` if the end level can't be sold.
+    pub expected_profit: Option<Silver>,
+}
+
+/// The best choice at every state of a chain, and what it's worth against fixed routes.
+///
+pub struct StoppingPolicy {
+    start_level: u8,
+    states: Vec<PolicyState>,
+    expected_profit: Silver,
+    fixed_plans: Vec<FixedTargetPlan>,
+}
+
+impl StoppingPolicy {
+    /// Gets every state, by level and then by stack.
+    ///
+    pub fn get_states(&self) -> &[PolicyState] {
+        &self.states
+    }
+
+    /// Gets the state of a level at a stack, or at the highest stack below it.
+    ///
+    pub fn find_state(&self, level: u8, stack: u16) -> Option<&PolicyState> {
+        self.states.iter().rev().find(|s| s.level == level && s.stack <= stack)
+    }
+
+    /// Gets the expected profit after tax of buying the starting accessory and following the policy.
+    ///
+    pub fn get_expected_profit(&self) -> Silver {
+        self.expected_profit
+    }
+
+    pub fn get_start_level(&self) -> u8 {
+        self.start_level
+    }
+
+    /// Gets every fixed route, in the order of the target levels.
+    ///
+    pub fn get_fixed_plans(&self) -> &[FixedTargetPlan] {
+        &self.fixed_plans
+    }
+
+    /// Gets the most profitable fixed route.
+    ///
+    pub fn get_best_fixed_plan(&self) -> Option<&FixedTargetPlan> {
+        self.fixed_plans.iter().filter(|p| p.expected_profit.is_some()).max_by_key(|p| p.expected_profit)
+    }
+}
+
+/// Gets the stacks tracked at a level, from its planned stack up to `max_stack` in steps of what a failure adds.
+///
+fn get_stack_grid(level: u8, planned: u16, max_stack: u16) -> Vec<u16> {
+    let gain = FAILSTACK_GAINS[level as usize];
+    let mut grid = Vec::new();
+    let mut stack = planned;
+    while stack < max_stack {
+        grid.push(stack);
+        // Saturates, so a maximum near the top of u16 still ends the grid.
+        stack = stack.saturating_add(gain);
+    }
+    grid.push(stack.min(max_stack).max(planned));
+    grid
+}
+
+/// Gets the value of a single tap, and whether buying again after a failure is worth it.
+///
+/// `next_value` is what the next level is worth at its planned stack, and `failed_value` what this level is worth
+/// at the stack a failure leads to, `None` at the highest stack. `forced` always buys again when possible.
+///
+fn get_tap_value(chance: f64, base_cost: f64, next_value: f64, rebuy_cost: Option<f64>, failed_value: Option<f64>, forced: bool) -> (f64, bool) {
+    let once = -base_cost + chance * next_value;
+    let rebuy_cost = match rebuy_cost {
+        Some(v) => v,
+        None => return (once, false),
+    };
+
+    match failed_value {
+        // A failure leads to a higher stack, which is already solved.
+        Some(failed_value) => {
+            let rebuy = failed_value - rebuy_cost;
+            if forced || rebuy > 0.0 {
+                (once + (1.0 - chance) * rebuy, true)
+            } else {
+                (once, false)
+            }
+        }
+        // At the highest stack a failure leads back here, so the tap repeats until it succeeds.
+        None => {
+            let repeated = next_value - (base_cost + (1.0 - chance) * rebuy_cost) / chance;
+            // Repeating beats a single tap exactly when the accessory is worth buying again.
+            if forced || repeated > rebuy_cost {
+                (repeated, true)
+            } else {
+                (once, false)
+            }
+        }
+    }
+}
+
+/// Solves the values of a chain from `end_level` down to `start_level`, for every stack of every level.
+///
+/// With `forced`, every level below the end is tapped and failures are always bought again, as a fixed route would.
+/// Returns the values by level, lowest first, along with each state's action and whether to buy again.
+///
+fn solve_chain(
+    prices: &[LevelPrices],
+    options: &ScanOptions,
+    end_level: u8,
+    max_stack: u16,
+    forced: bool,
+) -> Vec<Vec<(u16, f64, PolicyAction, bool)>> {
+    let base_cost = prices[0].buy_price.map(|v| v.to_f64()).unwrap_or(0.0);
+    let sell = |level: u8| prices[level as usize].sell_value.map(|v| v.after_tax(options.tax_rate).to_f64());
+
+    let mut levels: Vec<Vec<(u16, f64, PolicyAction, bool)>> = Vec::new();
+    let top_value = sell(end_level).unwrap_or(0.0);
+    let mut next_value = top_value;
+    for level in (options.start_level..end_level).rev() {
+        let grid = get_stack_grid(level, options.stacks[(level - options.start_level) as usize], max_stack);
+        let rebuy_cost = prices[level as usize].buy_price.map(|v| v.to_f64());
+        let sell_value = sell(level).unwrap_or(0.0);
+
+        let mut states: Vec<(u16, f64, PolicyAction, bool)> = Vec::new();
+        for stack in grid.iter().rev() {
+            let chance = calc_accessory_chance(level + 1, *stack).get();
+            let failed_value = states.last().map(|s| s.1);
+            let (tap_value, rebuy) = get_tap_value(chance, base_cost, next_value, rebuy_cost, failed_value, forced);
+
+            if forced || tap_value > sell_value {
+                states.push((*stack, tap_value, PolicyAction::Tap, rebuy));
+            } else {
+                states.push((*stack, sell_value, PolicyAction::Sell, false));
+            }
+        }
+        states.reverse();
+        next_value = states[0].1;
+        levels.push(states);
+    }
+    levels.reverse();
+    levels.push(vec![(0, top_value, PolicyAction::Sell, false)]);
+    levels
+}
+
+/// Finds the best choice at every state from `options.start_level` up to the highest target level.
+///
+/// `prices` has the prices of every level from the base up, see [`get_level_prices`]. Each level is tapped at its
+/// stack in `options.stacks`, which grows with every failure up to `max_stack`.
+/// Each of `options.target_levels` is also priced as a fixed route.
+///
+/// # Errors
+///
+/// If the options aren't valid, a level is missing from `prices`, or base accessories or the starting level can't be bought.
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::policy::{solve_policy, LevelPrices, PolicyAction};
+/// use bdo_enhancement_profit_calculator::scan::ScanOptions;
+/// use bdo_enhancement_profit_calculator::units::Silver;
+///
+/// let level = |buy: i64, sell: i64| LevelPrices { buy_price: Some(Silver::new(buy)), sell_value: Some(Silver::new(sell)) };
+/// // A DUO sells well, but a TRI is hardly worth more.
+/// let prices = vec![level(1000, 1000), level(4000, 4000), level(20000, 20000), level(25000, 25000)];
+///
+/// let mut options = ScanOptions::default();
+/// options.stacks = vec![20, 40, 44];
+/// options.target_levels = vec![2, 3];
+/// options.tax_rate = 1.0;
+///
+/// let policy = solve_policy(&prices, &options, 100).unwrap();
+///
+/// assert_eq!(PolicyAction::Tap, policy.find_state(1, 40).unwrap().action);
+/// assert_eq!(PolicyAction::Sell, policy.find_state(2, 44).unwrap().action);
+/// // Stopping at DUO is the best fixed route, and the policy is never worse than it.
+/// let best = policy.get_best_fixed_plan().unwrap();
+/// assert_eq!(2, best.end_level);
+/// assert!(policy.get_expected_profit() >= best.expected_profit.unwrap());
+/// ```
+///
+pub fn solve_policy(prices: &[LevelPrices], options: &ScanOptions, max_stack: u16) -> Result<StoppingPolicy, String> {
+    options.validate()?;
+    let top_level = *options.target_levels.iter().max().unwrap(); // Validated to have at least one.
+    if prices.len() <= top_level as usize {
+        return Err(format!("Prices are needed for every level up to {}, got {}.", top_level, prices.len()));
+    }
+    if prices[0].buy_price.is_none() {
+        return Err(String::from("Base accessories aren't on the market."));
+    }
+    let start_cost = match prices[options.start_level as usize].buy_price {
+        Some(v) => v.to_f64(),
+        None => return Err(format!("Level {} isn't on the market, so there's nothing to start from.", options.start_level)),
+    };
+
+    let levels = solve_chain(prices, options, top_level, max_stack, false);
+    let states = levels
+        .iter()
+        .zip(options.start_level..top_level)
+        .flat_map(|(states, level)| {
+            states.iter().map(move |(stack, value, action, rebuy)| PolicyState {
+                level,
+                stack: *stack,
+                action: *action,
+                rebuy_on_failure: *rebuy,
+                value: Silver::from_f64(*value, Rounding::Down),
+            })
+        })
+        .collect();
+
+    let fixed_plans = options
+        .target_levels
+        .iter()
+        .map(|end_level| FixedTargetPlan {
+            end_level: *end_level,
+            expected_profit: prices[*end_level as usize].sell_value.map(|_| {
+                let value = solve_chain(prices, options, *end_level, max_stack, true)[0][0].1;
+                Silver::from_f64(value - start_cost, Rounding::Down)
+            }),
+        })
+        .collect();
+
+    Ok(StoppingPolicy {
+        start_level: options.start_level,
+        states,
+        expected_profit: Silver::from_f64(levels[0][0].1 - start_cost, Rounding::Down),
+        fixed_plans,
+    })
+}
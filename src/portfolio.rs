@@ -0,0 +1,413 @@
+//! # Portfolio
+//!
+//! Portfolio plans how a fixed silver budget should be split across many accessory enhancements.
+
+use crate::accessories::{calc_accessory_chance, AccProfitDetails};
+
+/// Number of slices the budget is split into when searching for the best plan.
+const BUDGET_BUCKETS: u64 = 2000;
+
+/// An enhancement route that could be part of a plan, priced per finished accessory.
+///
+pub struct PlanCandidate {
+    item_id: u32,
+    name: String,
+    start_level: u8,
+    end_level: u8,
+    stacks: Vec<u16>,
+    make_cost: u64,
+    profit_taxed: i64,
+    risk: f64,
+    max_count: u32,
+}
+
+impl PlanCandidate {
+    /// Creates a candidate from the profit of making one accessory from `start_level` to `end_level`.
+    ///
+    /// # Panics
+    ///
+    /// If the route is not possible, or there are less stacks than levels in the route.
+    ///
+    pub fn new(
+        item_id: u32,
+        name: &str,
+        start_level: u8,
+        end_level: u8,
+        stacks: Vec<u16>,
+        profit: &AccProfitDetails,
+        max_count: u32,
+    ) -> Self {
+        if end_level <= start_level || end_level > 5 {
+            panic!("It is impossible to enhance {start_level} to {end_level}.")
+        }
+        if stacks.len() < (end_level - start_level).into() {
+            panic!("Not enough stacks were provided (needed {}).", end_level - start_level)
+        }
+
+        PlanCandidate {
+            item_id,
+            name: name.to_owned(),
+            start_level,
+            end_level,
+            stacks,
+            make_cost: profit.get_make_cost().to_u64(),
+            profit_taxed: profit.get_profit_taxed().get(),
+            risk: 0.0,
+            max_count,
+        }
+    }
+
+    /// Sets the standard deviation of the make cost of one accessory.
+    ///
+    pub fn set_risk(&mut self, std_dev: f64) {
+        self.risk = std_dev;
+    }
+
+    pub fn get_item_id(&self) -> u32 {
+        self.item_id
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_make_cost(&self) -> u64 {
+        self.make_cost
+    }
+
+    pub fn get_profit_taxed(&self) -> i64 {
+        self.profit_taxed
+    }
+
+    pub fn get_max_count(&self) -> u32 {
+        self.max_count
+    }
+
+    /// Taxed profit of one accessory, less `risk_aversion` standard deviations of its cost.
+    ///
+    pub fn get_risk_adjusted_profit(&self, risk_aversion: f64) -> f64 {
+        self.profit_taxed as f64 - risk_aversion * self.risk
+    }
+
+    /// Expected attempts at each step of the route to finish `count` accessories, first step first.
+    ///
+    fn expected_attempts(&self, count: u32) -> Vec<f64> {
+        let mut attempts = Vec::new();
+        let mut needed = count as f64;
+        for step in (0..(self.end_level - self.start_level)).rev() {
+            let chance = calc_accessory_chance(self.start_level + step + 1, self.stacks[step as usize]);
+            needed *= chance.expected_attempts();
+            attempts.push(needed);
+        }
+        attempts.reverse();
+        attempts
+    }
+}
+
+/// Caps how many of an accessory a plan can make, so it can actually be sold.
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::portfolio::max_count_from_liquidity;
+///
+/// // Only plan to make a quarter of what is currently listed at the target level.
+/// assert_eq!(3, max_count_from_liquidity(14, 0.25));
+/// assert_eq!(1, max_count_from_liquidity(0, 0.25));
+/// ```
+///
+pub fn max_count_from_liquidity(target_stock: u64, share: f64) -> u32 {
+    ((target_stock as f64 * share).floor() as u32).max(1)
+}
+
+/// How many of a candidate a plan makes.
+///
+pub struct PlanAllocation {
+    item_id: u32,
+    name: String,
+    start_level: u8,
+    end_level: u8,
+    count: u32,
+    cost: u64,
+    expected_profit: i64,
+    risk_adjusted_profit: f64,
+}
+
+impl PlanAllocation {
+    pub fn get_item_id(&self) -> u32 {
+        self.item_id
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_start_level(&self) -> u8 {
+        self.start_level
+    }
+
+    pub fn get_end_level(&self) -> u8 {
+        self.end_level
+    }
+
+    pub fn get_count(&self) -> u32 {
+        self.count
+    }
+
+    pub fn get_cost(&self) -> u64 {
+        self.cost
+    }
+
+    pub fn get_expected_profit(&self) -> i64 {
+        self.expected_profit
+    }
+
+    pub fn get_risk_adjusted_profit(&self) -> f64 {
+        self.risk_adjusted_profit
+    }
+}
+
+/// What needs to be bought for an allocation.
+///
+pub struct ShoppingItem {
+    item_id: u32,
+    name: String,
+    base_accessories: u64,
+    start_level: u8,
+    start_items: u64,
+}
+
+impl ShoppingItem {
+    pub fn get_item_id(&self) -> u32 {
+        self.item_id
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Base accessories used as material (and as the item being tapped when starting from base).
+    ///
+    pub fn get_base_accessories(&self) -> u64 {
+        self.base_accessories
+    }
+
+    pub fn get_start_level(&self) -> u8 {
+        self.start_level
+    }
+
+    /// Already enhanced accessories to start from, always 0 when starting from base.
+    ///
+    pub fn get_start_items(&self) -> u64 {
+        self.start_items
+    }
+}
+
+/// A single step of the tap plan.
+///
+pub struct TapPlanStep {
+    item_id: u32,
+    name: String,
+    from_level: u8,
+    to_level: u8,
+    stack: u16,
+    chance: f64,
+    expected_attempts: f64,
+}
+
+impl TapPlanStep {
+    pub fn get_item_id(&self) -> u32 {
+        self.item_id
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_from_level(&self) -> u8 {
+        self.from_level
+    }
+
+    pub fn get_to_level(&self) -> u8 {
+        self.to_level
+    }
+
+    pub fn get_stack(&self) -> u16 {
+        self.stack
+    }
+
+    pub fn get_chance(&self) -> f64 {
+        self.chance
+    }
+
+    pub fn get_expected_attempts(&self) -> f64 {
+        self.expected_attempts
+    }
+}
+
+/// Contains the chosen allocations, and what has to be bought and tapped to carry them out.
+///
+pub struct PortfolioPlan {
+    allocations: Vec<PlanAllocation>,
+    shopping_list: Vec<ShoppingItem>,
+    tap_plan: Vec<TapPlanStep>,
+}
+
+impl PortfolioPlan {
+    pub fn get_allocations(&self) -> &[PlanAllocation] {
+        &self.allocations
+    }
+
+    pub fn get_shopping_list(&self) -> &[ShoppingItem] {
+        &self.shopping_list
+    }
+
+    pub fn get_tap_plan(&self) -> &[TapPlanStep] {
+        &self.tap_plan
+    }
+
+    pub fn get_total_cost(&self) -> u64 {
+        self.allocations.iter().map(|a| a.cost).sum()
+    }
+
+    pub fn get_expected_profit(&self) -> i64 {
+        self.allocations.iter().map(|a| a.expected_profit).sum()
+    }
+
+    pub fn get_risk_adjusted_profit(&self) -> f64 {
+        self.allocations.iter().map(|a| a.risk_adjusted_profit).sum()
+    }
+}
+
+/// Chooses how many of each candidate to make, maximising risk-adjusted profit within a budget.
+///
+/// `risk_aversion` is the risk tolerance of the plan: 0 maximises expected profit, and each
+/// step above that takes one more standard deviation of cost off every accessory's profit.
+/// Candidates that aren't profitable after that are never chosen.
+///
+/// The budget is searched in slices of 1/2000th, and costs are rounded up to whole slices,
+/// so a plan never goes over budget but may leave a little unspent.
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::accessories::AccProfitDetails;
+/// use bdo_enhancement_profit_calculator::portfolio::{plan_portfolio, PlanCandidate};
+/// use bdo_enhancement_profit_calculator::units::Silver;
+///
+/// let cheap = PlanCandidate::new(1, "Cheap Ring", 0, 1, vec![20], &AccProfitDetails::new(Silver::new(100), Silver::new(300), 1.0), 10);
+/// let dear = PlanCandidate::new(2, "Dear Ring", 0, 1, vec![20], &AccProfitDetails::new(Silver::new(400), Silver::new(700), 1.0), 10);
+///
+/// let plan = plan_portfolio(vec![cheap, dear], 1000, 0.0);
+///
+/// // 10 cheap rings make more than 2 dear ones.
+/// assert_eq!(1, plan.get_allocations().len());
+/// assert_eq!(10, plan.get_allocations()[0].get_count());
+/// assert_eq!(2000, plan.get_expected_profit());
+/// ```
+///
+pub fn plan_portfolio(candidates: Vec<PlanCandidate>, budget: u64, risk_aversion: f64) -> PortfolioPlan {
+    let step = (budget / BUDGET_BUCKETS).max(1);
+    let buckets = (budget / step) as usize;
+
+    // Bounded knapsack, with each candidate's count split into powers of two.
+    let mut parts: Vec<(usize, u32)> = Vec::new();
+    for (i, candidate) in candidates.iter().enumerate() {
+        if candidate.get_risk_adjusted_profit(risk_aversion) <= 0.0 || candidate.make_cost == 0 {
+            continue;
+        }
+        let max_count = candidate.max_count.min((budget / candidate.make_cost) as u32);
+        let mut remaining = max_count;
+        let mut size = 1;
+        while remaining > 0 {
+            let take = size.min(remaining);
+            parts.push((i, take));
+            remaining -= take;
+            size *= 2;
+        }
+    }
+
+    let mut best = vec![0.0_f64; buckets + 1];
+    let mut taken = vec![vec![false; buckets + 1]; parts.len()];
+    for (p, (i, count)) in parts.iter().enumerate() {
+        let candidate = &candidates[*i];
+        let weight = ((candidate.make_cost * *count as u64) as f64 / step as f64).ceil() as usize;
+        let value = candidate.get_risk_adjusted_profit(risk_aversion) * *count as f64;
+        if weight > buckets {
+            continue;
+        }
+        for b in (weight..=buckets).rev() {
+            if best[b - weight] + value > best[b] {
+                best[b] = best[b - weight] + value;
+                taken[p][b] = true;
+            }
+        }
+    }
+
+    let mut counts = vec![0_u32; candidates.len()];
+    let mut b = buckets;
+    for (p, (i, count)) in parts.iter().enumerate().rev() {
+        if taken[p][b] {
+            counts[*i] += count;
+            let weight = ((candidates[*i].make_cost * *count as u64) as f64 / step as f64).ceil() as usize;
+            b -= weight;
+        }
+    }
+
+    let mut allocations = Vec::new();
+    let mut shopping_list = Vec::new();
+    let mut tap_plan = Vec::new();
+    for (candidate, count) in candidates.iter().zip(counts) {
+        if count == 0 {
+            continue;
+        }
+
+        let attempts = candidate.expected_attempts(count);
+        let start_needed = attempts[0].ceil() as u64;
+        let material_needed: f64 = attempts.iter().sum();
+        let (base_accessories, start_items) = if candidate.start_level == 0 {
+            (material_needed.ceil() as u64 + start_needed, 0)
+        } else {
+            (material_needed.ceil() as u64, start_needed)
+        };
+
+        for (step, expected_attempts) in attempts.iter().enumerate() {
+            let from_level = candidate.start_level + step as u8;
+            let stack = candidate.stacks[step];
+            tap_plan.push(TapPlanStep {
+                item_id: candidate.item_id,
+                name: candidate.name.clone(),
+                from_level,
+                to_level: from_level + 1,
+                stack,
+                chance: calc_accessory_chance(from_level + 1, stack).get(),
+                expected_attempts: *expected_attempts,
+            });
+        }
+
+        shopping_list.push(ShoppingItem {
+            item_id: candidate.item_id,
+            name: candidate.name.clone(),
+            base_accessories,
+            start_level: candidate.start_level,
+            start_items,
+        });
+
+        allocations.push(PlanAllocation {
+            item_id: candidate.item_id,
+            name: candidate.name.clone(),
+            start_level: candidate.start_level,
+            end_level: candidate.end_level,
+            count,
+            cost: candidate.make_cost * count as u64,
+            expected_profit: candidate.profit_taxed * count as i64,
+            risk_adjusted_profit: candidate.get_risk_adjusted_profit(risk_aversion) * count as f64,
+        });
+    }
+
+    PortfolioPlan {
+        allocations,
+        shopping_list,
+        tap_plan,
+    }
+}
@@ -0,0 +1,433 @@
+//! # Report
+//!
+//! Report writes scan results in formats that other tools can read.
+//!
+//! The fields written are versioned with [`SCHEMA_VERSION`], which is bumped whenever a field is
+//! renamed, removed or changes meaning. New fields can be added without a bump.
+
+use crate::bdo_market_requests::HasId;
+use crate::compare::ComparisonRow;
+use crate::enhancement_level::LEVEL_NAMES;
+use crate::general_calcs::market_calcs::CapState;
+use crate::scan::ScanResult;
+use crate::units::Probability;
+
+use serde::Serialize;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Formats results can be written in.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Aligned columns, for reading in a terminal.
+    Table,
+    /// A single JSON document, with the results in an array.
+    Json,
+    /// One JSON object per line.
+    Ndjson,
+    /// Comma separated values, with a header row.
+    Csv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            "csv" => Ok(OutputFormat::Csv),
+            oth => Err(format!("Unknown format \"{}\", expected one of: table, json, ndjson, csv.", oth)),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            OutputFormat::Table => "table",
+            OutputFormat::Json => "json",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Csv => "csv",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A single scan result, as it is written out.
+///
+#[derive(Serialize, Debug, Clone)]
+pub struct ReportRow {
+    pub item_id: u32,
+    pub name: String,
+    pub start_level: u8,
+    pub end_level: u8,
+    pub stacks: Vec<u16>,
+    pub base_price: u64,
+    pub make_cost: u64,
+    pub actual_value: u64,
+    pub profit: i64,
+    pub profit_taxed: i64,
+    /// Base accessories listed on the market.
+    pub stock: u64,
+    /// Chance the finished accessory sells when listed, `None` if it couldn't be priced.
+    pub sale_chance: Option<Probability>,
+    /// Where the finished accessory's price sits against its caps, `None` if it couldn't be priced.
+    pub cap_state: Option<CapState>,
+}
+
+impl From<&ScanResult> for ReportRow {
+    fn from(result: &ScanResult) -> Self {
+        let profit_details = result.get_profit_details();
+        ReportRow {
+            item_id: result.get_item_id(),
+            name: result.get_name().to_owned(),
+            start_level: result.get_start_level(),
+            end_level: result.get_end_level(),
+            stacks: result.get_stacks().to_vec(),
+            base_price: result.get_base_price(),
+            make_cost: profit_details.get_make_cost().to_u64(),
+            actual_value: profit_details.get_actual_value().to_u64(),
+            profit: profit_details.get_profit().get(),
+            profit_taxed: profit_details.get_profit_taxed().get(),
+            stock: result.get_stock(),
+            sale_chance: result.get_sell_estimate().map(|e| e.get_sale_chance()),
+            cap_state: result.get_sell_estimate().map(|e| e.get_cap_state()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    schema_version: u32,
+    generated_at: u64,
+    region: &'a str,
+    results: &'a [ReportRow],
+}
+
+#[derive(Serialize)]
+struct NdjsonRow<'a> {
+    schema_version: u32,
+    region: &'a str,
+    #[serde(flatten)]
+    row: &'a ReportRow,
+}
+
+const CSV_HEADER: [&str; 15] = [
+    "schema_version", "region", "item_id", "name", "start_level", "end_level", "stacks",
+    "base_price", "make_cost", "actual_value", "profit", "profit_taxed", "stock", "sale_chance", "cap_state",
+];
+
+#[derive(Serialize)]
+struct JsonComparison<'a> {
+    schema_version: u32,
+    generated_at: u64,
+    items: &'a [ComparisonRow],
+}
+
+#[derive(Serialize)]
+struct NdjsonComparison<'a> {
+    schema_version: u32,
+    #[serde(flatten)]
+    row: &'a ComparisonRow,
+}
+
+const COMPARISON_CSV_HEADER: [&str; 17] = [
+    "schema_version", "item_id", "name", "region", "base_price", "stock",
+    "price_0", "price_1", "price_2", "price_3", "price_4", "price_5",
+    "best_start_level", "best_end_level", "best_profit_taxed", "best_region", "profit_gap",
+];
+
+/// Writes results in the given format.
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::general_calcs::market_calcs::CapState;
+/// use bdo_enhancement_profit_calculator::report::{write_report, OutputFormat, ReportRow};
+/// use bdo_enhancement_profit_calculator::units::Probability;
+///
+/// let row = ReportRow {
+///     item_id: 11607,
+///     name: String::from("Ring, \"Shiny\""),
+///     start_level: 0,
+///     end_level: 4,
+///     stacks: vec![20, 40, 44, 110],
+///     base_price: 1000,
+///     make_cost: 75000,
+///     actual_value: 100000,
+///     profit: 25000,
+///     profit_taxed: 10150,
+///     stock: 12,
+///     sale_chance: Some(Probability::new(0.5).unwrap()),
+///     cap_state: Some(CapState::CappedHigh),
+/// };
+///
+/// let mut out = Vec::new();
+/// write_report(&mut out, OutputFormat::Csv, "eu", &[row]).unwrap();
+/// let csv = String::from_utf8(out).unwrap();
+///
+/// assert_eq!(
+///     "1,eu,11607,\"Ring, \"\"Shiny\"\"\",0,4,20;40;44;110,1000,75000,100000,25000,10150,12,0.5,capped_high",
+///     csv.lines().nth(1).unwrap()
+/// );
+/// ```
+///
+pub fn write_report<W: Write>(writer: &mut W, format: OutputFormat, region: &str, rows: &[ReportRow]) -> io::Result<()> {
+    match format {
+        OutputFormat::Json => {
+            let generated_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+            let report = JsonReport {
+                schema_version: SCHEMA_VERSION,
+                generated_at,
+                region,
+                results: rows,
+            };
+            serde_json::to_writer_pretty(&mut *writer, &report)?;
+            writeln!(writer)
+        }
+        OutputFormat::Ndjson => {
+            for row in rows {
+                write_ndjson_row(writer, region, row)?;
+            }
+            Ok(())
+        }
+        OutputFormat::Csv => {
+            write_csv_header(writer)?;
+            for row in rows {
+                write_csv_row(writer, region, row)?;
+            }
+            Ok(())
+        }
+        OutputFormat::Table => write_table(writer, rows),
+    }
+}
+
+/// Writes a single result as a line of NDJSON, so results can be streamed as they come in.
+///
+pub fn write_ndjson_row<W: Write>(writer: &mut W, region: &str, row: &ReportRow) -> io::Result<()> {
+    let line = NdjsonRow {
+        schema_version: SCHEMA_VERSION,
+        region,
+        row,
+    };
+    serde_json::to_writer(&mut *writer, &line)?;
+    writeln!(writer)
+}
+
+pub fn write_csv_header<W: Write>(writer: &mut W) -> io::Result<()> {
+    writeln!(writer, "{}", CSV_HEADER.join(","))
+}
+
+pub fn write_csv_row<W: Write>(writer: &mut W, region: &str, row: &ReportRow) -> io::Result<()> {
+    let stacks: Vec<String> = row.stacks.iter().map(|s| s.to_string()).collect();
+    writeln!(
+        writer,
+        "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+        SCHEMA_VERSION,
+        csv_field(region),
+        row.item_id,
+        csv_field(&row.name),
+        row.start_level,
+        row.end_level,
+        stacks.join(";"),
+        row.base_price,
+        row.make_cost,
+        row.actual_value,
+        row.profit,
+        row.profit_taxed,
+        row.stock,
+        row.sale_chance.map(|c| c.get().to_string()).unwrap_or_default(),
+        row.cap_state.map(|c| c.get_name()).unwrap_or_default()
+    )
+}
+
+/// Quotes a CSV field if it needs it.
+///
+pub(crate) fn csv_field(field: &str) -> String {
+    if field.contains(',') || field.contains('"') || field.contains('\n') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// Writes a multi-region comparison in the given format.
+///
+/// Tables and CSV have a line for every item in every region, the other formats a record per item.
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::compare::{ComparisonRow, RegionPrices};
+/// use bdo_enhancement_profit_calculator::report::{write_comparison, OutputFormat};
+///
+/// let prices = |region: &str, base_price: u64| RegionPrices {
+///     region: region.to_owned(),
+///     base_price,
+///     stock: 5,
+///     level_prices: vec![Some(base_price), Some(4000), None, None, None, None],
+///     best_route: None,
+/// };
+/// let row = ComparisonRow {
+///     item_id: 11607,
+///     name: String::from("Ring"),
+///     regions: vec![prices("eu", 1000), prices("na", 1200)],
+///     best_region: None,
+///     price_spread_percent: Some(20.0),
+///     profit_gap: 0,
+/// };
+///
+/// let mut out = Vec::new();
+/// write_comparison(&mut out, OutputFormat::Csv, &[row]).unwrap();
+/// let csv = String::from_utf8(out).unwrap();
+///
+/// assert_eq!("1,11607,Ring,na,1200,5,1200,4000,,,,,,,,,0", csv.lines().nth(2).unwrap());
+/// ```
+///
+pub fn write_comparison<W: Write>(writer: &mut W, format: OutputFormat, rows: &[ComparisonRow]) -> io::Result<()> {
+    match format {
+        OutputFormat::Json => {
+            let generated_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+            let report = JsonComparison {
+                schema_version: SCHEMA_VERSION,
+                generated_at,
+                items: rows,
+            };
+            serde_json::to_writer_pretty(&mut *writer, &report)?;
+            writeln!(writer)
+        }
+        OutputFormat::Ndjson => {
+            for row in rows {
+                serde_json::to_writer(&mut *writer, &NdjsonComparison { schema_version: SCHEMA_VERSION, row })?;
+                writeln!(writer)?;
+            }
+            Ok(())
+        }
+        OutputFormat::Csv => {
+            writeln!(writer, "{}", COMPARISON_CSV_HEADER.join(","))?;
+            for row in rows {
+                for region in &row.regions {
+                    let optional = |value: Option<String>| value.unwrap_or_default();
+                    let mut fields = vec![
+                        SCHEMA_VERSION.to_string(),
+                        row.item_id.to_string(),
+                        csv_field(&row.name),
+                        csv_field(&region.region),
+                        region.base_price.to_string(),
+                        region.stock.to_string(),
+                    ];
+                    for level in 0..=LEVEL_NAMES.len() {
+                        fields.push(optional(region.level_prices.get(level).copied().flatten().map(|p| p.to_string())));
+                    }
+                    let best = region.best_route.as_ref();
+                    fields.push(optional(best.map(|b| b.start_level.to_string())));
+                    fields.push(optional(best.map(|b| b.end_level.to_string())));
+                    fields.push(optional(best.map(|b| b.profit_taxed.to_string())));
+                    fields.push(optional(row.best_region.as_deref().map(csv_field)));
+                    fields.push(row.profit_gap.to_string());
+                    writeln!(writer, "{}", fields.join(","))?;
+                }
+            }
+            Ok(())
+        }
+        OutputFormat::Table => write_comparison_table(writer, rows),
+    }
+}
+
+fn write_comparison_table<W: Write>(writer: &mut W, rows: &[ComparisonRow]) -> io::Result<()> {
+    let mut header = vec!["Id", "Name", "Region", "Base"];
+    header.extend(LEVEL_NAMES);
+    header.extend(["Best route", "Profit after tax"]);
+
+    let mut lines: Vec<Vec<String>> = Vec::new();
+    for row in rows {
+        for (i, region) in row.regions.iter().enumerate() {
+            // The item is only named on its first line, so regions read as a group.
+            let (id, name) = if i == 0 { (row.item_id.to_string(), row.name.clone()) } else { (String::new(), String::new()) };
+            let best = Some(&region.region) == row.best_region.as_ref();
+            let mut line = vec![id, name, format!("{}{}", region.region, if best { " *" } else { "" }), region.base_price.to_string()];
+            for level in 1..=LEVEL_NAMES.len() {
+                line.push(region.level_prices.get(level).copied().flatten().map(|p| p.to_string()).unwrap_or_else(|| String::from("-")));
+            }
+            match &region.best_route {
+                Some(route) => {
+                    line.push(format!("{} -> {}", route.start_level, route.end_level));
+                    line.push(route.profit_taxed.to_string());
+                }
+                None => line.extend([String::from("-"), String::from("-")]),
+            }
+            lines.push(line);
+        }
+    }
+
+    write_aligned(writer, &header, &lines, &[1, 2])
+}
+
+fn write_table<W: Write>(writer: &mut W, rows: &[ReportRow]) -> io::Result<()> {
+    let header = ["Id", "Name", "Route", "Stacks", "Buy at", "Make cost", "Sell at", "Profit", "Profit after tax", "Stock", "Sale chance"];
+    let lines: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| {
+            let stacks: Vec<String> = row.stacks.iter().map(|s| s.to_string()).collect();
+            vec![
+                row.item_id.to_string(),
+                row.name.clone(),
+                format!("{} -> {}", row.start_level, row.end_level),
+                stacks.join(","),
+                row.base_price.to_string(),
+                row.make_cost.to_string(),
+                row.actual_value.to_string(),
+                row.profit.to_string(),
+                row.profit_taxed.to_string(),
+                row.stock.to_string(),
+                row.sale_chance.map(|c| c.to_string()).unwrap_or_else(|| String::from("-")),
+            ]
+        })
+        .collect();
+
+    write_aligned(writer, &header, &lines, &[1])
+}
+
+/// Writes a header, a rule and the lines in aligned columns, with the `left` columns left aligned.
+///
+fn write_aligned<W: Write>(writer: &mut W, header: &[&str], lines: &[Vec<String>], left: &[usize]) -> io::Result<()> {
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for line in lines {
+        for (i, cell) in line.iter().enumerate() {
+            widths[i] = widths[i].max(cell.chars().count());
+        }
+    }
+
+    let header: Vec<String> = header.iter().map(|h| h.to_string()).collect();
+    write_table_line(writer, &header, &widths, left)?;
+    let rule: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+    write_table_line(writer, &rule, &widths, left)?;
+    for line in lines {
+        write_table_line(writer, line, &widths, left)?;
+    }
+    Ok(())
+}
+
+fn write_table_line<W: Write>(writer: &mut W, cells: &[String], widths: &[usize], left: &[usize]) -> io::Result<()> {
+    let padded: Vec<String> = cells
+        .iter()
+        .zip(widths)
+        .enumerate()
+        .map(|(i, (cell, width))| {
+            // Names are left aligned, numbers right aligned.
+            if left.contains(&i) {
+                format!("{:<width$}", cell, width = width)
+            } else {
+                format!("{:>width$}", cell, width = width)
+            }
+        })
+        .collect();
+    writeln!(writer, "{}", padded.join(" | ").trim_end())
+}
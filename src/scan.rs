@@ -0,0 +1,345 @@
+//! # Scan
+//!
+//! Scan checks every accessory in a set of market categories for profitable enhancements.
+
+use crate::accessories::{self, AccEnhancementDetails, AccMarketData, AccProfitDetails};
+use crate::bdo_market_requests::market_client::MarketClient;
+use crate::bdo_market_requests::{CategoryGivenInfo, HasId, ItemID};
+use crate::catalogue::EnhancementType;
+use crate::general_calcs::market_calcs::SellEstimate;
+use crate::market_category::MarketCategory;
+use crate::units::Silver;
+
+/// Contains what to scan, and what counts as profitable.
+///
+/// The defaults scan grade 3 rings, necklaces, earrings and belts above 1M, from base to TET.
+///
+#[derive(Clone)]
+pub struct ScanOptions {
+    /// Pairs of main and sub category.
+    pub categories: Vec<(u16, u16)>,
+    pub grade: u8,
+    pub min_price: u64,
+    pub max_price: u64,
+    /// If not empty, only items matching one of these patterns are kept, see [`matches_pattern`].
+    pub include: Vec<String>,
+    /// Items matching any of these patterns are skipped.
+    pub exclude: Vec<String>,
+    pub stacks: Vec<u16>,
+    pub start_level: u8,
+    /// Every level to check enhancing to, each is reported separately.
+    pub target_levels: Vec<u8>,
+    pub tax_rate: f64,
+    /// Minimum profit (before tax) for an item to be reported.
+    pub min_profit: i64,
+    /// Minimum profit after tax for an item to be reported, if set.
+    pub min_profit_taxed: Option<i64>,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            categories: MarketCategory::accessories().iter().map(|c| c.get_ids()).collect(),
+            grade: 3,
+            min_price: 1000000,
+            max_price: u64::MAX,
+            include: Vec::new(),
+            exclude: vec![String::from("Manos")],
+            stacks: vec![20, 40, 44, 110],
+            start_level: 0,
+            target_levels: vec![4],
+            tax_rate: 0.8515,
+            min_profit: 50000000,
+            min_profit_taxed: None,
+        }
+    }
+}
+
+impl ScanOptions {
+    /// Checks that every route can be enhanced with the given stacks.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bdo_enhancement_profit_calculator::scan::ScanOptions;
+    ///
+    /// let mut options = ScanOptions::default();
+    /// assert!(options.validate().is_ok());
+    ///
+    /// options.target_levels = vec![5];
+    /// assert!(options.validate().is_err());
+    /// ```
+    ///
+    pub fn validate(&self) -> Result<(), String> {
+        if self.categories.is_empty() {
+            return Err(String::from("At least one category is required."));
+        }
+        for (main, sub) in &self.categories {
+            if let Some(category) = MarketCategory::find(*main, *sub) {
+                if category.get_enhancement_type() != EnhancementType::Accessory {
+                    return Err(format!("{} doesn't hold accessories, which are the only items scans can check.", category.get_path()));
+                }
+            }
+        }
+        if self.target_levels.is_empty() {
+            return Err(String::from("At least one target level is required."));
+        }
+        for target_level in &self.target_levels {
+            if *target_level <= self.start_level || *target_level > EnhancementType::Accessory.get_max_level() {
+                return Err(format!("It is impossible to enhance {} to {}.", self.start_level, target_level));
+            }
+            let level_gap = target_level - self.start_level;
+            if self.stacks.len() < level_gap.into() {
+                return Err(format!(
+                    "Not enough stacks were provided to enhance {} to {} (needed {}, got {}).",
+                    self.start_level,
+                    target_level,
+                    level_gap,
+                    self.stacks.len()
+                ));
+            }
+        }
+        if self.min_price > self.max_price {
+            return Err(format!("The minimum price ({}) is above the maximum price ({}).", self.min_price, self.max_price));
+        }
+        if self.tax_rate <= 0.0 || self.tax_rate > 1.0 {
+            return Err(format!("A tax rate of {} was given, when it should be above 0 and at most 1.", self.tax_rate));
+        }
+        Ok(())
+    }
+
+    /// Checks an item against the grade, price and name filters.
+    ///
+    pub fn is_item_allowed(&self, item: &CategoryGivenInfo) -> bool {
+        item.get_item_grade() == self.grade
+            && item.get_base_price() >= self.min_price
+            && item.get_base_price() <= self.max_price
+            && self.is_name_allowed(item.get_item_name())
+    }
+
+    /// Checks an item's name against the include and exclude patterns.
+    ///
+    pub fn is_name_allowed(&self, name: &str) -> bool {
+        if !self.include.is_empty() && !self.include.iter().any(|p| matches_pattern(name, p)) {
+            return false;
+        }
+        !self.exclude.iter().any(|p| matches_pattern(name, p))
+    }
+}
+
+/// Matches a name against a pattern.
+///
+/// Patterns with a `*` must match the whole name, with `*` matching anything.
+/// Patterns without one only need to appear somewhere in the name.
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::scan::matches_pattern;
+///
+/// assert!(matches_pattern("Manos Ruby Necklace", "Manos"));
+/// assert!(matches_pattern("Manos Ruby Necklace", "*Necklace"));
+/// assert!(!matches_pattern("Manos Ruby Necklace", "Necklace*"));
+/// ```
+///
+pub fn matches_pattern(name: &str, pattern: &str) -> bool {
+    if !pattern.contains('*') {
+        return name.contains(pattern);
+    }
+
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let (first, rest) = parts.split_first().unwrap(); // Split always returns at least 1 part.
+    let (last, middle) = rest.split_last().unwrap(); // Contains a '*', so there are at least 2 parts.
+
+    if !name.starts_with(first) {
+        return false;
+    }
+    let mut remaining = &name[first.len()..];
+    for part in middle {
+        match remaining.find(part) {
+            Some(i) => remaining = &remaining[i + part.len()..],
+            None => return false,
+        }
+    }
+    remaining.ends_with(last)
+}
+
+/// Contains the result of checking a single accessory.
+///
+pub struct ScanResult {
+    item_id: u32,
+    name: String,
+    base_price: u64,
+    stock: u64,
+    start_level: u8,
+    end_level: u8,
+    stacks: Vec<u16>,
+    profit_details: AccProfitDetails,
+    /// What the start level and each base were priced at, `None` if the route couldn't be priced.
+    start_cost: Option<Silver>,
+    base_cost: Option<Silver>,
+    /// Listed at the end level, `None` if the item's market couldn't be read.
+    target_stock: Option<u64>,
+    /// How the finished accessory sells, `None` if it couldn't be priced.
+    sell_estimate: Option<SellEstimate>,
+}
+
+impl HasId for ScanResult {
+    fn get_item_id(&self) -> u32 {
+        self.item_id
+    }
+}
+
+impl ScanResult {
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_base_price(&self) -> u64 {
+        self.base_price
+    }
+
+    pub fn get_stock(&self) -> u64 {
+        self.stock
+    }
+
+    pub fn get_start_level(&self) -> u8 {
+        self.start_level
+    }
+
+    pub fn get_end_level(&self) -> u8 {
+        self.end_level
+    }
+
+    pub fn get_stacks(&self) -> &[u16] {
+        &self.stacks
+    }
+
+    pub fn get_profit_details(&self) -> &AccProfitDetails {
+        &self.profit_details
+    }
+
+    /// Gets what the accessory at the start level was priced at, if the route could be priced.
+    ///
+    pub fn get_start_cost(&self) -> Option<Silver> {
+        self.start_cost
+    }
+
+    /// Gets the average price of each base the route uses, if it could be priced.
+    ///
+    pub fn get_base_cost(&self) -> Option<Silver> {
+        self.base_cost
+    }
+
+    /// Gets how many are listed at the end level, if the item's market could be read.
+    ///
+    pub fn get_target_stock(&self) -> Option<u64> {
+        self.target_stock
+    }
+
+    /// Gets how the finished accessory is expected to sell, if it could be priced.
+    ///
+    pub fn get_sell_estimate(&self) -> Option<&SellEstimate> {
+        self.sell_estimate.as_ref()
+    }
+
+    /// Checks the result against the scan's profit thresholds.
+    ///
+    pub fn is_profitable(&self, options: &ScanOptions) -> bool {
+        if !self.profit_details.is_priced() || self.profit_details.get_profit().get() <= options.min_profit {
+            return false;
+        }
+        match options.min_profit_taxed {
+            Some(min) => self.profit_details.get_profit_taxed().get() > min,
+            None => true,
+        }
+    }
+}
+
+/// Gets every accessory in the scanned categories that passes the grade, price and name filters.
+///
+pub fn get_scan_candidates(client: &dyn MarketClient, options: &ScanOptions) -> Result<Vec<CategoryGivenInfo>, String> {
+    let mut items = Vec::new();
+    for (main_category, sub_category) in &options.categories {
+        let mut category = client.get_category(*main_category, *sub_category)?;
+        items.append(&mut category);
+    }
+
+    let items = accessories::filter_accessories_category(items, options.grade, options.min_price, options.max_price);
+    Ok(items
+        .into_iter()
+        .filter(|item| options.is_name_allowed(item.get_item_name()))
+        .collect())
+}
+
+/// Checks a single accessory with the scan's stacks, once for every target level.
+///
+/// Every level is fetched once, and shared by the target levels, see [`AccMarketData`].
+///
+pub fn scan_item(client: &dyn MarketClient, item: &CategoryGivenInfo, options: &ScanOptions) -> Vec<ScanResult> {
+    let mut market = AccMarketData::from_post(client, item.get_item_id()).ok();
+    scan_item_market(item, market.as_mut(), options)
+}
+
+/// Checks a single accessory like [`scan_item`], from its market if it could be fetched.
+///
+pub fn scan_item_market(item: &CategoryGivenInfo, mut market: Option<&mut AccMarketData>, options: &ScanOptions) -> Vec<ScanResult> {
+    let item_id = item.get_item_id();
+    options
+        .target_levels
+        .iter()
+        .map(|target_level| {
+            let details = AccEnhancementDetails::new(ItemID::new(item_id), options.start_level, Some(options.stacks.clone()));
+            let breakdown = market.as_deref_mut().and_then(|m| m.get_tap_breakdown(&details, *target_level, options.tax_rate).ok());
+            let target_stock = market.as_deref().and_then(|m| m.get_level(*target_level)).map(|l| l.get_stock());
+            let (profit_details, start_cost, base_cost, sell_estimate) = match breakdown {
+                Some(v) => {
+                    let (start_cost, base_cost, sell_estimate) = (v.get_start_cost(), v.get_base_cost(), v.get_sell_estimate().clone());
+                    (v.into_profit_details(), Some(start_cost), Some(base_cost), Some(sell_estimate))
+                }
+                // Matches what get_tap_profit_mult reports for an item it can't read.
+                None => (AccProfitDetails::unpriced(), None, None, None),
+            };
+
+            ScanResult {
+                item_id,
+                name: item.get_item_name().to_owned(),
+                base_price: item.get_base_price(),
+                stock: item.get_stock(),
+                start_level: options.start_level,
+                end_level: *target_level,
+                stacks: options.stacks.clone(),
+                profit_details,
+                start_cost,
+                base_cost,
+                target_stock,
+                sell_estimate,
+            }
+        })
+        .collect()
+}
+
+/// Scans every candidate accessory, and returns those that make at least the minimum profit.
+///
+/// `on_result` is called with every checked accessory, profitable or not, as soon as it is checked.
+///
+pub fn scan_accessories<F: FnMut(&ScanResult)>(
+    client: &dyn MarketClient,
+    options: &ScanOptions,
+    mut on_result: F,
+) -> Result<Vec<ScanResult>, String> {
+    options.validate()?;
+
+    let mut profitable = Vec::new();
+    for item in get_scan_candidates(client, options)? {
+        for result in scan_item(client, &item, options) {
+            on_result(&result);
+
+            if result.is_profitable(options) {
+                profitable.push(result);
+            }
+        }
+    }
+
+    Ok(profitable)
+}
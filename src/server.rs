@@ -0,0 +1,548 @@
+//! # Server
+//!
+//! Server answers HTTP requests with market data and profit calculations as JSON, so tools not written in Rust can use them.
+//!
+//! | Endpoint | Parameters |
+//! |---|---|
+//! | `GET /health` | |
+//! | `GET /categories` | |
+//! | `GET /categories/{main}/{sub}` | `region` |
+//! | `GET /items/{id}/prices` | `region`, `levels` |
+//! | `GET /items/{id}/profit` | `region`, `stacks`, `start`, `end`, `tax` |
+//! | `GET /items/{id}/simulate` | `region`, `stacks`, `start`, `end`, `tax`, `runs` |
+//! | `GET /items/{id}/history` | `region`, `level` |
+//!
+//! Categories can be given by name or number, for example `/categories/accessories/ring` or `/categories/20/1`.
+//! Lists are comma separated, for example `/items/11607/profit?stacks=20,40,44,110&end=4`.
+//! Anything left out comes from the server's [`ServerOptions`].
+//! Profits are priced the same way as a scan, see [`AccMarketData::get_tap_breakdown`].
+//!
+//! Errors are returned as `{"error": "..."}`, with status 400 for bad requests, 404 for unknown paths
+//! and 502 when the market couldn't be reached.
+
+use crate::accessories::{calc_risk_metrics, simulate_tap_mult, AccEnhancementDetails, AccMarketData};
+use crate::bdo_market_requests::market_client::{new_market_client, MarketClient};
+use crate::bdo_market_requests::get_bdo_urls::REGIONS;
+use crate::bdo_market_requests::{HasId, ItemBuySellInfo, ItemID};
+use crate::catalogue::Catalogue;
+use crate::market_cache::{CachedClient, MarketCache};
+use crate::market_category::MarketCategory;
+use crate::scan::ScanOptions;
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// Highest enhancement level any item on the market has.
+const MAX_ENHANCEMENT_LEVEL: u8 = 20;
+
+/// Contains how the server runs, and the defaults for requests.
+///
+pub struct ServerOptions {
+    /// Address to listen on, such as `127.0.0.1:8080`.
+    pub bind: String,
+    /// Number of requests handled at once.
+    pub threads: usize,
+    /// Region used when a request doesn't give one.
+    pub region: String,
+    /// Stacks, levels and tax used when a request doesn't give them.
+    pub route: ScanOptions,
+    /// Most runs a single simulation request can ask for.
+    pub max_runs: u32,
+    /// Client for each region requests can ask for, shared by every request so rate limits and logins carry over.
+    pub clients: HashMap<String, Arc<dyn MarketClient>>,
+}
+
+/// Creates a client for every region, with the bundled catalogue naming items on console markets.
+///
+pub fn new_clients() -> HashMap<String, Arc<dyn MarketClient>> {
+    let catalogue = Catalogue::bundled();
+    REGIONS
+        .iter()
+        .map(|region| (region.to_string(), Arc::from(new_market_client(region, catalogue.clone()))))
+        .collect()
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        ServerOptions {
+            bind: String::from("127.0.0.1:8080"),
+            threads: 4,
+            region: String::from("eu"),
+            route: ScanOptions::default(),
+            max_runs: 100000,
+            clients: new_clients(),
+        }
+    }
+}
+
+impl ServerOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.threads == 0 {
+            return Err(String::from("At least 1 thread is required."));
+        }
+        if self.max_runs == 0 {
+            return Err(String::from("At least 1 simulation run must be allowed."));
+        }
+        if !self.clients.contains_key(&self.region) {
+            return Err(format!("There's no client for the default region \"{}\".", self.region));
+        }
+        self.route.validate()
+    }
+}
+
+/// An error to send back, with its HTTP status.
+///
+struct ApiError {
+    status: u16,
+    message: String,
+}
+
+impl ApiError {
+    fn bad_request(message: String) -> Self {
+        ApiError { status: 400, message }
+    }
+
+    fn not_found(message: String) -> Self {
+        ApiError { status: 404, message }
+    }
+
+    fn market(message: String) -> Self {
+        ApiError { status: 502, message: format!("The market request failed: {}", message) }
+    }
+}
+
+/// Listens on the bind address and answers requests until the process is stopped.
+///
+/// Market data is shared through `cache` by every thread.
+///
+pub fn serve(options: ServerOptions, cache: Arc<MarketCache>) -> Result<(), String> {
+    options.validate()?;
+
+    let server = Server::http(&options.bind).map_err(|e| format!("Could not listen on {}: {}", options.bind, e))?;
+    let server = Arc::new(server);
+    let options = Arc::new(options);
+
+    let workers: Vec<_> = (0..options.threads)
+        .map(|_| {
+            let server = Arc::clone(&server);
+            let options = Arc::clone(&options);
+            let cache = Arc::clone(&cache);
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    cache.remove_expired();
+                    respond(request, &options, &cache);
+                }
+            })
+        })
+        .collect();
+
+    for worker in workers {
+        worker.join().map_err(|_| String::from("A server thread stopped unexpectedly."))?;
+    }
+    Ok(())
+}
+
+fn respond(request: Request, options: &ServerOptions, cache: &MarketCache) {
+    let (status, body) = if *request.method() != Method::Get {
+        (405, json!({ "error": "Only GET requests are supported." }))
+    } else {
+        // A panic from a malformed market response shouldn't take the thread down with it.
+        match panic::catch_unwind(AssertUnwindSafe(|| handle_request(request.url(), options, cache))) {
+            Ok(v) => v,
+            Err(_) => (500, json!({ "error": "The request could not be handled." })),
+        }
+    };
+
+    let header = Header::from_bytes("Content-Type", "application/json").unwrap(); // Both are valid header bytes.
+    let response = Response::from_string(body.to_string()).with_status_code(status).with_header(header);
+    if let Err(e) = request.respond(response) {
+        eprintln!("Could not send a response: {}", e);
+    }
+}
+
+/// Answers a single GET request, given its path and query, with a status code and JSON body.
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::market_cache::MarketCache;
+/// use bdo_enhancement_profit_calculator::server::{handle_request, ServerOptions};
+/// use std::time::Duration;
+///
+/// let options = ServerOptions::default();
+/// let cache = MarketCache::new(Duration::from_secs(60));
+///
+/// let (status, body) = handle_request("/health", &options, &cache);
+/// assert_eq!(200, status);
+/// assert_eq!("ok", body["status"]);
+///
+/// // Requests are checked before anything is fetched.
+/// let (status, body) = handle_request("/items/11607/profit?start=4&end=2", &options, &cache);
+/// assert_eq!(400, status);
+/// assert!(body["error"].is_string());
+///
+/// let (status, _) = handle_request("/items/11607/simulate?runs=0", &options, &cache);
+/// assert_eq!(400, status);
+///
+/// let (status, _) = handle_request("/items/11607/prices?colour=red", &options, &cache);
+/// assert_eq!(400, status);
+///
+/// let (status, _) = handle_request("/items/11607/prices?region=mars", &options, &cache);
+/// assert_eq!(400, status);
+///
+/// let (status, _) = handle_request("/nowhere", &options, &cache);
+/// assert_eq!(404, status);
+///
+/// let (status, _) = handle_request("/categories/accessories/hat", &options, &cache);
+/// assert_eq!(404, status);
+///
+/// let (_, body) = handle_request("/categories", &options, &cache);
+/// let ring = body["categories"].as_array().unwrap().iter().find(|c| c["name"] == "Accessories/Ring").unwrap();
+/// assert_eq!(20, ring["main_category"]);
+/// assert_eq!("accessory", ring["enhancement_type"]);
+/// ```
+///
+pub fn handle_request(url: &str, options: &ServerOptions, cache: &MarketCache) -> (u16, Value) {
+    match route(url, options, cache) {
+        Ok(body) => (200, body),
+        Err(e) => (e.status, json!({ "error": e.message })),
+    }
+}
+
+fn route(url: &str, options: &ServerOptions, cache: &MarketCache) -> Result<Value, ApiError> {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let params = Params::parse(query)?;
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    match segments.as_slice() {
+        ["health"] => {
+            params.allow(&[])?;
+            Ok(json!({ "status": "ok" }))
+        }
+        ["categories"] => {
+            params.allow(&[])?;
+            Ok(list_categories())
+        }
+        ["categories", main, sub] => {
+            let categories = MarketCategory::parse(&format!("{}/{}", main, sub)).map_err(ApiError::not_found)?;
+            let (main_category, sub_category) = categories[0]; // A main and sub pair is always a single category.
+            get_category(main_category, sub_category, &params, options, cache)
+        }
+        ["items", id, "prices"] => get_prices(parse_segment(id, "item id")?, &params, options, cache),
+        ["items", id, "profit"] => get_profit(parse_segment(id, "item id")?, &params, options, cache),
+        ["items", id, "simulate"] => get_simulation(parse_segment(id, "item id")?, &params, options, cache),
+        ["items", id, "history"] => get_history(parse_segment(id, "item id")?, &params, options, cache),
+        _ => Err(ApiError::not_found(format!("Unknown path \"{}\".", path))),
+    }
+}
+
+fn list_categories() -> Value {
+    let categories: Vec<Value> = MarketCategory::all()
+        .iter()
+        .map(|category| {
+            let (main_category, sub_category) = category.get_ids();
+            json!({
+                "main_category": main_category,
+                "sub_category": sub_category,
+                "name": category.get_path(),
+                "enhancement_type": category.get_enhancement_type(),
+            })
+        })
+        .collect();
+    json!({ "categories": categories })
+}
+
+fn get_category(main_category: u16, sub_category: u16, params: &Params, options: &ServerOptions, cache: &MarketCache) -> Result<Value, ApiError> {
+    params.allow(&["region"])?;
+    let client = params.get_client(options)?;
+
+    let items = cache.get_category(client, main_category, sub_category).map_err(ApiError::market)?;
+    let items: Vec<Value> = items
+        .iter()
+        .map(|item| {
+            json!({
+                "item_id": item.get_item_id(),
+                "name": item.get_item_name(),
+                "grade": item.get_item_grade(),
+                "base_price": item.get_base_price(),
+                "stock": item.get_stock(),
+            })
+        })
+        .collect();
+
+    Ok(json!({
+        "region": client.get_region(),
+        "main_category": main_category,
+        "sub_category": sub_category,
+        "name": MarketCategory::find(main_category, sub_category).map(|c| c.get_path()),
+        "items": items,
+    }))
+}
+
+fn get_prices(item_id: u32, params: &Params, options: &ServerOptions, cache: &MarketCache) -> Result<Value, ApiError> {
+    params.allow(&["region", "levels"])?;
+    let client = params.get_client(options)?;
+    let levels: Vec<u8> = params.get_list("levels")?.unwrap_or_else(|| (0..=5).collect());
+    if let Some(level) = levels.iter().find(|l| **l > MAX_ENHANCEMENT_LEVEL) {
+        return Err(ApiError::bad_request(format!("Level {} is above the highest level ({}).", level, MAX_ENHANCEMENT_LEVEL)));
+    }
+
+    let mut prices = Vec::new();
+    for level in levels {
+        let item = cache.get_item(client, item_id, level).map_err(ApiError::market)?;
+        prices.push(price_json(level, &item));
+    }
+
+    Ok(json!({
+        "region": client.get_region(),
+        "item_id": item_id,
+        "levels": prices,
+    }))
+}
+
+fn price_json(level: u8, item: &ItemBuySellInfo) -> Value {
+    let estimate = item.get_sell_estimate();
+
+    json!({
+        "level": level,
+        "base_price": item.get_base_price(),
+        "lowest_listed": item.get_lowest_listed(),
+        "min_price": item.get_min_price(),
+        "max_price": item.get_max_price(),
+        "listed": item.get_bids().iter().map(|b| b.get_sell_count() as u64).sum::<u64>(),
+        "pre_orders": item.get_bids().iter().map(|b| b.get_buy_count() as u64).sum::<u64>(),
+        "cap_state": estimate.get_cap_state().get_name(),
+        "sale_chance": estimate.get_sale_chance(),
+        "expected_value": estimate.get_expected_value(),
+    })
+}
+
+fn get_profit(item_id: u32, params: &Params, options: &ServerOptions, cache: &MarketCache) -> Result<Value, ApiError> {
+    params.allow(&["region", "stacks", "start", "end", "tax"])?;
+    let client = params.get_client(options)?;
+    let route = params.get_route(&options.route)?;
+
+    // Priced the same way as a scan, so both give the same profit for an item. Ladders come from the cache too.
+    let cached = CachedClient::new(client, cache);
+    let mut market = AccMarketData::from_post(&cached, item_id).map_err(ApiError::market)?;
+
+    let mut routes = Vec::new();
+    for end_level in &route.target_levels {
+        let details = AccEnhancementDetails::new(ItemID::new(item_id), route.start_level, Some(route.stacks.clone()));
+        let breakdown = market.get_tap_breakdown(&details, *end_level, route.tax_rate).map_err(ApiError::market)?;
+        let unused_stacks = breakdown.get_unused_stacks().to_vec();
+        let profit = breakdown.into_profit_details();
+
+        routes.push(json!({
+            "start_level": route.start_level,
+            "end_level": end_level,
+            "unused_stacks": unused_stacks,
+            "make_cost": profit.get_make_cost(),
+            "actual_value": profit.get_actual_value(),
+            "profit": profit.get_profit(),
+            "profit_taxed": profit.get_profit_taxed(),
+        }));
+    }
+
+    Ok(json!({
+        "region": client.get_region(),
+        "item_id": item_id,
+        "stacks": route.stacks,
+        "tax_rate": route.tax_rate,
+        "routes": routes,
+    }))
+}
+
+fn get_simulation(item_id: u32, params: &Params, options: &ServerOptions, cache: &MarketCache) -> Result<Value, ApiError> {
+    params.allow(&["region", "stacks", "start", "end", "tax", "runs"])?;
+    let client = params.get_client(options)?;
+    let route = params.get_route(&options.route)?;
+    let runs: u32 = params.get("runs")?.unwrap_or(10000.min(options.max_runs));
+    if runs == 0 || runs > options.max_runs {
+        return Err(ApiError::bad_request(format!("Runs should be between 1 and {}, not {}.", options.max_runs, runs)));
+    }
+
+    // Priced the same way as /profit, so both give the same value and cost for a route.
+    let cached = CachedClient::new(client, cache);
+    let mut market = AccMarketData::from_post(&cached, item_id).map_err(ApiError::market)?;
+
+    let mut routes = Vec::new();
+    for end_level in &route.target_levels {
+        let details = AccEnhancementDetails::new(ItemID::new(item_id), route.start_level, Some(route.stacks.clone()));
+        let breakdown = market.get_tap_breakdown(&details, *end_level, route.tax_rate).map_err(ApiError::market)?;
+        let (start_cost, base_cost) = (breakdown.get_start_cost(), breakdown.get_base_cost());
+        let actual_value = breakdown.get_profit_details().get_actual_value();
+
+        let simulation = simulate_tap_mult(&details, *end_level, start_cost, base_cost, runs);
+        let metrics = calc_risk_metrics(&details, *end_level, start_cost, base_cost, actual_value, route.tax_rate);
+
+        routes.push(json!({
+            "start_level": route.start_level,
+            "end_level": end_level,
+            "actual_value": actual_value,
+            "mean_cost": simulation.get_mean_cost(),
+            "expected_cost": breakdown.get_profit_details().get_make_cost(),
+            "min_cost": simulation.get_min_cost(),
+            "median_cost": simulation.get_percentile_cost(50.0),
+            "p90_cost": simulation.get_percentile_cost(90.0),
+            "max_cost": simulation.get_max_cost(),
+            "mean_bases_used": simulation.get_mean_bases_used(),
+            "profit_chance": simulation.get_profit_chance(actual_value, route.tax_rate),
+            "expected_profit_chance": metrics.get_profit_chance(),
+        }));
+    }
+
+    Ok(json!({
+        "region": client.get_region(),
+        "item_id": item_id,
+        "stacks": route.stacks,
+        "tax_rate": route.tax_rate,
+        "runs": runs,
+        "routes": routes,
+    }))
+}
+
+fn get_history(item_id: u32, params: &Params, options: &ServerOptions, cache: &MarketCache) -> Result<Value, ApiError> {
+    params.allow(&["region", "level"])?;
+    let client = params.get_client(options)?;
+    let level: u8 = params.get("level")?.unwrap_or(0);
+    if level > MAX_ENHANCEMENT_LEVEL {
+        return Err(ApiError::bad_request(format!("Level {} is above the highest level ({}).", level, MAX_ENHANCEMENT_LEVEL)));
+    }
+
+    let history = cache.get_history(client, item_id, level).map_err(ApiError::market)?;
+
+    Ok(json!({
+        "region": client.get_region(),
+        "item_id": history.get_item_id(),
+        "level": history.get_enhancement_level(),
+        "prices": history.get_prices(),
+        "latest": history.get_latest_price(),
+        "average_7d": history.get_average(7),
+        "change_7d_percent": history.get_change_percent(7),
+    }))
+}
+
+fn parse_segment<T: FromStr>(segment: &str, name: &str) -> Result<T, ApiError> {
+    segment
+        .parse()
+        .map_err(|_| ApiError::bad_request(format!("Invalid {} \"{}\".", name, segment)))
+}
+
+/// Query parameters of a request.
+///
+struct Params {
+    values: HashMap<String, String>,
+}
+
+impl Params {
+    fn parse(query: &str) -> Result<Self, ApiError> {
+        let mut values = HashMap::new();
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let name = percent_decode(name)?;
+            if values.insert(name.clone(), percent_decode(value)?).is_some() {
+                return Err(ApiError::bad_request(format!("Parameter \"{}\" was given more than once.", name)));
+            }
+        }
+        Ok(Params { values })
+    }
+
+    /// Rejects any parameter not in `names`, so typos aren't silently ignored.
+    ///
+    fn allow(&self, names: &[&str]) -> Result<(), ApiError> {
+        match self.values.keys().find(|k| !names.contains(&k.as_str())) {
+            Some(name) if names.is_empty() => Err(ApiError::bad_request(format!("Unknown parameter \"{}\", none are expected.", name))),
+            Some(name) => Err(ApiError::bad_request(format!("Unknown parameter \"{}\", expected one of: {}.", name, names.join(", ")))),
+            None => Ok(()),
+        }
+    }
+
+    fn get<T: FromStr>(&self, name: &str) -> Result<Option<T>, ApiError> {
+        match self.values.get(name) {
+            Some(value) => value
+                .parse()
+                .map(Some)
+                .map_err(|_| ApiError::bad_request(format!("Invalid value \"{}\" for parameter \"{}\".", value, name))),
+            None => Ok(None),
+        }
+    }
+
+    fn get_list<T: FromStr>(&self, name: &str) -> Result<Option<Vec<T>>, ApiError> {
+        match self.values.get(name) {
+            Some(value) => value
+                .split(',')
+                .map(|v| {
+                    v.trim()
+                        .parse()
+                        .map_err(|_| ApiError::bad_request(format!("Invalid value \"{}\" in parameter \"{}\".", v, name)))
+                })
+                .collect::<Result<Vec<T>, ApiError>>()
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Gets the server's client for the requested region, or the default one.
+    ///
+    fn get_client<'a>(&self, options: &'a ServerOptions) -> Result<&'a dyn MarketClient, ApiError> {
+        let region = self.values.get("region").map(|r| r.to_lowercase()).unwrap_or_else(|| options.region.clone());
+        match options.clients.get(&region) {
+            Some(client) => Ok(client.as_ref()),
+            None => {
+                let mut regions: Vec<&str> = options.clients.keys().map(|r| r.as_str()).collect();
+                regions.sort();
+                Err(ApiError::bad_request(format!("Unknown region \"{}\", expected one of: {}.", region, regions.join(", "))))
+            }
+        }
+    }
+
+    /// Fills in the route from the parameters, using `defaults` for anything not given.
+    ///
+    fn get_route(&self, defaults: &ScanOptions) -> Result<ScanOptions, ApiError> {
+        let mut route = defaults.clone();
+        if let Some(stacks) = self.get_list("stacks")? {
+            route.stacks = stacks;
+        }
+        if let Some(start_level) = self.get("start")? {
+            route.start_level = start_level;
+        }
+        if let Some(end_level) = self.get("end")? {
+            route.target_levels = vec![end_level];
+        }
+        if let Some(tax_rate) = self.get("tax")? {
+            route.tax_rate = tax_rate;
+        }
+        route.validate().map_err(ApiError::bad_request)?;
+        Ok(route)
+    }
+}
+
+/// Decodes `%XX` escapes and `+` as a space.
+///
+fn percent_decode(value: &str) -> Result<String, ApiError> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let byte = value
+                    .get(i + 1..i + 3)
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| ApiError::bad_request(format!("Invalid escape in \"{}\".", value)))?;
+                decoded.push(byte);
+                i += 2;
+            }
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8(decoded).map_err(|_| ApiError::bad_request(format!("\"{}\" is not valid UTF-8.", value)))
+}
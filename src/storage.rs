@@ -0,0 +1,474 @@
+//! # Storage
+//!
+//! Storage keeps every market observation in a SQLite database, with when and in which region it was seen.
+//!
+//! The market only gives 90 days of daily prices, so recording snapshots regularly builds a finer history.
+//! The schema is upgraded when a database is opened, see [`Storage::open`].
+
+use crate::accessories::filter_accessories_category;
+use crate::bdo_market_requests::market_client::MarketClient;
+use crate::bdo_market_requests::{BiddingInfo, CategoryGivenInfo, HasId, ItemBuySellInfo, RegQueueItem, SpecificItemInfo};
+use crate::scan::ScanOptions;
+
+use rusqlite::{params, Connection, OptionalExtension, ToSql};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Each migration upgrades the schema by one version, and is never changed once released.
+///
+/// The schema version is kept in SQLite's `user_version`.
+const MIGRATIONS: [&str; 1] = ["
+    CREATE TABLE category_items (
+        observed_at INTEGER NOT NULL,
+        region TEXT NOT NULL,
+        main_category INTEGER NOT NULL,
+        sub_category INTEGER NOT NULL,
+        item_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        grade INTEGER NOT NULL,
+        base_price INTEGER NOT NULL,
+        stock INTEGER NOT NULL
+    );
+    CREATE INDEX category_items_item ON category_items (region, item_id, observed_at);
+
+    CREATE TABLE item_info (
+        observed_at INTEGER NOT NULL,
+        region TEXT NOT NULL,
+        item_id INTEGER NOT NULL,
+        enhancement_min INTEGER NOT NULL,
+        enhancement_max INTEGER NOT NULL,
+        base_price INTEGER NOT NULL,
+        stock INTEGER NOT NULL,
+        total_trades INTEGER NOT NULL,
+        price_cap_min INTEGER NOT NULL,
+        price_cap_max INTEGER NOT NULL,
+        last_sale_price INTEGER NOT NULL,
+        last_sale_time TEXT NOT NULL
+    );
+    CREATE INDEX item_info_item ON item_info (region, item_id, observed_at);
+
+    CREATE TABLE bid_ladders (
+        id INTEGER PRIMARY KEY,
+        observed_at INTEGER NOT NULL,
+        region TEXT NOT NULL,
+        item_id INTEGER NOT NULL,
+        enhancement_level INTEGER NOT NULL,
+        base_price INTEGER NOT NULL,
+        enhancement_group INTEGER NOT NULL,
+        enhancement_material_id INTEGER NOT NULL,
+        enhancement_material_base_price INTEGER NOT NULL,
+        enhancement_material_required_amount INTEGER NOT NULL,
+        max_bids_per_person INTEGER NOT NULL
+    );
+    CREATE INDEX bid_ladders_item ON bid_ladders (region, item_id, enhancement_level, observed_at);
+
+    CREATE TABLE bids (
+        ladder_id INTEGER NOT NULL REFERENCES bid_ladders (id),
+        price INTEGER NOT NULL,
+        sell_count INTEGER NOT NULL,
+        buy_count INTEGER NOT NULL
+    );
+    CREATE INDEX bids_ladder ON bids (ladder_id);
+
+    CREATE TABLE queue_items (
+        observed_at INTEGER NOT NULL,
+        region TEXT NOT NULL,
+        item_id INTEGER NOT NULL,
+        enhancement_level INTEGER NOT NULL,
+        listed_price INTEGER NOT NULL,
+        registered_at TEXT NOT NULL
+    );
+    CREATE INDEX queue_items_item ON queue_items (region, item_id, observed_at);
+"];
+
+/// A SQLite database of market observations.
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::bdo_market_requests::{BiddingInfo, ItemBuySellInfo};
+/// use bdo_enhancement_profit_calculator::storage::Storage;
+///
+/// let mut storage = Storage::open_in_memory().unwrap();
+/// assert_eq!(1, storage.get_schema_version().unwrap());
+///
+/// let ladder = ItemBuySellInfo::new(vec![BiddingInfo::new(1000, 2, 0), BiddingInfo::new(1100, 0, 5)], 1000, 0, 0, 0, 0, 20);
+/// storage.record_bids("eu", 100, 11607, 0, &ladder).unwrap();
+/// storage.record_bids("eu", 200, 11607, 0, &ladder).unwrap();
+///
+/// let history = storage.get_ladders("eu", 11607, 0, 150, u64::MAX).unwrap();
+/// assert_eq!(1, history.len());
+/// assert_eq!(200, history[0].0);
+/// assert_eq!(1000, history[0].1.get_lowest_listed());
+/// ```
+///
+pub struct Storage {
+    connection: Connection,
+}
+
+impl Storage {
+    /// Opens a database, creating it if needed, and upgrades its schema.
+    ///
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let connection = Connection::open(path).map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+        Storage::from_connection(connection)
+    }
+
+    pub fn open_in_memory() -> Result<Self, String> {
+        let connection = Connection::open_in_memory().map_err(|e| e.to_string())?;
+        Storage::from_connection(connection)
+    }
+
+    fn from_connection(connection: Connection) -> Result<Self, String> {
+        let mut storage = Storage { connection };
+        storage.migrate()?;
+        Ok(storage)
+    }
+
+    pub fn get_schema_version(&self) -> Result<usize, String> {
+        self.connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .map_err(|e| e.to_string())
+    }
+
+    /// Applies every migration the database hasn't had yet, each in its own transaction.
+    ///
+    fn migrate(&mut self) -> Result<(), String> {
+        let version = self.get_schema_version()?;
+        if version > MIGRATIONS.len() {
+            return Err(format!(
+                "The database has schema version {}, but only up to {} is known. It was made by a newer version.",
+                version,
+                MIGRATIONS.len()
+            ));
+        }
+
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let transaction = self.connection.transaction().map_err(|e| e.to_string())?;
+            transaction
+                .execute_batch(migration)
+                .map_err(|e| format!("Migration to schema version {} failed: {}", i + 1, e))?;
+            transaction.pragma_update(None, "user_version", i + 1).map_err(|e| e.to_string())?;
+            transaction.commit().map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Records every item listed in a category.
+    ///
+    pub fn record_category(
+        &mut self,
+        region: &str,
+        observed_at: u64,
+        main_category: u16,
+        sub_category: u16,
+        items: &[CategoryGivenInfo],
+    ) -> Result<(), String> {
+        let transaction = self.connection.transaction().map_err(|e| e.to_string())?;
+        {
+            let mut insert = transaction
+                .prepare_cached(
+                    "INSERT INTO category_items (observed_at, region, main_category, sub_category, item_id, name, grade, base_price, stock)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                )
+                .map_err(|e| e.to_string())?;
+            for item in items {
+                insert
+                    .execute(params![
+                        observed_at,
+                        region,
+                        main_category,
+                        sub_category,
+                        item.get_item_id(),
+                        item.get_item_name(),
+                        item.get_item_grade(),
+                        item.get_base_price(),
+                        item.get_stock()
+                    ])
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        transaction.commit().map_err(|e| e.to_string())
+    }
+
+    /// Records the per level details of items, see [`SpecificItemInfo`].
+    ///
+    pub fn record_item_info(&mut self, region: &str, observed_at: u64, infos: &[SpecificItemInfo]) -> Result<(), String> {
+        let transaction = self.connection.transaction().map_err(|e| e.to_string())?;
+        {
+            let mut insert = transaction
+                .prepare_cached(
+                    "INSERT INTO item_info (observed_at, region, item_id, enhancement_min, enhancement_max, base_price, stock,
+                        total_trades, price_cap_min, price_cap_max, last_sale_price, last_sale_time)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                )
+                .map_err(|e| e.to_string())?;
+            for info in infos {
+                insert
+                    .execute(params![
+                        observed_at,
+                        region,
+                        info.get_item_id(),
+                        info.get_enhancement_min(),
+                        info.get_enhancement_max(),
+                        info.get_base_price(),
+                        info.get_stock(),
+                        info.get_total_trades(),
+                        info.get_price_cap_min(),
+                        info.get_price_cap_max(),
+                        info.get_last_sale_price(),
+                        info.get_last_sale_time()
+                    ])
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        transaction.commit().map_err(|e| e.to_string())
+    }
+
+    /// Records an item's bid ladder at a level.
+    ///
+    pub fn record_bids(&mut self, region: &str, observed_at: u64, item_id: u32, enhancement_level: u8, info: &ItemBuySellInfo) -> Result<(), String> {
+        let transaction = self.connection.transaction().map_err(|e| e.to_string())?;
+        transaction
+            .execute(
+                "INSERT INTO bid_ladders (observed_at, region, item_id, enhancement_level, base_price, enhancement_group,
+                    enhancement_material_id, enhancement_material_base_price, enhancement_material_required_amount, max_bids_per_person)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    observed_at,
+                    region,
+                    item_id,
+                    enhancement_level,
+                    info.get_base_price(),
+                    info.get_enhancement_group(),
+                    info.get_enhancement_material_id(),
+                    info.get_enhancement_material_base_price(),
+                    info.get_enhancement_material_required_amount(),
+                    info.get_max_bids_per_person()
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        let ladder_id = transaction.last_insert_rowid();
+        {
+            let mut insert = transaction
+                .prepare_cached("INSERT INTO bids (ladder_id, price, sell_count, buy_count) VALUES (?1, ?2, ?3, ?4)")
+                .map_err(|e| e.to_string())?;
+            for bid in info.get_bids() {
+                insert
+                    .execute(params![ladder_id, bid.get_bidding_price(), bid.get_sell_count(), bid.get_buy_count()])
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        transaction.commit().map_err(|e| e.to_string())
+    }
+
+    /// Records the items waiting to be registered.
+    ///
+    pub fn record_queue(&mut self, region: &str, observed_at: u64, items: &[RegQueueItem]) -> Result<(), String> {
+        let transaction = self.connection.transaction().map_err(|e| e.to_string())?;
+        {
+            let mut insert = transaction
+                .prepare_cached(
+                    "INSERT INTO queue_items (observed_at, region, item_id, enhancement_level, listed_price, registered_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                )
+                .map_err(|e| e.to_string())?;
+            for item in items {
+                insert
+                    .execute(params![
+                        observed_at,
+                        region,
+                        item.get_item_id(),
+                        item.get_enhancement_level(),
+                        item.get_listed_price(),
+                        item.get_registered_timestamp()
+                    ])
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        transaction.commit().map_err(|e| e.to_string())
+    }
+
+    /// Gets every recorded bid ladder of an item at a level, observed between `from` and `to` (inclusive), oldest first.
+    ///
+    pub fn get_ladders(&self, region: &str, item_id: u32, enhancement_level: u8, from: u64, to: u64) -> Result<Vec<(u64, ItemBuySellInfo)>, String> {
+        let ladders = self.query_ladders(
+            "region = ?1 AND observed_at BETWEEN ?2 AND ?3 AND item_id = ?4 AND enhancement_level = ?5",
+            params![region, clamp_time(from), clamp_time(to), item_id, enhancement_level],
+        )?;
+        Ok(ladders.into_iter().map(|ladder| (ladder.observed_at, ladder.info)).collect())
+    }
+
+    /// Gets every recorded bid ladder in a region, observed between `from` and `to` (inclusive), oldest first.
+    ///
+    pub fn get_region_ladders(&self, region: &str, from: u64, to: u64) -> Result<Vec<LadderObservation>, String> {
+        self.query_ladders("region = ?1 AND observed_at BETWEEN ?2 AND ?3", params![region, clamp_time(from), clamp_time(to)])
+    }
+
+    fn query_ladders(&self, filter: &str, filter_params: &[&dyn ToSql]) -> Result<Vec<LadderObservation>, String> {
+        let mut ladders_query = self
+            .connection
+            .prepare_cached(&format!(
+                "SELECT id, observed_at, item_id, enhancement_level, base_price, enhancement_group, enhancement_material_id,
+                    enhancement_material_base_price, enhancement_material_required_amount, max_bids_per_person
+                 FROM bid_ladders
+                 WHERE {}
+                 ORDER BY observed_at, id",
+                filter
+            ))
+            .map_err(|e| e.to_string())?;
+        let mut bids_query = self
+            .connection
+            .prepare_cached("SELECT price, sell_count, buy_count FROM bids WHERE ladder_id = ?1 ORDER BY price")
+            .map_err(|e| e.to_string())?;
+
+        let rows = ladders_query
+            .query_map(filter_params, |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    (row.get(1)?, row.get(2)?, row.get(3)?),
+                    (row.get(4)?, row.get(5)?, row.get(6)?, row.get(7)?, row.get(8)?, row.get(9)?),
+                ))
+            })
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+
+        let mut ladders = Vec::new();
+        for (ladder_id, (observed_at, item_id, enhancement_level), (base_price, group, material_id, material_price, material_amount, max_bids)) in rows {
+            let bids = bids_query
+                .query_map(params![ladder_id], |row| Ok(BiddingInfo::new(row.get(0)?, row.get(1)?, row.get(2)?)))
+                .map_err(|e| e.to_string())?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| e.to_string())?;
+            ladders.push(LadderObservation {
+                observed_at,
+                item_id,
+                enhancement_level,
+                info: ItemBuySellInfo::new(bids, base_price, group, material_id, material_price, material_amount, max_bids),
+            });
+        }
+        Ok(ladders)
+    }
+
+    /// Gets every recorded category listing in a region, observed between `from` and `to` (inclusive), oldest first.
+    ///
+    pub fn get_region_items(&self, region: &str, from: u64, to: u64) -> Result<Vec<(u64, CategoryGivenInfo)>, String> {
+        let mut query = self
+            .connection
+            .prepare_cached(
+                "SELECT observed_at, item_id, name, grade, base_price, stock
+                 FROM category_items
+                 WHERE region = ?1 AND observed_at BETWEEN ?2 AND ?3
+                 ORDER BY observed_at",
+            )
+            .map_err(|e| e.to_string())?;
+
+        let items = query
+            .query_map(params![region, clamp_time(from), clamp_time(to)], |row| {
+                Ok((
+                    row.get(0)?,
+                    CategoryGivenInfo::new(row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?),
+                ))
+            })
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        Ok(items)
+    }
+
+    /// Gets the time of the latest observation of anything in a region, if there is one.
+    ///
+    pub fn get_last_observed(&self, region: &str) -> Result<Option<u64>, String> {
+        self.connection
+            .query_row(
+                "SELECT MAX(observed_at) FROM (
+                    SELECT observed_at FROM category_items WHERE region = ?1
+                    UNION ALL SELECT observed_at FROM bid_ladders WHERE region = ?1
+                    UNION ALL SELECT observed_at FROM queue_items WHERE region = ?1
+                )",
+                params![region],
+                |row| row.get::<_, Option<u64>>(0),
+            )
+            .optional()
+            .map(|v| v.flatten())
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// A bid ladder, with when and for which item and level it was recorded.
+///
+pub struct LadderObservation {
+    pub observed_at: u64,
+    pub item_id: u32,
+    pub enhancement_level: u8,
+    pub info: ItemBuySellInfo,
+}
+
+/// SQLite integers are signed, so times past `i64::MAX` are treated as `i64::MAX`.
+///
+fn clamp_time(time: u64) -> u64 {
+    time.min(i64::MAX as u64)
+}
+
+/// Counts of what a snapshot recorded.
+///
+pub struct SnapshotSummary {
+    pub observed_at: u64,
+    pub items: usize,
+    pub ladders: usize,
+    pub queued: usize,
+    /// Requests that failed, and were skipped.
+    pub failed: Vec<String>,
+}
+
+/// Records a snapshot of a region: every item in the scan's categories, the prices of every level and
+/// bid ladders up to the highest target of the items that pass its filters, and the registration queue.
+///
+pub fn record_snapshot(storage: &mut Storage, client: &dyn MarketClient, options: &ScanOptions) -> Result<SnapshotSummary, String> {
+    let region = client.get_region();
+    let observed_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let mut summary = SnapshotSummary {
+        observed_at,
+        items: 0,
+        ladders: 0,
+        queued: 0,
+        failed: Vec::new(),
+    };
+
+    let mut candidates = Vec::new();
+    for (main_category, sub_category) in &options.categories {
+        let items = client.get_category(*main_category, *sub_category)?;
+        storage.record_category(region, observed_at, *main_category, *sub_category, &items)?;
+        summary.items += items.len();
+        candidates.extend(items);
+    }
+
+    let candidates = filter_accessories_category(candidates, options.grade, options.min_price, options.max_price);
+    let max_level = options.target_levels.iter().copied().max().unwrap_or(0);
+    for item in candidates.iter().filter(|item| options.is_name_allowed(item.get_item_name())) {
+        match client.get_item_levels(item.get_item_id()) {
+            Ok(levels) => storage.record_item_info(region, observed_at, &levels)?,
+            Err(e) => summary.failed.push(format!("{} levels: {}", item.get_item_name(), e)),
+        }
+        for level in 0..=max_level {
+            match client.get_bids(item.get_item_id(), level) {
+                Ok(info) => {
+                    storage.record_bids(region, observed_at, item.get_item_id(), level, &info)?;
+                    summary.ladders += 1;
+                }
+                Err(e) => summary.failed.push(format!("{} at level {}: {}", item.get_item_name(), level, e)),
+            }
+        }
+    }
+
+    match client.get_registration_queue() {
+        Ok(queue) => {
+            storage.record_queue(region, observed_at, &queue)?;
+            summary.queued = queue.len();
+        }
+        Err(e) => summary.failed.push(format!("Registration queue: {}", e)),
+    }
+
+    Ok(summary)
+}
@@ -0,0 +1,144 @@
+//! # Trends
+//!
+//! Trends ranks the items moving on a region's market, as a morning check of what to look at.
+//!
+//! An item is a mover if it's on the hot list or waiting in the registration queue. Each mover is scored
+//! from its price change against its recent average, whether it's hot, and how many are queued.
+
+use crate::bdo_market_requests::market_client::MarketClient;
+use crate::bdo_market_requests::{HasId, HotItem, PriceHistory, RegQueueItem};
+use crate::market_cache::MarketCache;
+
+use serde::Serialize;
+use std::sync::Arc;
+
+/// Score added for being on the hot list, worth the same as a 10% price change.
+///
+pub const HOT_WEIGHT: f64 = 10.0;
+
+/// Score added for every listing waiting in the registration queue.
+///
+pub const QUEUE_WEIGHT: f64 = 5.0;
+
+/// An item at a level, and why it's moving.
+///
+#[derive(Serialize, Debug, Clone)]
+pub struct Mover {
+    pub item_id: u32,
+    pub enhancement_level: u8,
+    /// Market price from the hot list, or the lowest queued price for items only in the queue.
+    pub base_price: u64,
+    pub hot: bool,
+    /// Last price move on the hot list, 0 for items not on it.
+    pub hot_change: i64,
+    /// Change of the latest price against the average of the days before it, in percent.
+    pub change_percent: Option<f64>,
+    /// Listings waiting in the registration queue.
+    pub queued: usize,
+    pub score: f64,
+}
+
+/// Ranks the hot list and registration queue, highest score first.
+///
+/// `histories` are matched to movers by item and level, movers without one have no price change.
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::bdo_market_requests::{HotItem, PriceHistory, RegQueueItem};
+/// use bdo_enhancement_profit_calculator::trends::rank_movers;
+///
+/// let hot = HotItem::build_vec(String::from("11607-4-4-900-2-150-1-50-800-1000-900-0"));
+/// let queue = RegQueueItem::build_vec(String::from("11853-0-90-1700000000|11853-0-95-1700000100"));
+/// // Ring at TET is up 50% on its weekly average.
+/// let history = PriceHistory::build(11607, 4, String::from("600-600-600-900"));
+///
+/// let movers = rank_movers(&hot, &queue, &[&history], 7);
+///
+/// assert_eq!(11607, movers[0].item_id);
+/// assert_eq!(Some(50.0), movers[0].change_percent);
+/// assert_eq!(60.0, movers[0].score);
+///
+/// assert_eq!(11853, movers[1].item_id);
+/// assert_eq!(2, movers[1].queued);
+/// assert_eq!(90, movers[1].base_price);
+/// ```
+///
+pub fn rank_movers(hot: &[HotItem], queue: &[RegQueueItem], histories: &[&PriceHistory], days: usize) -> Vec<Mover> {
+    let mut movers: Vec<Mover> = Vec::new();
+
+    for item in hot {
+        movers.push(Mover {
+            item_id: item.get_item_id(),
+            enhancement_level: item.get_enhancement_min(),
+            base_price: item.get_base_price(),
+            hot: true,
+            hot_change: item.get_signed_price_change(),
+            change_percent: None,
+            queued: 0,
+            score: 0.0,
+        });
+    }
+
+    for item in queue {
+        let key = (item.get_item_id(), item.get_enhancement_level());
+        match movers.iter_mut().find(|m| (m.item_id, m.enhancement_level) == key) {
+            Some(mover) => {
+                mover.queued += 1;
+                if !mover.hot {
+                    mover.base_price = mover.base_price.min(item.get_listed_price());
+                }
+            }
+            None => movers.push(Mover {
+                item_id: key.0,
+                enhancement_level: key.1,
+                base_price: item.get_listed_price(),
+                hot: false,
+                hot_change: 0,
+                change_percent: None,
+                queued: 1,
+                score: 0.0,
+            }),
+        }
+    }
+
+    for mover in &mut movers {
+        mover.change_percent = histories
+            .iter()
+            .find(|h| h.get_item_id() == mover.item_id && h.get_enhancement_level() == mover.enhancement_level)
+            .and_then(|h| h.get_change_percent(days));
+
+        mover.score = mover.change_percent.map(|c| c.abs()).unwrap_or(0.0)
+            + if mover.hot { HOT_WEIGHT } else { 0.0 }
+            + mover.queued as f64 * QUEUE_WEIGHT;
+    }
+
+    movers.sort_by(|a, b| b.score.total_cmp(&a.score));
+    movers
+}
+
+/// Fetches the hot list, registration queue and the price history of every mover, and ranks them.
+///
+/// Histories go through `cache`, so checking again later in the day doesn't request them again.
+/// Movers whose history can't be fetched are still ranked, without a price change.
+///
+pub fn get_movers(client: &dyn MarketClient, cache: &MarketCache, days: usize) -> Result<Vec<Mover>, String> {
+    let hot = client.get_hot_list()?;
+    let queue = client.get_registration_queue()?;
+
+    let mut keys: Vec<(u32, u8)> = hot.iter().map(|h| (h.get_item_id(), h.get_enhancement_min())).collect();
+    for item in &queue {
+        let key = (item.get_item_id(), item.get_enhancement_level());
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+
+    let histories: Vec<Arc<PriceHistory>> = keys
+        .iter()
+        .filter_map(|(item_id, level)| cache.get_history(client, *item_id, *level).ok())
+        .collect();
+    let histories: Vec<&PriceHistory> = histories.iter().map(|h| h.as_ref()).collect();
+
+    Ok(rank_movers(&hot, &queue, &histories, days))
+}
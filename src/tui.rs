@@ -0,0 +1,468 @@
+//! # Tui
+//!
+//! Tui browses a scan in the terminal: a table of results sortable by column, and a detail view per item
+//! with its price history and every tap of its route, whose stacks can be changed and recalculated.
+
+use bdo_enhancement_profit_calculator::accessories::{calc_risk_metrics, AccEnhancementDetails, AccMarketData, AccRouteBreakdown};
+use bdo_enhancement_profit_calculator::bdo_market_requests::market_client::MarketClient;
+use bdo_enhancement_profit_calculator::bdo_market_requests::{HasId, ItemBuySellInfo, ItemID, PriceHistory, SpecificItemInfo};
+use bdo_enhancement_profit_calculator::scan::{get_scan_candidates, scan_item, ScanOptions, ScanResult};
+use bdo_enhancement_profit_calculator::units::Silver;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Cell, Paragraph, Row, Sparkline, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Messages sent from the background threads to the UI.
+enum Update {
+    Total(usize),
+    Result(ScanResult),
+    ScanDone,
+    Detail(Box<ItemDetail>),
+    Error(String),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SortColumn {
+    Name,
+    BuyAt,
+    MakeCost,
+    SellAt,
+    Profit,
+    ProfitTaxed,
+}
+
+/// Everything fetched when drilling into an item.
+struct ItemDetail {
+    item_id: u32,
+    name: String,
+    start_level: u8,
+    end_level: u8,
+    levels: Vec<SpecificItemInfo>,
+    base_info: ItemBuySellInfo,
+    target_info: ItemBuySellInfo,
+    history: Option<PriceHistory>,
+    stacks: Vec<u16>,
+    selected_step: usize,
+}
+
+impl ItemDetail {
+    /// Prices the route at the current stacks the same way as the scan, from what was fetched so no requests are made.
+    fn get_breakdown(&self, client: &dyn MarketClient, tax_rate: f64) -> Result<AccRouteBreakdown, String> {
+        let mut market = AccMarketData::new(client, self.item_id, self.levels.clone());
+        market.add_ladder(0, self.base_info.clone());
+        market.add_ladder(self.end_level, self.target_info.clone());
+        let details = AccEnhancementDetails::new(ItemID::new(self.item_id), self.start_level, Some(self.stacks.clone()));
+        market.get_tap_breakdown(&details, self.end_level, tax_rate)
+    }
+
+    /// Gets what a level costs to buy, as the scan prices it.
+    fn get_level_cost(&self, enhancement_level: u8) -> Silver {
+        Silver::from(SpecificItemInfo::find_level(&self.levels, enhancement_level).map(|l| l.get_lowest_listed()).unwrap_or(0))
+    }
+}
+
+struct App {
+    client: Arc<dyn MarketClient>,
+    options: ScanOptions,
+    results: Vec<ScanResult>,
+    total: Option<usize>,
+    scanning: bool,
+    sort: SortColumn,
+    descending: bool,
+    table_state: TableState,
+    detail: Option<ItemDetail>,
+    loading_detail: bool,
+    status: String,
+}
+
+/// Runs the terminal UI until the user quits.
+///
+pub fn run_tui(client: Box<dyn MarketClient>, options: ScanOptions) -> Result<(), String> {
+    let client: Arc<dyn MarketClient> = Arc::from(client);
+    let (sender, receiver) = mpsc::channel();
+    start_scan(client.clone(), options.clone(), sender.clone());
+
+    let mut app = App {
+        client,
+        options,
+        results: Vec::new(),
+        total: None,
+        scanning: true,
+        sort: SortColumn::ProfitTaxed,
+        descending: true,
+        table_state: TableState::default().with_selected(0),
+        detail: None,
+        loading_detail: false,
+        status: String::new(),
+    };
+
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &mut app, &receiver, &sender);
+    ratatui::restore();
+    result
+}
+
+fn start_scan(client: Arc<dyn MarketClient>, options: ScanOptions, sender: Sender<Update>) {
+    thread::spawn(move || {
+        let candidates = match get_scan_candidates(client.as_ref(), &options) {
+            Ok(v) => v,
+            Err(e) => {
+                let _ = sender.send(Update::Error(e));
+                return;
+            }
+        };
+        let _ = sender.send(Update::Total(candidates.len() * options.target_levels.len()));
+        for item in &candidates {
+            for result in scan_item(client.as_ref(), item, &options) {
+                if sender.send(Update::Result(result)).is_err() {
+                    return; // The UI has closed.
+                }
+            }
+        }
+        let _ = sender.send(Update::ScanDone);
+    });
+}
+
+fn start_detail(client: Arc<dyn MarketClient>, result: &ScanResult, sender: Sender<Update>) {
+    let item_id = result.get_item_id();
+    let name = result.get_name().to_owned();
+    let start_level = result.get_start_level();
+    let end_level = result.get_end_level();
+    let stacks = result.get_stacks().to_vec();
+
+    thread::spawn(move || {
+        let fetch = || -> Result<ItemDetail, String> {
+            let levels = client.get_item_levels(item_id)?;
+            let base_info = client.get_bids(item_id, 0)?;
+            let target_info = client.get_bids(item_id, end_level)?;
+            let history = client.get_history(item_id, end_level).ok();
+
+            Ok(ItemDetail {
+                item_id,
+                name,
+                start_level,
+                end_level,
+                levels,
+                base_info,
+                target_info,
+                history,
+                stacks,
+                selected_step: 0,
+            })
+        };
+        let update = match fetch() {
+            Ok(detail) => Update::Detail(Box::new(detail)),
+            Err(e) => Update::Error(e),
+        };
+        let _ = sender.send(update);
+    });
+}
+
+fn event_loop(
+    terminal: &mut DefaultTerminal,
+    app: &mut App,
+    receiver: &Receiver<Update>,
+    sender: &Sender<Update>,
+) -> Result<(), String> {
+    loop {
+        while let Ok(update) = receiver.try_recv() {
+            match update {
+                Update::Total(total) => app.total = Some(total),
+                Update::Result(result) => {
+                    app.results.push(result);
+                    sort_results(app);
+                }
+                Update::ScanDone => {
+                    app.scanning = false;
+                    app.status = String::from("Scan finished");
+                }
+                Update::Detail(detail) => {
+                    app.loading_detail = false;
+                    app.detail = Some(*detail);
+                }
+                Update::Error(e) => {
+                    app.loading_detail = false;
+                    app.status = format!("Error: {}", e);
+                }
+            }
+        }
+
+        terminal.draw(|frame| draw(frame, app)).map_err(|e| e.to_string())?;
+
+        if !event::poll(Duration::from_millis(200)).map_err(|e| e.to_string())? {
+            continue;
+        }
+        let key = match event::read().map_err(|e| e.to_string())? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            _ => continue,
+        };
+
+        if let Some(detail) = app.detail.as_mut() {
+            match key.code {
+                KeyCode::Esc | KeyCode::Backspace => app.detail = None,
+                KeyCode::Char('q') => return Ok(()),
+                KeyCode::Up => detail.selected_step = detail.selected_step.saturating_sub(1),
+                KeyCode::Down => {
+                    let steps = (detail.end_level - detail.start_level) as usize;
+                    detail.selected_step = (detail.selected_step + 1).min(steps - 1);
+                }
+                KeyCode::Left => adjust_stack(detail, -1),
+                KeyCode::Right => adjust_stack(detail, 1),
+                KeyCode::PageDown => adjust_stack(detail, -10),
+                KeyCode::PageUp => adjust_stack(detail, 10),
+                _ => {}
+            }
+            continue;
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Up => app.table_state.select_previous(),
+            KeyCode::Down => app.table_state.select_next(),
+            KeyCode::Char(c @ '1'..='6') => {
+                let column = match c {
+                    '1' => SortColumn::Name,
+                    '2' => SortColumn::BuyAt,
+                    '3' => SortColumn::MakeCost,
+                    '4' => SortColumn::SellAt,
+                    '5' => SortColumn::Profit,
+                    _ => SortColumn::ProfitTaxed,
+                };
+                if app.sort == column {
+                    app.descending = !app.descending;
+                } else {
+                    app.sort = column;
+                    app.descending = column != SortColumn::Name;
+                }
+                sort_results(app);
+            }
+            KeyCode::Enter if !app.loading_detail => {
+                if let Some(result) = app.table_state.selected().and_then(|i| app.results.get(i)) {
+                    app.loading_detail = true;
+                    app.status = format!("Loading {}...", result.get_name());
+                    start_detail(app.client.clone(), result, sender.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn adjust_stack(detail: &mut ItemDetail, change: i32) {
+    let stack = &mut detail.stacks[detail.selected_step];
+    *stack = (*stack as i32 + change).clamp(0, 999) as u16;
+}
+
+fn sort_results(app: &mut App) {
+    let selected_id = app
+        .table_state
+        .selected()
+        .and_then(|i| app.results.get(i))
+        .map(|r| (r.get_item_id(), r.get_end_level()));
+
+    let sort = app.sort;
+    app.results.sort_by(|a, b| {
+        let (pa, pb) = (a.get_profit_details(), b.get_profit_details());
+        let ordering = match sort {
+            SortColumn::Name => a.get_name().cmp(b.get_name()),
+            SortColumn::BuyAt => a.get_base_price().cmp(&b.get_base_price()),
+            SortColumn::MakeCost => pa.get_make_cost().cmp(&pb.get_make_cost()),
+            SortColumn::SellAt => pa.get_actual_value().cmp(&pb.get_actual_value()),
+            SortColumn::Profit => pa.get_profit().cmp(&pb.get_profit()),
+            SortColumn::ProfitTaxed => pa.get_profit_taxed().cmp(&pb.get_profit_taxed()),
+        };
+        if app.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+
+    // Keep the same item selected as results come in.
+    if let Some(id) = selected_id {
+        let index = app.results.iter().position(|r| (r.get_item_id(), r.get_end_level()) == id);
+        app.table_state.select(index.or(Some(0)));
+    }
+}
+
+fn draw(frame: &mut Frame, app: &mut App) {
+    let [main, footer] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+
+    let keys = if app.detail.is_some() {
+        "↑/↓ step  ←/→ stack ±1  PgUp/PgDn stack ±10  Esc back  q quit"
+    } else {
+        "↑/↓ select  Enter details  1-6 sort by column  q quit"
+    };
+    let status = if app.status.is_empty() { String::new() } else { format!("  |  {}", app.status) };
+    frame.render_widget(Paragraph::new(format!("{}{}", keys, status)).dim(), footer);
+
+    match &app.detail {
+        Some(detail) => draw_detail(frame, main, detail, app.client.as_ref(), app.options.tax_rate),
+        None => draw_table(frame, main, app),
+    }
+}
+
+fn draw_table(frame: &mut Frame, area: ratatui::layout::Rect, app: &mut App) {
+    let progress = match app.total {
+        Some(total) if app.scanning => format!("scanning {}/{}", app.results.len(), total),
+        Some(total) => format!("{} checked", total),
+        None => String::from("fetching categories"),
+    };
+    let title = format!(" Accessories ({}, {}) ", app.client.get_region(), progress);
+
+    let arrow = if app.descending { "▼" } else { "▲" };
+    let headers = [
+        (SortColumn::Name, "1 Name"),
+        (SortColumn::BuyAt, "2 Buy at"),
+        (SortColumn::MakeCost, "3 Make cost"),
+        (SortColumn::SellAt, "4 Sell at"),
+        (SortColumn::Profit, "5 Profit"),
+        (SortColumn::ProfitTaxed, "6 After tax"),
+    ];
+    let mut header: Vec<Cell> = headers
+        .iter()
+        .map(|(column, name)| {
+            if *column == app.sort {
+                Cell::from(format!("{} {}", name, arrow))
+            } else {
+                Cell::from(*name)
+            }
+        })
+        .collect();
+    header.insert(1, Cell::from("Route"));
+
+    let rows = app.results.iter().map(|result| {
+        let profit_details = result.get_profit_details();
+        // Items whose market couldn't be read show a dash instead of made up amounts.
+        let amount = |value: Silver| if profit_details.is_priced() { value.to_string() } else { "-".to_owned() };
+        let row = Row::new(vec![
+            Cell::from(result.get_name().to_owned()),
+            Cell::from(format!("{} -> {}", result.get_start_level(), result.get_end_level())),
+            Cell::from(result.get_base_price().to_string()),
+            Cell::from(amount(profit_details.get_make_cost())),
+            Cell::from(amount(profit_details.get_actual_value())),
+            Cell::from(amount(profit_details.get_profit())),
+            Cell::from(amount(profit_details.get_profit_taxed())),
+        ]);
+        if result.is_profitable(&app.options) {
+            row.green()
+        } else {
+            row
+        }
+    });
+
+    let widths = [
+        Constraint::Min(24),
+        Constraint::Length(7),
+        Constraint::Length(14),
+        Constraint::Length(14),
+        Constraint::Length(14),
+        Constraint::Length(14),
+        Constraint::Length(14),
+    ];
+    let table = Table::new(rows, widths)
+        .header(Row::new(header).bold())
+        .block(Block::bordered().title(title))
+        .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+
+    frame.render_stateful_widget(table, area, &mut app.table_state);
+}
+
+fn draw_detail(frame: &mut Frame, area: ratatui::layout::Rect, detail: &ItemDetail, client: &dyn MarketClient, tax_rate: f64) {
+    let [left, right] = Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(area);
+    let [base_area, target_area] = Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(left);
+    let [steps_area, summary_area, history_area] =
+        Layout::vertical([Constraint::Min(8), Constraint::Length(6), Constraint::Length(8)]).areas(right);
+
+    frame.render_widget(bid_ladder(&detail.base_info, " Bid ladder: base "), base_area);
+    frame.render_widget(bid_ladder(&detail.target_info, &format!(" Bid ladder: level {} ", detail.end_level)), target_area);
+
+    // Per-level breakdown, recomputed from the fetched prices whenever a stack changes.
+    let title = format!(" {} ({}) ", detail.name, detail.item_id);
+    let breakdown = match detail.get_breakdown(client, tax_rate) {
+        Ok(v) => v,
+        Err(e) => {
+            frame.render_widget(Paragraph::new(e).block(Block::bordered().title(title)), steps_area);
+            return;
+        }
+    };
+    let mut rows = Vec::new();
+    for (i, step) in breakdown.get_steps().iter().enumerate() {
+        let row = Row::new(vec![
+            format!("{} -> {}", step.get_from_level(), step.get_to_level()),
+            step.get_stack().to_string(),
+            step.get_chance().to_string(),
+            format!("{:.2}", step.get_expected_attempts()),
+            format!("{:.2}", step.get_expected_bases()),
+            step.get_cumulative_cost().to_string(),
+        ]);
+        rows.push(if i == detail.selected_step { row.reversed() } else { row });
+    }
+    let steps = Table::new(
+        rows,
+        [
+            Constraint::Length(8),
+            Constraint::Length(6),
+            Constraint::Length(8),
+            Constraint::Length(9),
+            Constraint::Length(7),
+            Constraint::Min(12),
+        ],
+    )
+    .header(Row::new(vec!["Step", "Stack", "Chance", "Attempts", "Bases", "Cumulative cost"]).bold())
+    .block(Block::bordered().title(title));
+    frame.render_widget(steps, steps_area);
+
+    // Costs and profit match the selected row, the spread of outcomes comes from the risk metrics.
+    let (start_cost, base_cost) = (detail.get_level_cost(detail.start_level), detail.get_level_cost(0));
+    let profit_details = breakdown.get_profit_details();
+    let actual_value = profit_details.get_actual_value();
+    let details = AccEnhancementDetails::new(ItemID::new(detail.item_id), detail.start_level, Some(detail.stacks.clone()));
+    let metrics = calc_risk_metrics(&details, detail.end_level, start_cost, base_cost, actual_value, tax_rate);
+    let summary = Paragraph::new(vec![
+        Line::from(format!("Start at: {}  Base at: {}  Sell at: {}", start_cost, base_cost, actual_value)),
+        Line::from(format!("Make cost: {}  Profit after tax: {}", profit_details.get_make_cost(), profit_details.get_profit_taxed())),
+        Line::from(format!(
+            "ROI: {:.1}%  Chance of profit: {}  Kelly: {:.2}",
+            metrics.get_roi() * 100.0,
+            metrics.get_profit_chance(),
+            metrics.get_kelly_fraction()
+        )),
+    ])
+    .block(Block::bordered().title(" Profit "));
+    frame.render_widget(summary, summary_area);
+
+    let history_block = Block::bordered().title(format!(" Price history: level {} ", detail.end_level));
+    match &detail.history {
+        Some(history) => {
+            let sparkline = Sparkline::default().data(history.get_prices()).block(history_block);
+            frame.render_widget(sparkline, history_area);
+        }
+        None => frame.render_widget(Paragraph::new("Not available").block(history_block), history_area),
+    }
+}
+
+fn bid_ladder<'a>(info: &ItemBuySellInfo, title: &str) -> Table<'a> {
+    let mut bids: Vec<_> = info.get_bids().iter().collect();
+    bids.sort_by_key(|b| std::cmp::Reverse(b.get_bidding_price()));
+
+    let rows = bids.into_iter().map(|bid| {
+        Row::new(vec![
+            bid.get_bidding_price().to_string(),
+            bid.get_sell_count().to_string(),
+            bid.get_buy_count().to_string(),
+        ])
+    });
+    Table::new(rows, [Constraint::Min(14), Constraint::Length(8), Constraint::Length(8)])
+        .header(Row::new(vec!["Price", "Listed", "Wanted"]).bold())
+        .block(Block::bordered().title(title.to_owned()))
+}
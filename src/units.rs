@@ -0,0 +1,265 @@
+//! # Units
+//!
+//! Units gives silver amounts and chances types of their own, so they can't be mixed up or silently wrap.
+//!
+//! Every calculation rounds silver the same way the game does:
+//!
+//! | Amount | Rounding |
+//! |---|---|
+//! | Silver received from a sale, after tax | Down, see [`Silver::after_tax`] |
+//! | Expected value of a chance of silver | Down, see [`Silver::scale`] |
+//! | Expected cost of making an item | Up |
+//!
+//! Arithmetic on [`Silver`] saturates instead of wrapping, with `checked_` versions for callers that want to know.
+
+use serde::Serialize;
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Neg, Sub, SubAssign};
+
+/// How an exact amount is rounded to whole silver.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    Down,
+    Up,
+    Nearest,
+}
+
+/// An amount of silver, which is negative for losses.
+///
+/// Displays in short form, such as `1.25B` or `340M`, use [`get`](Silver::get) for the exact amount.
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::units::{Rounding, Silver};
+///
+/// let price = Silver::from(2_500_000_000_u64);
+/// assert_eq!(Silver::new(2_125_000_000), price.after_tax(0.85));
+/// assert_eq!("2.5B", price.to_string());
+/// assert_eq!("-340M", (Silver::new(160_000_000) - Silver::new(500_000_000)).to_string());
+///
+/// assert_eq!(Silver::new(1001), Silver::from_f64(1000.2, Rounding::Up));
+/// // Arithmetic saturates rather than wrapping.
+/// assert_eq!(Silver::MAX, Silver::from(u64::MAX) + Silver::new(1));
+/// assert_eq!(None, Silver::MAX.checked_add(Silver::new(1)));
+/// ```
+///
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(transparent)]
+pub struct Silver(i64);
+
+impl Silver {
+    pub const ZERO: Silver = Silver(0);
+    pub const MAX: Silver = Silver(i64::MAX);
+
+    pub fn new(amount: i64) -> Self {
+        Silver(amount)
+    }
+
+    /// Rounds an exact amount, saturating at the largest amounts and treating NaN as 0.
+    ///
+    pub fn from_f64(amount: f64, rounding: Rounding) -> Self {
+        let rounded = match rounding {
+            Rounding::Down => amount.floor(),
+            Rounding::Up => amount.ceil(),
+            Rounding::Nearest => amount.round(),
+        };
+        Silver(rounded as i64) // Casts from f64 saturate.
+    }
+
+    pub fn get(&self) -> i64 {
+        self.0
+    }
+
+    /// Gets the amount as a market price, 0 for losses.
+    ///
+    pub fn to_u64(&self) -> u64 {
+        self.0.max(0) as u64
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.0 as f64
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.0 < 0
+    }
+
+    pub fn checked_add(self, other: Silver) -> Option<Silver> {
+        self.0.checked_add(other.0).map(Silver)
+    }
+
+    pub fn checked_sub(self, other: Silver) -> Option<Silver> {
+        self.0.checked_sub(other.0).map(Silver)
+    }
+
+    pub fn checked_mul(self, times: u64) -> Option<Silver> {
+        i64::try_from(times).ok().and_then(|t| self.0.checked_mul(t)).map(Silver)
+    }
+
+    /// Multiplies by a whole number, saturating.
+    ///
+    pub fn times(self, times: u64) -> Silver {
+        self.checked_mul(times).unwrap_or(if self.0 < 0 { Silver(i64::MIN) } else { Silver::MAX })
+    }
+
+    /// Gets what a sale at this price pays out after tax, which the game rounds down.
+    ///
+    pub fn after_tax(self, tax_rate: f64) -> Silver {
+        Silver::from_f64(self.0 as f64 * tax_rate, Rounding::Down)
+    }
+
+    /// Gets the expected value of receiving this with a chance, rounded down.
+    ///
+    pub fn scale(self, chance: Probability) -> Silver {
+        Silver::from_f64(self.0 as f64 * chance.get(), Rounding::Down)
+    }
+}
+
+/// Prices from the market, saturating above the largest amount, which no market price comes near.
+///
+impl From<u64> for Silver {
+    fn from(amount: u64) -> Self {
+        Silver(i64::try_from(amount).unwrap_or(i64::MAX))
+    }
+}
+
+impl From<u32> for Silver {
+    fn from(amount: u32) -> Self {
+        Silver(amount as i64)
+    }
+}
+
+impl Add for Silver {
+    type Output = Silver;
+
+    fn add(self, other: Silver) -> Silver {
+        Silver(self.0.saturating_add(other.0))
+    }
+}
+
+impl AddAssign for Silver {
+    fn add_assign(&mut self, other: Silver) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Silver {
+    type Output = Silver;
+
+    fn sub(self, other: Silver) -> Silver {
+        Silver(self.0.saturating_sub(other.0))
+    }
+}
+
+impl SubAssign for Silver {
+    fn sub_assign(&mut self, other: Silver) {
+        *self = *self - other;
+    }
+}
+
+impl Neg for Silver {
+    type Output = Silver;
+
+    fn neg(self) -> Silver {
+        Silver(self.0.saturating_neg())
+    }
+}
+
+impl Sum for Silver {
+    fn sum<I: Iterator<Item = Silver>>(iter: I) -> Silver {
+        iter.fold(Silver::ZERO, |total, s| total + s)
+    }
+}
+
+const SHORT_UNITS: [(&str, f64); 4] = [("T", 1e12), ("B", 1e9), ("M", 1e6), ("K", 1e3)];
+
+impl fmt::Display for Silver {
+    /// Writes the amount to 3 significant figures with a unit, such as `1.25B`, `340M` or `12.5K`.
+    /// Amounts below 1000 are written in full.
+    ///
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let amount = self.0.unsigned_abs() as f64;
+
+        for (i, (unit, size)) in SHORT_UNITS.iter().enumerate() {
+            let scaled = amount / size;
+            if scaled < 1.0 {
+                continue;
+            }
+            let decimals = if scaled >= 100.0 { 0 } else if scaled >= 10.0 { 1 } else { 2 };
+            let text = format!("{:.*}", decimals, scaled);
+            // Rounding up can reach the next unit, such as 999.9K to 1000K.
+            if text.parse::<f64>().unwrap_or(0.0) >= 1000.0 && i > 0 {
+                return write!(f, "{}1{}", sign, SHORT_UNITS[i - 1].0);
+            }
+            let text = if text.contains('.') { text.trim_end_matches('0').trim_end_matches('.') } else { &text };
+            return write!(f, "{}{}{}", sign, text, unit);
+        }
+        write!(f, "{}{}", sign, self.0.unsigned_abs())
+    }
+}
+
+/// A chance between 0 and 1.
+///
+/// # Example
+///
+/// ```
+/// use bdo_enhancement_profit_calculator::units::Probability;
+///
+/// let chance = Probability::new(0.675).unwrap();
+/// assert_eq!("67.5%", chance.to_string());
+/// assert_eq!("32.5%", chance.complement().to_string());
+/// assert!(Probability::new(1.2).is_err());
+/// assert!(Probability::new(f64::NAN).is_err());
+/// ```
+///
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
+#[serde(transparent)]
+pub struct Probability(f64);
+
+impl Probability {
+    pub const ZERO: Probability = Probability(0.0);
+    pub const ONE: Probability = Probability(1.0);
+
+    pub fn new(chance: f64) -> Result<Self, String> {
+        if !(0.0..=1.0).contains(&chance) {
+            return Err(format!("A chance has to be between 0 and 1, got {}.", chance));
+        }
+        Ok(Probability(chance))
+    }
+
+    /// Creates a chance, clamping it between 0 and 1, with NaN as 0.
+    ///
+    pub fn clamped(chance: f64) -> Self {
+        if chance.is_nan() {
+            return Probability::ZERO;
+        }
+        Probability(chance.clamp(0.0, 1.0))
+    }
+
+    pub fn get(&self) -> f64 {
+        self.0
+    }
+
+    /// Gets the chance of this not happening.
+    ///
+    pub fn complement(&self) -> Probability {
+        Probability(1.0 - self.0)
+    }
+
+    /// Gets the number of attempts this takes on average.
+    ///
+    pub fn expected_attempts(&self) -> f64 {
+        1.0 / self.0
+    }
+}
+
+impl fmt::Display for Probability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percent = format!("{:.2}", self.0 * 100.0);
+        write!(f, "{}%", percent.trim_end_matches('0').trim_end_matches('.'))
+    }
+}
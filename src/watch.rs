@@ -0,0 +1,322 @@
+//! # Watch
+//!
+//! Watch re-checks the market on an interval, and raises alerts when a rule matches.
+//!
+//! Each check scans the configured categories, then evaluates every [`AlertRule`] against what it found.
+//! An alert for the same rule, item and level is only raised again once its cooldown has passed.
+
+use crate::accessories::AccProfitDetails;
+use crate::bdo_market_requests::market_client::MarketClient;
+use crate::bdo_market_requests::{CategoryGivenInfo, HasId};
+use crate::market_cache::MarketCache;
+use crate::report::ReportRow;
+use crate::scan::{get_scan_candidates, scan_item, ScanOptions};
+use crate::units::Silver;
+
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// A condition that raises an alert.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlertRule {
+    /// Profit after tax is above `min_profit_taxed`, and return on the make cost is above `min_roi`.
+    Profit { min_profit_taxed: i64, min_roi: f64 },
+    /// The item appears in the registration queue, when it wasn't there on the last check.
+    NewInQueue,
+    /// The base price is at least `percent` below its average over the last `days` days.
+    PriceDrop { percent: f64, days: usize },
+}
+
+impl AlertRule {
+    /// Short name of the rule, used in alerts.
+    ///
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            AlertRule::Profit { .. } => "profit",
+            AlertRule::NewInQueue => "queue",
+            AlertRule::PriceDrop { .. } => "price_drop",
+        }
+    }
+
+    /// Checks a scan result against a profit rule. Other rules never match a scan result.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bdo_enhancement_profit_calculator::accessories::AccProfitDetails;
+    /// use bdo_enhancement_profit_calculator::units::Silver;
+    /// use bdo_enhancement_profit_calculator::watch::AlertRule;
+    ///
+    /// let rule = AlertRule::Profit { min_profit_taxed: 1000, min_roi: 0.1 };
+    ///
+    /// // 20000 * 0.85 - 10000 = 7000 profit after tax, a 70% return.
+    /// assert!(rule.matches_profit(&AccProfitDetails::new(Silver::new(10000), Silver::new(20000), 0.85)));
+    /// assert!(!rule.matches_profit(&AccProfitDetails::new(Silver::new(10000), Silver::new(12000), 0.85)));
+    /// ```
+    ///
+    pub fn matches_profit(&self, profit_details: &AccProfitDetails) -> bool {
+        match self {
+            AlertRule::Profit { min_profit_taxed, min_roi } => {
+                let make_cost = profit_details.get_make_cost();
+                if !profit_details.is_priced() || make_cost == Silver::ZERO {
+                    return false;
+                }
+                let profit_taxed = profit_details.get_profit_taxed();
+                let roi = profit_taxed.to_f64() / make_cost.to_f64();
+                profit_taxed.get() > *min_profit_taxed && roi > *min_roi
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A rule that matched, ready to be sent somewhere.
+///
+#[derive(Serialize, Debug, Clone)]
+pub struct Alert {
+    /// Name of the rule that matched, see [`AlertRule::get_name`].
+    pub rule: String,
+    pub region: String,
+    pub item_id: u32,
+    /// Level the alert is about: the end level of a profit alert, the queued level, or 0 for a price drop.
+    pub level: u8,
+    pub name: String,
+    pub message: String,
+    /// Seconds since the unix epoch.
+    pub fired_at: u64,
+    /// The scan result that matched, for profit alerts.
+    pub result: Option<ReportRow>,
+}
+
+/// Remembers when each alert last fired, so the same alert isn't repeated every check.
+///
+pub struct Cooldown {
+    period: Duration,
+    /// Keyed by the rule's index in the watch, the item id and the level.
+    last_fired: HashMap<(usize, u32, u8), Instant>,
+}
+
+impl Cooldown {
+    pub fn new(period: Duration) -> Self {
+        Cooldown {
+            period,
+            last_fired: HashMap::new(),
+        }
+    }
+
+    /// Checks whether a rule, given by its index, may fire for an item's level at `now`, and if so records that it did.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bdo_enhancement_profit_calculator::watch::Cooldown;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let mut cooldown = Cooldown::new(Duration::from_secs(60));
+    /// let now = Instant::now();
+    ///
+    /// assert!(cooldown.try_fire(0, 11607, 4, now));
+    /// assert!(!cooldown.try_fire(0, 11607, 4, now + Duration::from_secs(30)));
+    /// // Other rules, items and levels have their own cooldown.
+    /// assert!(cooldown.try_fire(1, 11607, 4, now));
+    /// assert!(cooldown.try_fire(0, 11853, 4, now));
+    /// assert!(cooldown.try_fire(0, 11607, 3, now));
+    ///
+    /// assert!(cooldown.try_fire(0, 11607, 4, now + Duration::from_secs(60)));
+    /// ```
+    ///
+    pub fn try_fire(&mut self, rule: usize, item_id: u32, level: u8, now: Instant) -> bool {
+        let key = (rule, item_id, level);
+        if let Some(last) = self.last_fired.get(&key) {
+            if now.saturating_duration_since(*last) < self.period {
+                return false;
+            }
+        }
+        self.last_fired.insert(key, now);
+        true
+    }
+
+    /// Forgets alerts whose cooldown has passed, so the map doesn't grow forever.
+    ///
+    pub fn remove_expired(&mut self, now: Instant) {
+        let period = self.period;
+        self.last_fired.retain(|_, last| now.saturating_duration_since(*last) < period);
+    }
+}
+
+/// Checks a region against a set of rules, keeping what it needs between checks.
+///
+pub struct Watcher {
+    client: Box<dyn MarketClient>,
+    options: ScanOptions,
+    rules: Vec<AlertRule>,
+    cooldown: Cooldown,
+    /// Price histories only change daily, so they're kept between checks.
+    histories: MarketCache,
+    /// Queue entries seen on the last check, `None` before the first.
+    seen_queue: Option<HashSet<(u32, u8, String)>>,
+}
+
+impl Watcher {
+    pub fn new(client: Box<dyn MarketClient>, options: ScanOptions, rules: Vec<AlertRule>, cooldown: Duration) -> Result<Self, String> {
+        options.validate()?;
+        if rules.is_empty() {
+            return Err(String::from("At least one alert rule is required."));
+        }
+
+        Ok(Watcher {
+            client,
+            options,
+            rules,
+            cooldown: Cooldown::new(cooldown),
+            histories: MarketCache::new(Duration::from_secs(60 * 60)),
+            seen_queue: None,
+        })
+    }
+
+    pub fn get_rules(&self) -> &[AlertRule] {
+        &self.rules
+    }
+
+    /// Scans the market once, and returns every alert that isn't on cooldown.
+    ///
+    /// New queue entries are only reported from the second check on, since everything is new to the first.
+    /// A request that fails for one item or rule is reported on stderr and skipped, so the rest are still checked.
+    ///
+    pub fn check(&mut self) -> Result<Vec<Alert>, String> {
+        let candidates = get_scan_candidates(self.client.as_ref(), &self.options)?;
+        let now = Instant::now();
+        self.cooldown.remove_expired(now);
+        self.histories.remove_expired();
+
+        let mut alerts = Vec::new();
+        for (index, rule) in self.rules.clone().into_iter().enumerate() {
+            let found = match &rule {
+                AlertRule::Profit { .. } => self.check_profit(&rule, &candidates),
+                AlertRule::NewInQueue => match self.check_queue(&candidates) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        eprintln!("Skipped the queue check: {}", e);
+                        Vec::new()
+                    }
+                },
+                AlertRule::PriceDrop { percent, days } => self.check_price_drop(*percent, *days, &candidates),
+            };
+            for alert in found {
+                if self.cooldown.try_fire(index, alert.item_id, alert.level, now) {
+                    alerts.push(alert);
+                }
+            }
+        }
+        Ok(alerts)
+    }
+
+    fn check_profit(&self, rule: &AlertRule, candidates: &[CategoryGivenInfo]) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        for item in candidates {
+            let results = scan_item(self.client.as_ref(), item, &self.options);
+            for result in results.iter().filter(|r| rule.matches_profit(r.get_profit_details())) {
+                let profit_details = result.get_profit_details();
+                let message = format!(
+                    "{} {} -> {} makes {} after tax ({:.1}% return on {}).",
+                    result.get_name(),
+                    result.get_start_level(),
+                    result.get_end_level(),
+                    profit_details.get_profit_taxed(),
+                    profit_details.get_profit_taxed().to_f64() / profit_details.get_make_cost().to_f64() * 100.0,
+                    profit_details.get_make_cost()
+                );
+                alerts.push(self.new_alert(
+                    rule,
+                    result.get_item_id(),
+                    result.get_end_level(),
+                    result.get_name(),
+                    message,
+                    Some(ReportRow::from(result)),
+                ));
+            }
+        }
+        alerts
+    }
+
+    fn check_queue(&mut self, candidates: &[CategoryGivenInfo]) -> Result<Vec<Alert>, String> {
+        let queue = self.client.get_registration_queue()?;
+        let names: HashMap<u32, &str> = candidates.iter().map(|c| (c.get_item_id(), c.get_item_name())).collect();
+
+        let current: HashSet<(u32, u8, String)> = queue
+            .iter()
+            .map(|q| (q.get_item_id(), q.get_enhancement_level(), q.get_registered_timestamp().to_owned()))
+            .collect();
+        let previous = self.seen_queue.replace(current);
+        let previous = match previous {
+            Some(v) => v,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut alerts = Vec::new();
+        for item in &queue {
+            let key = (item.get_item_id(), item.get_enhancement_level(), item.get_registered_timestamp().to_owned());
+            let name = match names.get(&item.get_item_id()) {
+                Some(v) => *v,
+                None => continue,
+            };
+            if previous.contains(&key) {
+                continue;
+            }
+            let message = format!(
+                "{} at level {} is waiting to be registered for {}.",
+                name,
+                item.get_enhancement_level(),
+                item.get_listed_price()
+            );
+            alerts.push(self.new_alert(&AlertRule::NewInQueue, item.get_item_id(), item.get_enhancement_level(), name, message, None));
+        }
+        Ok(alerts)
+    }
+
+    fn check_price_drop(&self, percent: f64, days: usize, candidates: &[CategoryGivenInfo]) -> Vec<Alert> {
+        let rule = AlertRule::PriceDrop { percent, days };
+        let mut alerts = Vec::new();
+        for item in candidates {
+            let history = match self.histories.get_history(self.client.as_ref(), item.get_item_id(), 0) {
+                Ok(v) => v,
+                Err(e) => {
+                    eprintln!("Skipped {} in the price drop check: {}", item.get_item_name(), e);
+                    continue;
+                }
+            };
+            let average = match history.get_average(days) {
+                Some(v) if v > 0.0 => v,
+                _ => continue,
+            };
+            let drop = (average - item.get_base_price() as f64) / average * 100.0;
+            if drop >= percent {
+                let message = format!(
+                    "{} is at {}, {:.1}% below its {} day average of {:.0}.",
+                    item.get_item_name(),
+                    item.get_base_price(),
+                    drop,
+                    days,
+                    average
+                );
+                alerts.push(self.new_alert(&rule, item.get_item_id(), 0, item.get_item_name(), message, None));
+            }
+        }
+        alerts
+    }
+
+    fn new_alert(&self, rule: &AlertRule, item_id: u32, level: u8, name: &str, message: String, result: Option<ReportRow>) -> Alert {
+        Alert {
+            rule: rule.get_name().to_owned(),
+            region: self.client.get_region().to_owned(),
+            item_id,
+            level,
+            name: name.to_owned(),
+            message,
+            fired_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            result,
+        }
+    }
+}